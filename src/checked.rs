@@ -0,0 +1,490 @@
+//! A debugging wrapper that validates a map after every modification.
+
+use compare::Compare;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::thread;
+use super::cmp::Natural;
+use super::map::{BatchOp, Entry, IterMut, Map, RenameError};
+#[cfg(feature = "range")] use super::map::RangeMut;
+#[cfg(feature = "range")] use std::collections::Bound;
+
+/// A map that validates its underlying tree after every modification.
+///
+/// A `CheckedMap` forwards each modifying operation to an inner `Map` and then calls
+/// [`Map::validate`](../map/struct.Map.html#method.validate), panicking with the name of the
+/// operation on the first violation of the tree's invariants. This localizes corruption caused by
+/// an inconsistent comparator or by keys whose ordering changes while they are in the map, at the
+/// cost of `O(n)` time per modification. Read-only operations are available through `Deref`.
+///
+/// Entries are returned as [`CheckedEntry`](struct.CheckedEntry.html) guards, which validate the
+/// map when they are dropped. Operations that only give mutable access to values are forwarded
+/// without validation, since values cannot affect the tree's invariants. Operations that expose
+/// the tree's structure directly, such as `root_mut` and `transaction`, are not available.
+///
+/// # Examples
+///
+/// ```
+/// use tree::checked::CheckedMap;
+///
+/// let mut map = CheckedMap::new();
+///
+/// map.insert(2, "b");
+/// map.insert(1, "a");
+/// map.insert(3, "c");
+/// map.remove(&2);
+///
+/// assert_eq!(map.get(&1), Some(&"a"));
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&3, &"c")]);
+/// ```
+///
+/// ```should_panic
+/// use std::cell::Cell;
+/// use tree::checked::CheckedMap;
+///
+/// let reversed = Cell::new(false);
+/// let cmp = |l: &u32, r: &u32| if reversed.get() { r.cmp(l) } else { l.cmp(r) };
+///
+/// let mut map = CheckedMap::with_cmp(cmp);
+/// map.insert(1, "a");
+/// map.insert(2, "b");
+///
+/// reversed.set(true);
+/// map.insert(3, "c"); // panics
+/// ```
+#[derive(Clone)]
+pub struct CheckedMap<K, V, C = Natural<K>> where C: Compare<K> {
+    map: Map<K, V, C>,
+}
+
+impl<K, V> CheckedMap<K, V> where K: Ord {
+    /// Creates an empty map ordered according to the natural order of its keys.
+    pub fn new() -> Self { CheckedMap::with_cmp(Natural::default()) }
+}
+
+impl<K, V, C> CheckedMap<K, V, C> where C: Compare<K> {
+    /// Creates an empty map ordered according to the given comparator.
+    pub fn with_cmp(cmp: C) -> Self { CheckedMap { map: Map::with_cmp(cmp) } }
+
+    /// Wraps the given map, validating it first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map violates any of its invariants.
+    pub fn from_map(map: Map<K, V, C>) -> Self {
+        let map = CheckedMap { map: map };
+        map.check("from_map");
+        map
+    }
+
+    /// Consumes the wrapper, returning the inner map.
+    pub fn into_inner(self) -> Map<K, V, C> { self.map }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.check("clear");
+    }
+
+    /// Inserts an entry into the map, returning the previous value, if any, associated with the
+    /// key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old = self.map.insert(key, value);
+        self.check("insert");
+        old
+    }
+
+    /// Inserts an entry into the map, returning the previous key and value, if any, that were
+    /// equal to the given key.
+    pub fn insert_full(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let old = self.map.insert_full(key, value);
+        self.check("insert_full");
+        old
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key, returning `None` if
+    /// the map does not contain the key.
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)> where C: Compare<Q, K> {
+        let old = self.map.remove(key);
+        self.check("remove");
+        old
+    }
+
+    /// Inserts an entry whose key is greater than every key in the map.
+    pub fn insert_max(&mut self, key: K, value: V) {
+        self.map.insert_max(key, value);
+        self.check("insert_max");
+    }
+
+    /// Inserts an entry whose key is less than every key in the map.
+    pub fn insert_min(&mut self, key: K, value: V) {
+        self.map.insert_min(key, value);
+        self.check("insert_min");
+    }
+
+    /// Inserts the given entries into the map, returning the number of keys that were not
+    /// already present.
+    pub fn insert_many<I>(&mut self, entries: I) -> usize where I: IntoIterator<Item=(K, V)> {
+        let inserted = self.map.insert_many(entries);
+        self.check("insert_many");
+        inserted
+    }
+
+    /// Inserts the result of `insert` if the map does not contain the key, and applies `update`
+    /// to its value otherwise, returning `true` if an entry was inserted.
+    pub fn upsert<I, U>(&mut self, key: K, insert: I, update: U) -> bool
+        where I: FnOnce() -> V, U: FnOnce(&mut V) {
+
+        let inserted = self.map.upsert(key, insert, update);
+        self.check("upsert");
+        inserted
+    }
+
+    /// Moves all entries from the given map into the map, leaving the given map empty.
+    pub fn append(&mut self, other: &mut Self) {
+        self.map.append(&mut other.map);
+        self.check("append");
+        other.check("append");
+    }
+
+    /// Splits the map at the given key, returning a map containing the entries whose keys are
+    /// greater than or equal to it.
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where C: Compare<Q, K> + Clone {
+        let other = CheckedMap { map: self.map.split_off(key) };
+        self.check("split_off");
+        other.check("split_off");
+        other
+    }
+
+    /// Applies the given batch of operations, whose keys must be in strictly ascending order.
+    pub fn apply_batch<I>(&mut self, ops: I) where I: IntoIterator<Item=(K, BatchOp<V>)> {
+        self.map.apply_batch(ops);
+        self.check("apply_batch");
+    }
+
+    /// Retains only the entries for which the given predicate returns `true`.
+    pub fn retain<F>(&mut self, mut pred: F) where F: FnMut(&K, &mut V) -> bool, C: Clone {
+        let empty = Map::with_cmp(self.map.cmp().clone());
+        let map = mem::replace(&mut self.map, empty);
+        self.map = map.filter_map(|key, mut value| {
+            if pred(key, &mut value) { Some(value) } else { None }
+        });
+        self.check("retain");
+    }
+
+    /// Returns the entry for the given key, which validates the map when it is dropped.
+    pub fn entry(&mut self, key: K) -> CheckedEntry<K, V, C> {
+        let map: *mut Map<K, V, C> = &mut self.map;
+        CheckedEntry::new(map, "entry", unsafe { (*map).entry(key) })
+    }
+
+    /// Inserts an entry into the map and returns it, replacing the value of any entry whose key
+    /// is equal to the given key.
+    pub fn insert_entry(&mut self, key: K, value: V) -> CheckedEntry<K, V, C> {
+        let map: *mut Map<K, V, C> = &mut self.map;
+        let e = unsafe { (*map).insert_entry(key, value) };
+        CheckedEntry::new(map, "insert_entry", Entry::Occupied(e))
+    }
+
+    /// Returns the entry for the map's maximum key, or `None` if the map is empty.
+    pub fn last_entry(&mut self) -> Option<CheckedEntry<K, V, C>> {
+        let map: *mut Map<K, V, C> = &mut self.map;
+        unsafe { (*map).last_entry() }
+            .map(|e| CheckedEntry::new(map, "last_entry", Entry::Occupied(e)))
+    }
+
+    /// Returns the entry for the map's minimum key, or `None` if the map is empty.
+    pub fn first_entry(&mut self) -> Option<CheckedEntry<K, V, C>> {
+        let map: *mut Map<K, V, C> = &mut self.map;
+        unsafe { (*map).first_entry() }
+            .map(|e| CheckedEntry::new(map, "first_entry", Entry::Occupied(e)))
+    }
+
+    /// Returns the entry for the predecessor of the given key, or `None` if no such key is
+    /// present in the map.
+    pub fn pred_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
+        -> Option<CheckedEntry<K, V, C>> where C: Compare<Q, K> {
+
+        let map: *mut Map<K, V, C> = &mut self.map;
+        unsafe { (*map).pred_entry(key, inclusive) }
+            .map(|e| CheckedEntry::new(map, "pred_entry", Entry::Occupied(e)))
+    }
+
+    /// Returns the entry for the successor of the given key, or `None` if no such key is
+    /// present in the map.
+    pub fn succ_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
+        -> Option<CheckedEntry<K, V, C>> where C: Compare<Q, K> {
+
+        let map: *mut Map<K, V, C> = &mut self.map;
+        unsafe { (*map).succ_entry(key, inclusive) }
+            .map(|e| CheckedEntry::new(map, "succ_entry", Entry::Occupied(e)))
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key if the given predicate
+    /// returns `true` for it.
+    pub fn remove_if<Q: ?Sized, F>(&mut self, key: &Q, pred: F) -> Option<(K, V)>
+        where C: Compare<Q, K>, F: FnOnce(&K, &V) -> bool {
+
+        let old = self.map.remove_if(key, pred);
+        self.check("remove_if");
+        old
+    }
+
+    /// Replaces the value associated with the given key, returning the old value, or returns
+    /// the given value as an error if the map does not contain the key.
+    pub fn replace_value<Q: ?Sized>(&mut self, key: &Q, value: V) -> Result<V, V>
+        where C: Compare<Q, K> {
+
+        let old = self.map.replace_value(key, value);
+        self.check("replace_value");
+        old
+    }
+
+    /// Changes the key of the entry whose key is equal to `old` to `new`.
+    pub fn rename_key<Q: ?Sized>(&mut self, old: &Q, new: K) -> Result<(), RenameError>
+        where C: Compare<Q, K> {
+
+        let result = self.map.rename_key(old, new);
+        self.check("rename_key");
+        result
+    }
+
+    /// Swaps the values associated with the given keys, returning `true` if the map contains
+    /// both keys.
+    pub fn swap_values<Q: ?Sized>(&mut self, k1: &Q, k2: &Q) -> bool where C: Compare<Q, K> {
+        let swapped = self.map.swap_values(k1, k2);
+        self.check("swap_values");
+        swapped
+    }
+
+    /// Returns a mutable reference to the value associated with the given key, or `None` if the
+    /// map does not contain the key.
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V> where C: Compare<Q, K> {
+        self.map.get_mut(key)
+    }
+
+    /// Returns a reference to the map's maximum key and a mutable reference to its associated
+    /// value, or `None` if the map is empty.
+    pub fn last_mut(&mut self) -> Option<(&K, &mut V)> { self.map.last_mut() }
+
+    /// Returns a reference to the map's minimum key and a mutable reference to its associated
+    /// value, or `None` if the map is empty.
+    pub fn first_mut(&mut self) -> Option<(&K, &mut V)> { self.map.first_mut() }
+
+    /// Returns a reference to the predecessor of the given key and a mutable reference to its
+    /// associated value, or `None` if no such key is present in the map.
+    pub fn pred_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
+        where C: Compare<Q, K> {
+
+        self.map.pred_mut(key, inclusive)
+    }
+
+    /// Returns a reference to the successor of the given key and a mutable reference to its
+    /// associated value, or `None` if no such key is present in the map.
+    pub fn succ_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
+        where C: Compare<Q, K> {
+
+        self.map.succ_mut(key, inclusive)
+    }
+
+    /// Returns an iterator over the map's entries, yielding references to their keys and mutable
+    /// references to their values in ascending order.
+    pub fn iter_mut(&mut self) -> IterMut<K, V> { self.map.iter_mut() }
+
+    /// Applies the given function to each of the map's entries in ascending order.
+    pub fn for_each_mut<F>(&mut self, f: F) where F: FnMut(&K, &mut V) { self.map.for_each_mut(f) }
+
+    /// Applies the given function to each of the map's entries in ascending order, stopping at
+    /// the first error.
+    pub fn try_for_each_mut<E, F>(&mut self, f: F) -> Result<(), E>
+        where F: FnMut(&K, &mut V) -> Result<(), E> {
+
+        self.map.try_for_each_mut(f)
+    }
+
+    /// Returns an iterator over the entries whose keys lie in the given range, yielding
+    /// references to their keys and mutable references to their values in ascending order.
+    #[cfg(feature = "range")]
+    pub fn range_mut<Min: ?Sized, Max: ?Sized>(&mut self, min: Bound<&Min>, max: Bound<&Max>)
+        -> RangeMut<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+
+        self.map.range_mut(min, max)
+    }
+
+    /// Resets the map's operation counters to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) { self.map.reset_stats(); }
+
+    /// Removes the map's maximum key and returns it and its associated value, or `None` if the
+    /// map is empty.
+    pub fn remove_last(&mut self) -> Option<(K, V)> {
+        let old = self.map.remove_last();
+        self.check("remove_last");
+        old
+    }
+
+    /// Removes the map's minimum key and returns it and its associated value, or `None` if the
+    /// map is empty.
+    pub fn remove_first(&mut self) -> Option<(K, V)> {
+        let old = self.map.remove_first();
+        self.check("remove_first");
+        old
+    }
+
+    /// Removes the predecessor of the given key from the map and returns it and its associated
+    /// value, or `None` if no such key is present in the map.
+    ///
+    /// See [`Map::pred`](../map/struct.Map.html#method.pred) for the meaning of `inclusive`.
+    pub fn remove_pred<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
+        where C: Compare<Q, K> {
+
+        let old = self.map.remove_pred(key, inclusive);
+        self.check("remove_pred");
+        old
+    }
+
+    /// Removes the successor of the given key from the map and returns it and its associated
+    /// value, or `None` if no such key is present in the map.
+    ///
+    /// See [`Map::succ`](../map/struct.Map.html#method.succ) for the meaning of `inclusive`.
+    pub fn remove_succ<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
+        where C: Compare<Q, K> {
+
+        let old = self.map.remove_succ(key, inclusive);
+        self.check("remove_succ");
+        old
+    }
+
+    /// Applies the given function to the value associated with the given key, returning `true`
+    /// if the map contains the key and `false` otherwise.
+    pub fn update<Q: ?Sized, F>(&mut self, key: &Q, f: F) -> bool
+        where C: Compare<Q, K>, F: FnOnce(&mut V) {
+
+        let updated = self.map.update(key, f);
+        self.check("update");
+        updated
+    }
+
+    fn check(&self, op: &str) { check(&self.map, op); }
+}
+
+// Panic with the name of the given operation if the map violates any of its invariants
+fn check<K, V, C>(map: &Map<K, V, C>, op: &str) where C: Compare<K> {
+    if let Err(err) = map.validate() {
+        panic!("map invariant violated after `{}`: {}", op, err);
+    }
+}
+
+impl<K, V, C> Debug for CheckedMap<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<K, V, C> Default for CheckedMap<K, V, C> where C: Compare<K> + Default {
+    fn default() -> Self { CheckedMap::with_cmp(C::default()) }
+}
+
+impl<K, V, C> Deref for CheckedMap<K, V, C> where C: Compare<K> {
+    type Target = Map<K, V, C>;
+    fn deref(&self) -> &Map<K, V, C> { &self.map }
+}
+
+impl<K, V, C> Extend<(K, V)> for CheckedMap<K, V, C> where C: Compare<K> {
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, it: I) {
+        for (k, v) in it { self.insert(k, v); }
+    }
+}
+
+/// An entry in a [`CheckedMap`](struct.CheckedMap.html) that validates the map when it is
+/// dropped.
+///
+/// # Examples
+///
+/// ```
+/// use tree::checked::CheckedMap;
+///
+/// let mut map = CheckedMap::new();
+///
+/// for s in vec!["a", "b", "a"] {
+///     *map.entry(s).or_insert(0) += 1;
+/// }
+///
+/// assert_eq!(map.entry("a").remove(), Some(("a", 2)));
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(&"b", &1)]);
+/// ```
+pub struct CheckedEntry<'a, K: 'a, V: 'a, C: 'a> where C: Compare<K> {
+    map: *mut Map<K, V, C>,
+    op: &'static str,
+    entry: Option<Entry<'a, K, V>>,
+    marker: PhantomData<&'a mut Map<K, V, C>>,
+}
+
+impl<'a, K, V, C> CheckedEntry<'a, K, V, C> where C: Compare<K> {
+    // The entry must borrow the map through the given pointer, and no reference with its
+    // lifetime may be handed out, so that nothing else borrows the map when it is validated
+    fn new(map: *mut Map<K, V, C>, op: &'static str, entry: Entry<'a, K, V>) -> Self {
+        CheckedEntry { map: map, op: op, entry: Some(entry), marker: PhantomData }
+    }
+
+    /// Returns a reference to the entry's key.
+    pub fn key(&self) -> &K { self.entry.as_ref().unwrap().key() }
+
+    /// Returns a reference to the entry's value, or `None` if the entry is vacant.
+    pub fn get(&self) -> Option<&V> {
+        match *self.entry.as_ref().unwrap() {
+            Entry::Occupied(ref e) => Some(e.get()),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Returns a mutable reference to the entry's value, or `None` if the entry is vacant.
+    pub fn get_mut(&mut self) -> Option<&mut V> {
+        match *self.entry.as_mut().unwrap() {
+            Entry::Occupied(ref mut e) => Some(e.get_mut()),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Sets the entry's value, returning its previous value, if any.
+    pub fn insert(&mut self, value: V) -> Option<V> {
+        match self.entry.take().unwrap() {
+            Entry::Occupied(mut e) => {
+                let old = e.insert(value);
+                self.entry = Some(Entry::Occupied(e));
+                Some(old)
+            }
+            Entry::Vacant(e) => {
+                let key = e.into_key();
+                let e = unsafe { (*self.map).insert_entry(key, value) };
+                self.entry = Some(Entry::Occupied(e));
+                None
+            }
+        }
+    }
+
+    /// Returns the entry's value, inserting the given default if the entry is vacant.
+    pub fn or_insert(&mut self, default: V) -> &mut V { self.or_insert_with(|| default) }
+
+    /// Returns the entry's value, inserting the given function's result if the entry is vacant.
+    pub fn or_insert_with<F>(&mut self, default: F) -> &mut V where F: FnOnce() -> V {
+        if self.get().is_none() { self.insert(default()); }
+        self.get_mut().unwrap()
+    }
+
+    /// Removes the entry from the map, returning its key and value, or `None` if the entry is
+    /// vacant.
+    pub fn remove(mut self) -> Option<(K, V)> {
+        match self.entry.take().unwrap() {
+            Entry::Occupied(e) => Some(e.remove()),
+            Entry::Vacant(_) => None,
+        }
+    }
+}
+
+impl<'a, K, V, C> Drop for CheckedEntry<'a, K, V, C> where C: Compare<K> {
+    fn drop(&mut self) {
+        self.entry = None;
+        if !thread::panicking() { check(unsafe { &*self.map }, self.op); }
+    }
+}
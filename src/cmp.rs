@@ -0,0 +1,183 @@
+//! Comparators for use with maps and sets.
+//!
+//! The comparators in this module order keys according to the natural order of their borrowed
+//! forms, so that maps and sets that use them can be queried without constructing an owned key.
+//! For example, a map with `String` keys can be queried with a `&str`, and one with `Vec<u8>` keys
+//! with a `&[u8]`:
+//!
+//! ```
+//! let mut map = tree::Map::new();
+//! map.insert(b"abc".to_vec(), 1);
+//!
+//! assert_eq!(map.get(&b"abc"[..]), Some(&1));
+//! ```
+//!
+//! # Upgrading from 0.4
+//!
+//! Before version 0.5, the default comparator of every map, set, and view type was
+//! `compare::Natural`, and it is now [`Natural`](struct.Natural.html). This is a breaking change:
+//!
+//! - Types that name the old default explicitly, such as `Map<K, V, compare::Natural<K>>`, are
+//!   distinct from `Map<K, V>`. Such maps still work, but they must be converted with
+//!   [`Map::into_with_cmp`](../map/struct.Map.html#method.into_with_cmp) to be used where a
+//!   `Map<K, V>` is expected, and they do not support borrowed lookups.
+//! - Because the default comparator accepts any borrowed form of the key, an `Unbounded` range
+//!   bound no longer determines its type, which must be given explicitly:
+//!
+//! ```
+//! # #[cfg(feature = "range")]
+//! # fn main() {
+//! use std::collections::Bound::{Included, Unbounded};
+//!
+//! let map: tree::Map<_, _> = (0..5).map(|i| (i, ())).collect();
+//! assert_eq!(map.range::<i32, i32>(Unbounded, Included(&1)).count(), 2);
+//! # }
+//! # #[cfg(not(feature = "range"))]
+//! # fn main() {}
+//! ```
+
+use compare::Compare;
+use std::borrow::Borrow;
+use std::cmp::Ordering::{self, Equal};
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+/// A comparator that orders keys according to their natural order.
+///
+/// This is the default comparator for [`Map`](../map/struct.Map.html) and
+/// [`Set`](../set/struct.Set.html). Unlike `compare::Natural`, it can also compare a key with
+/// any type that the key can be borrowed as, so that, like the standard library's collections, a
+/// map with `String` keys can be queried with a `&str`.
+///
+/// # Examples
+///
+/// ```
+/// let mut map = tree::Map::new();
+///
+/// map.insert("b".to_string(), 2);
+/// map.insert("a".to_string(), 1);
+///
+/// assert_eq!(map.get("a"), Some(&1));
+/// assert_eq!(map.remove("b"), Some(("b".to_string(), 2)));
+/// assert!(!map.contains_key("b"));
+/// ```
+pub struct Natural<K: Ord>(PhantomData<fn(&K)>);
+
+impl<K, Q: ?Sized> Compare<Q, K> for Natural<K> where K: Ord + Borrow<Q>, Q: Ord {
+    fn compare(&self, l: &Q, r: &K) -> Ordering { Ord::cmp(l, r.borrow()) }
+    fn compares_lt(&self, l: &Q, r: &K) -> bool { PartialOrd::lt(l, r.borrow()) }
+    fn compares_le(&self, l: &Q, r: &K) -> bool { PartialOrd::le(l, r.borrow()) }
+    fn compares_ge(&self, l: &Q, r: &K) -> bool { PartialOrd::ge(l, r.borrow()) }
+    fn compares_gt(&self, l: &Q, r: &K) -> bool { PartialOrd::gt(l, r.borrow()) }
+    fn compares_eq(&self, l: &Q, r: &K) -> bool { PartialEq::eq(l, r.borrow()) }
+    fn compares_ne(&self, l: &Q, r: &K) -> bool { PartialEq::ne(l, r.borrow()) }
+}
+
+impl<K> Clone for Natural<K> where K: Ord {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<K> Copy for Natural<K> where K: Ord {}
+
+impl<K> Default for Natural<K> where K: Ord {
+    fn default() -> Self { Natural(PhantomData) }
+}
+
+impl<K> PartialEq for Natural<K> where K: Ord {
+    fn eq(&self, _other: &Self) -> bool { true }
+}
+
+impl<K> Eq for Natural<K> where K: Ord {}
+
+impl<K> Debug for Natural<K> where K: Ord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Natural") }
+}
+
+macro_rules! tuple {
+    ($(#[$attr:meta])* struct $name:ident<$($T:ident, $Q:ident, $K:ident, $i:tt),+>) => {
+        $(#[$attr])*
+        pub struct $name<$($T: ?Sized + Ord),+>(PhantomData<($(fn(&$T)),+)>);
+
+        impl<$($T: ?Sized, $Q, $K),+> Compare<($($Q),+), ($($K),+)> for $name<$($T),+>
+            where $($T: Ord, $Q: Borrow<$T>, $K: Borrow<$T>),+ {
+
+            fn compare(&self, l: &($($Q),+), r: &($($K),+)) -> Ordering {
+                $(
+                    match Ord::cmp(l.$i.borrow(), r.$i.borrow()) {
+                        Equal => {}
+                        order => return order,
+                    }
+                )+
+
+                Equal
+            }
+        }
+
+        impl<$($T: ?Sized),+> Clone for $name<$($T),+> where $($T: Ord),+ {
+            fn clone(&self) -> Self { *self }
+        }
+
+        impl<$($T: ?Sized),+> Copy for $name<$($T),+> where $($T: Ord),+ {}
+
+        impl<$($T: ?Sized),+> Default for $name<$($T),+> where $($T: Ord),+ {
+            fn default() -> Self { $name(PhantomData) }
+        }
+
+        impl<$($T: ?Sized),+> PartialEq for $name<$($T),+> where $($T: Ord),+ {
+            fn eq(&self, _other: &Self) -> bool { true }
+        }
+
+        impl<$($T: ?Sized),+> Eq for $name<$($T),+> where $($T: Ord),+ {}
+
+        impl<$($T: ?Sized),+> Debug for $name<$($T),+> where $($T: Ord),+ {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, stringify!($name))
+            }
+        }
+    }
+}
+
+tuple! {
+    /// A comparator that orders pairs lexicographically according to the natural order of their
+    /// elements' borrowed forms `A` and `B`.
+    ///
+    /// This allows a map whose keys are pairs of owned values to be queried with pairs of
+    /// borrowed values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::cmp::Pair;
+    ///
+    /// let mut map = tree::Map::with_cmp(Pair::<str, [u8]>::default());
+    ///
+    /// map.insert(("b".to_string(), vec![1]), 2);
+    /// map.insert(("a".to_string(), vec![2]), 1);
+    ///
+    /// assert_eq!(map.get(&("a", &[2][..])), Some(&1));
+    /// assert_eq!(map.get(&("a", &[1][..])), None);
+    /// ```
+    struct Pair<A, QA, KA, 0, B, QB, KB, 1>
+}
+
+tuple! {
+    /// A comparator that orders triples lexicographically according to the natural order of their
+    /// elements' borrowed forms `A`, `B`, and `C`.
+    ///
+    /// This allows a map whose keys are triples of owned values to be queried with triples of
+    /// borrowed values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::cmp::Triple;
+    ///
+    /// let mut map = tree::Map::with_cmp(Triple::<str, str, u32>::default());
+    ///
+    /// map.insert(("a".to_string(), "b".to_string(), 1), "x");
+    ///
+    /// assert_eq!(map.get(&("a", "b", 1)), Some(&"x"));
+    /// assert_eq!(map.get(&("a", "b", 2)), None);
+    /// ```
+    struct Triple<A, QA, KA, 0, B, QB, KB, 1, C, QC, KC, 2>
+}
@@ -0,0 +1,57 @@
+//! The crate's error type.
+
+use std::error;
+use std::fmt;
+use super::map::{InvariantError, RenameError};
+
+/// An error returned by one of the crate's fallible operations.
+///
+/// Each fallible operation returns its own, more specific error type, which converts into this
+/// one. This allows the `?` operator to be used with several of them in a function returning
+/// `Result<_, tree::Error>`.
+///
+/// # Examples
+///
+/// ```
+/// fn rename_all(map: &mut tree::Map<&'static str, u32>) -> Result<(), tree::Error> {
+///     map.rename_key(&"a", "b")?;
+///     map.rename_key(&"b", "c")?;
+///     map.validate()?;
+///     Ok(())
+/// }
+///
+/// let mut map = tree::Map::new();
+/// map.insert("a", 1);
+/// map.insert("c", 2);
+///
+/// let err = rename_all(&mut map).unwrap_err();
+///
+/// assert_eq!(err, tree::Error::Rename(tree::map::RenameError::Occupied));
+/// assert_eq!(err.to_string(), "the map already contains the new key");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A map's underlying tree violates one of its invariants.
+    Invariant(InvariantError),
+    /// A key could not be renamed.
+    Rename(RenameError),
+}
+
+impl From<InvariantError> for Error {
+    fn from(err: InvariantError) -> Self { Error::Invariant(err) }
+}
+
+impl From<RenameError> for Error {
+    fn from(err: RenameError) -> Self { Error::Rename(err) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Invariant(ref err) => err.fmt(f),
+            Error::Rename(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {}
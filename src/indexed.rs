@@ -0,0 +1,304 @@
+//! An ordered map with a secondary index that orders its entries by value.
+
+use compare::Compare;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::iter;
+use super::cmp::Natural;
+use super::map::{self, Map};
+use super::set::{self, Set};
+
+/// An ordered map that also maintains an index of its entries ordered by value.
+///
+/// The map can be queried by key like a [`Map`](../map/struct.Map.html), and its entries can
+/// also be iterated in ascending or descending order of their values, with entries whose values
+/// are equal ordered by key. Every insertion, removal, and update keeps the index in sync, at the
+/// cost of storing a clone of each key and value in the index.
+///
+/// The behavior of this map is undefined if a key's ordering relative to any other key, or a
+/// value's ordering relative to any other value, changes while it is in the map, other than
+/// through [`update`](#method.update). This is normally only possible through `Cell`, `RefCell`,
+/// or unsafe code.
+///
+/// # Examples
+///
+/// ```
+/// let mut scores = tree::BiOrderedMap::new();
+///
+/// scores.insert("alice", 30);
+/// scores.insert("bob", 10);
+/// scores.insert("carol", 20);
+///
+/// assert_eq!(scores.get(&"bob"), Some(&10));
+/// assert_eq!(scores.iter_by_value().rev().take(2).collect::<Vec<_>>(),
+///            [(&"alice", &30), (&"carol", &20)]);
+/// ```
+#[derive(Clone)]
+pub struct BiOrderedMap<K, V, C = Natural<K>, D = Natural<V>>
+    where C: Compare<K>, D: Compare<V> {
+
+    map: Map<K, V, C>,
+    index: Set<(V, K), ByValue<C, D>>,
+}
+
+impl<K, V> BiOrderedMap<K, V> where K: Ord + Clone, V: Ord + Clone {
+    /// Creates an empty map ordered according to the natural order of its keys and indexed
+    /// according to the natural order of its values.
+    pub fn new() -> Self { BiOrderedMap::with_cmps(Natural::default(), Natural::default()) }
+}
+
+impl<K, V, C, D> BiOrderedMap<K, V, C, D>
+    where K: Clone, V: Clone, C: Compare<K>, D: Compare<V> {
+
+    /// Creates an empty map ordered according to the given key comparator and indexed according
+    /// to the given value comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut map = tree::BiOrderedMap::with_cmps(natural(), natural().rev());
+    ///
+    /// map.insert(1, "b");
+    /// map.insert(2, "a");
+    ///
+    /// assert_eq!(map.iter_by_value().next(), Some((&1, &"b")));
+    /// # }
+    /// ```
+    pub fn with_cmps(key_cmp: C, value_cmp: D) -> Self where C: Clone {
+        let index = Set::with_cmp(ByValue { key: key_cmp.clone(), value: value_cmp });
+        BiOrderedMap { map: Map::with_cmp(key_cmp), index: index }
+    }
+
+    /// Checks if the map is empty.
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize { self.map.len() }
+
+    /// Returns a reference to the map's key comparator.
+    pub fn key_cmp(&self) -> &C { self.map.cmp() }
+
+    /// Returns a reference to the map's value comparator.
+    pub fn value_cmp(&self) -> &D { &self.index.cmp().value }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.index.clear();
+    }
+
+    /// Inserts an entry into the map, returning the previous value, if any, associated with the
+    /// key.
+    ///
+    /// As with [`Map::insert`](../map/struct.Map.html#method.insert), the key already stored in
+    /// the map, if any, is kept in both the map and the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::BiOrderedMap::new();
+    ///
+    /// assert_eq!(map.insert(1, "b"), None);
+    /// assert_eq!(map.insert(1, "a"), Some("b"));
+    /// assert_eq!(map.iter_by_value().collect::<Vec<_>>(), [(&1, &"a")]);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.map.entry(key) {
+            map::Entry::Occupied(mut e) => {
+                self.index.remove(&(e.get(), e.key()));
+                self.index.insert((value.clone(), e.key().clone()));
+                Some(e.insert(value))
+            }
+            map::Entry::Vacant(e) => {
+                self.index.insert((value.clone(), e.key().clone()));
+                e.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Removes the given key from the map and returns it along with its value, or `None` if the
+    /// map does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::BiOrderedMap::new();
+    ///
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove(&1), Some((1, "a")));
+    /// assert_eq!(map.iter_by_value().next(), None);
+    /// ```
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)> where C: Compare<Q, K> {
+        self.map.remove(key).map(|(key, value)| {
+            self.index.remove(&(&value, &key));
+            (key, value)
+        })
+    }
+
+    /// Applies the given function to the value associated with the given key, repositioning the
+    /// entry in the index afterward, and returns `true`, or returns `false` if the map does not
+    /// contain the key.
+    ///
+    /// This is the only way to modify a value in place, since a mutable reference to a value
+    /// would allow its order to change without the index being updated. The function is applied
+    /// to a clone of the value, so the map is left unchanged if it panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::BiOrderedMap::new();
+    ///
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// assert!(map.update(&"a", |score| *score += 10));
+    /// assert!(!map.update(&"c", |score| *score += 10));
+    /// assert_eq!(map.iter_by_value().collect::<Vec<_>>(), [(&"b", &2), (&"a", &11)]);
+    /// ```
+    pub fn update<Q: ?Sized, F>(&mut self, key: &Q, f: F) -> bool
+        where C: Compare<Q, K>, F: FnOnce(&mut V) {
+
+        let mut value = match self.map.get(key) {
+            None => return false,
+            Some(value) => value.clone(),
+        };
+
+        f(&mut value);
+        let (key, _) = self.remove(key).unwrap();
+        self.insert(key, value);
+        true
+    }
+
+    /// Checks if the map contains the given key.
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.map.contains_key(key)
+    }
+
+    /// Returns a reference to the value associated with the given key, or `None` if the map does
+    /// not contain the key.
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V> where C: Compare<Q, K> {
+        self.map.get(key)
+    }
+
+    /// Returns an iterator over the map's entries in ascending order of their keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::BiOrderedMap::new();
+    ///
+    /// map.insert(2, "a");
+    /// map.insert(1, "b");
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"b"), (&2, &"a")]);
+    /// ```
+    pub fn iter(&self) -> map::Iter<K, V> { self.map.iter() }
+
+    /// Returns an iterator over the map's entries in ascending order of their values, with
+    /// entries whose values are equal in ascending order of their keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::BiOrderedMap::new();
+    ///
+    /// map.insert(3, "a");
+    /// map.insert(1, "b");
+    /// map.insert(2, "a");
+    ///
+    /// assert_eq!(map.iter_by_value().collect::<Vec<_>>(), [(&2, &"a"), (&3, &"a"), (&1, &"b")]);
+    /// ```
+    pub fn iter_by_value(&self) -> ByValueIter<K, V> { ByValueIter(self.index.iter()) }
+}
+
+impl<K, V, C, D> Debug for BiOrderedMap<K, V, C, D>
+    where K: Debug, V: Debug, C: Compare<K>, D: Compare<V> {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<K, V, C, D> Default for BiOrderedMap<K, V, C, D>
+    where K: Clone, V: Clone, C: Compare<K> + Clone + Default, D: Compare<V> + Default {
+
+    fn default() -> Self { BiOrderedMap::with_cmps(C::default(), D::default()) }
+}
+
+impl<K, V, C, D> Extend<(K, V)> for BiOrderedMap<K, V, C, D>
+    where K: Clone, V: Clone, C: Compare<K>, D: Compare<V> {
+
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, it: I) {
+        for (k, v) in it { self.insert(k, v); }
+    }
+}
+
+impl<K, V, C, D> iter::FromIterator<(K, V)> for BiOrderedMap<K, V, C, D>
+    where K: Clone, V: Clone, C: Compare<K> + Clone + Default, D: Compare<V> + Default {
+
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(it: I) -> Self {
+        let mut map = BiOrderedMap::default();
+        map.extend(it);
+        map
+    }
+}
+
+impl<'a, K, V, C, D> IntoIterator for &'a BiOrderedMap<K, V, C, D>
+    where K: Clone, V: Clone, C: Compare<K>, D: Compare<V> {
+
+    type Item = (&'a K, &'a V);
+    type IntoIter = map::Iter<'a, K, V>;
+    fn into_iter(self) -> map::Iter<'a, K, V> { self.iter() }
+}
+
+// Orders an index's entries by value, and then by key
+#[derive(Clone)]
+struct ByValue<C, D> {
+    key: C,
+    value: D,
+}
+
+impl<K, V, C, D> Compare<(V, K)> for ByValue<C, D> where C: Compare<K>, D: Compare<V> {
+    fn compare(&self, l: &(V, K), r: &(V, K)) -> Ordering {
+        match self.value.compare(&l.0, &r.0) {
+            Ordering::Equal => self.key.compare(&l.1, &r.1),
+            order => order,
+        }
+    }
+}
+
+impl<'a, K, V, C, D> Compare<(&'a V, &'a K), (V, K)> for ByValue<C, D>
+    where C: Compare<K>, D: Compare<V> {
+
+    fn compare(&self, l: &(&'a V, &'a K), r: &(V, K)) -> Ordering {
+        match self.value.compare(l.0, &r.0) {
+            Ordering::Equal => self.key.compare(l.1, &r.1),
+            order => order,
+        }
+    }
+}
+
+/// An iterator over the map's entries in ascending order of their values.
+///
+/// Acquire through [`BiOrderedMap::iter_by_value`](struct.BiOrderedMap.html#method.iter_by_value).
+pub struct ByValueIter<'a, K: 'a, V: 'a>(set::Iter<'a, (V, K)>);
+
+impl<'a, K, V> Clone for ByValueIter<'a, K, V> {
+    fn clone(&self) -> Self { ByValueIter(self.0.clone()) }
+}
+
+impl<'a, K, V> Iterator for ByValueIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|e| (&e.1, &e.0)) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, K, V> DoubleEndedIterator for ByValueIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back().map(|e| (&e.1, &e.0)) }
+}
+
+impl<'a, K, V> ExactSizeIterator for ByValueIter<'a, K, V> {}
@@ -1,18 +1,51 @@
 //! An ordered map and set based on a binary search tree.
 
-#![cfg_attr(feature = "range", feature(collections_bound))]
-
 extern crate compare;
+#[cfg(feature = "tracing")] #[macro_use] extern crate log;
 
+pub use error::Error;
+pub use indexed::BiOrderedMap;
 pub use map::Map;
+pub use multimap::MultiMap;
+pub use multiset::Multiset;
 pub use set::Set;
+#[cfg(feature = "stats")] pub use stats::Stats;
+
+// Emit a trace event for a structural operation on a tree, if the `tracing` feature is enabled
+//
+// Events are logged at the trace level with the target `tree`, and never include keys or values.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($($arg:tt)*) => { trace!(target: "tree", $($arg)*) }
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($($arg:tt)*) => {}
+}
 
+#[forbid(missing_docs)]
+pub mod checked;
+#[forbid(missing_docs)]
+pub mod cmp;
+#[forbid(missing_docs)]
+pub mod indexed;
 #[forbid(missing_docs)]
 pub mod map;
 #[forbid(missing_docs)]
+pub mod multimap;
+#[forbid(missing_docs)]
+pub mod multiset;
+#[forbid(missing_docs)]
 pub mod set;
+#[forbid(missing_docs)]
+pub mod persistent;
+#[forbid(missing_docs)]
+pub mod sync;
 
+mod error;
 mod node;
+mod stats;
 
 #[cfg(feature = "ordered_iter")]
 mod ordered_iter;
@@ -1,13 +1,19 @@
 //! An ordered map based on a binary search tree.
 
-use compare::{Compare, Natural};
-use std::cmp::Ordering;
+use compare::Compare;
+#[cfg(feature = "range")] use std::borrow::Borrow;
+use std::cmp::{self, Ordering};
 use std::cmp::Ordering::*;
 #[cfg(feature = "range")] use std::collections::Bound;
+use std::error::Error;
 use std::fmt::{self, Debug};
 use std::hash::{self, Hash};
 use std::iter;
+use std::mem;
 use std::ops;
+use super::cmp::Natural;
+use super::stats::Counters;
+#[cfg(feature = "stats")] use super::stats::Stats;
 use super::node::{self, Extreme, Max, Min, MarkedNode, MutMarkedNode, Node};
 use super::node::build::{Get, GetMut, PathBuilder};
 
@@ -18,11 +24,26 @@ pub use super::node::{OccupiedEntry, VacantEntry};
 /// The behavior of this map is undefined if a key's ordering relative to any other key changes
 /// while the key is in the map. This is normally only possible through `Cell`, `RefCell`, or
 /// unsafe code.
+///
+/// To help diagnose such problems, as well as comparators that do not implement a total order,
+/// the `debug-validate` feature makes the map cross-check its comparator against the tree's
+/// structure on insertion and lookup, and to spot-check its transitivity on samples of the
+/// map's keys, panicking if either check fails.
+///
+/// Cloning a map copies its entire tree. When cheap copies are needed, e.g. to keep snapshots of
+/// a map while continuing to modify it, convert it into a
+/// [`persistent::Map`](../persistent/map/struct.Map.html) instead, whose clones share their
+/// nodes until modified.
+///
+/// With the `tracing` feature, the map logs its structural operations (insertions, removals,
+/// rotations, and rebuilds) through the `log` crate at the trace level with the target `tree`.
+/// The events describe the tree's size and node levels, but never include keys or values.
 #[derive(Clone)]
 pub struct Map<K, V, C = Natural<K>> where C: Compare<K> {
     root: node::Link<K, V>,
     len: usize,
     cmp: C,
+    counters: Counters,
 }
 
 impl<K, V> Map<K, V> where K: Ord {
@@ -46,6 +67,42 @@ impl<K, V> Map<K, V> where K: Ord {
     pub fn new() -> Self { Map::with_cmp(Natural::default()) }
 }
 
+impl<K, V> Map<K, V> where K: Discrete {
+    /// Returns the smallest key in the given range that is not present in the map, or `None` if
+    /// every key in the range is present.
+    ///
+    /// This takes `O(log n)` time, since comparing the number of entries before a key with the
+    /// number of keys in the range before it reveals whether a key is missing there.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// for id in vec![3, 4, 5, 7, 8] { map.insert(id, ()); }
+    ///
+    /// assert_eq!(map.first_absent_key(0..10), Some(0));
+    /// assert_eq!(map.first_absent_key(3..10), Some(6));
+    /// assert_eq!(map.first_absent_key(7..10), Some(9));
+    /// assert_eq!(map.first_absent_key(7..9), None);
+    /// ```
+    pub fn first_absent_key(&self, range: ops::Range<K>) -> Option<K> {
+        if range.start >= range.end { return None; }
+
+        let start = range.start;
+        let cmp = self.counters.count(&self.cmp);
+        let (start_rank, _) = node::rank(&self.root, &cmp, &start);
+
+        let rank = node::partition_point(&self.root, |rank, &key| {
+            key < start || start.distance(key) == (rank - start_rank) as u64
+        });
+
+        start.forward((rank - start_rank) as u64).and_then(|key| {
+            if key < range.end { Some(key) } else { None }
+        })
+    }
+}
+
 impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     /// Creates an empty map ordered according to the given comparator.
     ///
@@ -71,7 +128,7 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     /// # }
     /// ```
     pub fn with_cmp(cmp: C) -> Self {
-        Map { root: None, len: 0, cmp: cmp }
+        Map { root: None, len: 0, cmp: cmp, counters: Counters::default() }
     }
 
     /// Checks if the map is empty.
@@ -119,412 +176,603 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     /// ```
     pub fn cmp(&self) -> &C { &self.cmp }
 
-    /// Removes all entries from the map.
+    /// Consumes the map and rebuilds it ordered according to the given comparator.
+    ///
+    /// If the map's entries are already in strictly ascending or strictly descending order under
+    /// the new comparator, the new map is built in `O(n)` time without any further comparisons.
+    /// Otherwise, the entries are reinserted one at a time in ascending order of the old
+    /// comparator, as with `Extend`. If the new comparator considers several keys equal, the
+    /// least of them according to the old comparator is retained, associated with the value of
+    /// the greatest of them.
     ///
     /// # Examples
     ///
     /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, Extract, natural};
+    ///
     /// let mut map = tree::Map::new();
     ///
     /// map.insert(2, "b");
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// assert_eq!(map.len(), 3);
-    /// assert_eq!(map.iter().next(), Some((&1, &"a")));
+    /// let map = map.into_with_cmp(natural().rev());
     ///
-    /// map.clear();
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&3, &"c")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), None);
     ///
-    /// assert_eq!(map.len(), 0);
-    /// assert_eq!(map.iter().next(), None);
+    /// let map: tree::Map<_, _> = vec![(11, "a"), (12, "b"), (21, "c")].into_iter().collect();
+    /// let map = map.into_with_cmp(Extract::new(|key: &i32| key / 10));
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&11, &"b"), (&21, &"c")]);
+    /// # }
     /// ```
-    pub fn clear(&mut self) {
-        self.root = None;
-        self.len = 0;
+    pub fn into_with_cmp<D>(self, cmp: D) -> Map<K, V, D> where D: Compare<K> {
+        Map::from_parts(cmp, self)
     }
 
-    /// Inserts an entry into the map, returning the previous value, if any, associated
-    /// with the key.
+    /// Creates a map ordered according to the given comparator and containing the given entries.
+    ///
+    /// This is the inverse of [`into_parts`](#method.into_parts). If the entries are in strictly
+    /// ascending or strictly descending order according to the comparator, the map is built in
+    /// `O(n)` time without any further comparisons. Otherwise, the entries are inserted one at a
+    /// time, as with `Extend`.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    /// assert_eq!(map.insert(1, "a"), None);
-    /// assert_eq!(map.get(&1), Some(&"a"));
-    /// assert_eq!(map.insert(1, "b"), Some("a"));
-    /// assert_eq!(map.get(&1), Some(&"b"));
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let map = tree::Map::from_parts(natural().rev(), vec![(3, "c"), (2, "b"), (1, "a")]);
+    ///
+    /// assert!(map.cmp().compares_gt(&1, &2));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&3, &"c"), (&2, &"b"), (&1, &"a")]);
+    /// # }
     /// ```
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        let old_value = node::insert(&mut self.root, &self.cmp, key, value);
-        if old_value.is_none() { self.len += 1; }
-        old_value
+    pub fn from_parts<I>(cmp: C, entries: I) -> Self where I: IntoIterator<Item=(K, V)> {
+        let entries: Vec<(K, V)> = entries.into_iter().collect();
+        let len = entries.len();
+
+        if entries.windows(2).all(|e| cmp.compares_lt(&e[0].0, &e[1].0)) {
+            Map::from_sorted(cmp, entries)
+        } else if entries.windows(2).all(|e| cmp.compares_gt(&e[0].0, &e[1].0)) {
+            event!("rebuild: len={}", len);
+            let root = node::from_sorted(&mut entries.into_iter().rev(), len);
+            Map { root: root, len: len, cmp: cmp, counters: Counters::default() }
+        } else {
+            let mut map = Map::with_cmp(cmp);
+            map.extend(entries);
+            map
+        }
     }
 
-    /// Removes and returns the entry whose key is equal to the given key, returning
-    /// `None` if the map does not contain the key.
+    /// Consumes the map, returning its comparator and an iterator over its entries.
+    ///
+    /// The iterator yields the entries in ascending order according to the map's comparator.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut map = tree::Map::with_cmp(natural().rev());
     ///
     /// map.insert(2, "b");
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// assert_eq!(map.len(), 3);
-    /// assert_eq!(map.get(&1), Some(&"a"));
-    /// assert_eq!(map.remove(&1), Some((1, "a")));
-    ///
-    /// assert_eq!(map.len(), 2);
-    /// assert_eq!(map.get(&1), None);
-    /// assert_eq!(map.remove(&1), None);
+    /// let (cmp, it) = map.into_parts();
+    /// assert!(cmp.compares_gt(&1, &2));
+    /// assert_eq!(it.collect::<Vec<_>>(), [(3, "c"), (2, "b"), (1, "a")]);
+    /// # }
     /// ```
-    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
-        where C: Compare<Q, K> {
-
-        node::find(&mut self.root, PathBuilder::default(), &self.cmp, key).remove(&mut self.len)
+    pub fn into_parts(self) -> (C, IntoIter<K, V>) {
+        (self.cmp, IntoIter(node::Iter::new(self.root, self.len)))
     }
 
-    /// Returns the map's entry corresponding to the given key.
+    /// Consumes the map and returns a map with the same keys and comparator whose values are the
+    /// results of applying the given function to the map's entries.
+    ///
+    /// The function is applied to the entries in ascending order according to the map's
+    /// comparator. The new map is built in `O(n)` time without any comparisons.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut counts = tree::Map::new();
+    /// let mut map = tree::Map::new();
     ///
-    /// for s in vec!["a", "b", "a", "c", "a", "b"] {
-    ///     *counts.entry(s).or_insert(0) += 1;
-    /// }
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
     ///
-    /// assert_eq!(counts[&"a"], 3);
-    /// assert_eq!(counts[&"b"], 2);
-    /// assert_eq!(counts[&"c"], 1);
+    /// let map = map.map_values(|key, value| format!("{}{}", value, key));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(),
+    ///     [(&1, &"a1".to_string()), (&2, &"b2".to_string()), (&3, &"c3".to_string())]);
     /// ```
-    pub fn entry(&mut self, key: K) -> Entry<K, V> {
-        node::find(&mut self.root, PathBuilder::default(), &self.cmp, &key)
-            .into_entry(&mut self.len, key)
+    pub fn map_values<U, F>(self, mut f: F) -> Map<K, U, C> where F: FnMut(&K, V) -> U {
+        let root = node::map_values(self.root, &mut f);
+        Map { root: root, len: self.len, cmp: self.cmp, counters: self.counters }
     }
 
-    /// Checks if the map contains the given key.
+    /// Consumes the map and returns a map with the same comparator containing the entries for
+    /// which the given function returns `Some`, with the values it returns.
+    ///
+    /// The function is applied to the entries in ascending order according to the map's
+    /// comparator. The new map is built in `O(n)` time without any comparisons.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert!(!map.contains_key(&1));
+    ///
+    /// map.insert(2, "b");
     /// map.insert(1, "a");
-    /// assert!(map.contains_key(&1));
+    /// map.insert(3, "c");
+    ///
+    /// let map = map.filter_map(|key, value| if key % 2 == 1 { Some(value.len()) } else { None });
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &1), (&3, &1)]);
     /// ```
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
-        self.get(key).is_some()
+    pub fn filter_map<U, F>(self, mut f: F) -> Map<K, U, C> where F: FnMut(&K, V) -> Option<U> {
+        let (cmp, it) = self.into_parts();
+        let entries = it.filter_map(|(key, value)| f(&key, value).map(|value| (key, value)));
+        Map::from_sorted(cmp, entries.collect())
     }
 
-    /// Returns a reference to the value associated with the given key, or `None` if the
-    /// map does not contain the key.
+    /// Consumes the map and partitions its entries into two maps with the same comparator: one
+    /// containing the entries for which the given predicate returns `true`, and one containing
+    /// the rest.
+    ///
+    /// The predicate is applied to the entries in ascending order according to the map's
+    /// comparator. Both maps are built from the map's nodes in `O(n)` time without any
+    /// comparisons or allocations.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert_eq!(map.get(&1), None);
+    ///
+    /// map.insert(2, "b");
     /// map.insert(1, "a");
-    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// map.insert(3, "c");
+    ///
+    /// let (odd, even) = map.partition(|key, _| key % 2 == 1);
+    /// assert_eq!(odd.iter().collect::<Vec<_>>(), [(&1, &"a"), (&3, &"c")]);
+    /// assert_eq!(even.iter().collect::<Vec<_>>(), [(&2, &"b")]);
     /// ```
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V> where C: Compare<Q, K> {
-        node::find(&self.root, Get::default(), &self.cmp, key).map(|e| e.1)
+    pub fn partition<F>(self, mut pred: F) -> (Self, Self)
+        where F: FnMut(&K, &V) -> bool, C: Clone {
+
+        let mut pass = Builder::with_cmp(self.cmp.clone());
+        let mut fail = Builder::with_cmp(self.cmp);
+        node::partition(self.root, &mut pred, &mut pass.inner, &mut fail.inner);
+        (pass.build(), fail.build())
     }
 
-    /// Returns a mutable reference to the value associated with the given key, or `None`
-    /// if the map does not contain the key.
+    /// Consumes the map and returns a map from each of its values to the key associated with it,
+    /// ordered according to the natural order of the values.
+    ///
+    /// If several keys are associated with equal values, the returned map associates the value
+    /// with the greatest of those keys according to this map's comparator. Use
+    /// [`invert_multi`](#method.invert_multi) to keep all of them.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert_eq!(map.get(&1), None);
-    /// map.insert(1, "a");
     ///
-    /// {
-    ///     let value = map.get_mut(&1).unwrap();
-    ///     assert_eq!(*value, "a");
-    ///     *value = "b";
-    /// }
+    /// map.insert("a", 2);
+    /// map.insert("b", 1);
+    /// map.insert("c", 2);
     ///
-    /// assert_eq!(map.get(&1), Some(&"b"));
+    /// let inverse = map.invert();
+    /// assert_eq!(inverse.iter().collect::<Vec<_>>(), [(&1, &"b"), (&2, &"c")]);
     /// ```
-    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
-        where C: Compare<Q, K> {
-
-        node::find(&mut self.root, GetMut::default(), &self.cmp, key).map(|e| e.1)
-    }
+    pub fn invert(self) -> Map<V, K> where V: Ord { self.invert_with_cmp(Natural::default()) }
 
-    /// Returns a reference to the map's maximum key and a reference to its associated
-    /// value, or `None` if the map is empty.
+    /// Consumes the map and returns a map from each of its values to the key associated with it,
+    /// ordered according to the given comparator.
+    ///
+    /// See [`invert`](#method.invert) for how equal values are handled. If the values are in
+    /// strictly ascending or strictly descending order according to the comparator, the new map
+    /// is built in `O(n)` time.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    /// assert_eq!(map.last(), None);
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// let map: tree::Map<_, _> = vec![(1, "b"), (2, "a")].into_iter().collect();
     ///
-    /// assert_eq!(map.last(), Some((&3, &"c")));
+    /// let inverse = map.invert_with_cmp(natural().rev());
+    /// assert_eq!(inverse.iter().collect::<Vec<_>>(), [(&"b", &1), (&"a", &2)]);
+    /// # }
     /// ```
-    pub fn last(&self) -> Option<(&K, &V)> {
-        Max::extreme(&self.root, Get::default())
+    pub fn invert_with_cmp<D>(self, cmp: D) -> Map<V, K, D> where D: Compare<V> {
+        Map::from_parts(cmp, self.into_iter().map(|(key, value)| (value, key)))
     }
 
-    /// Returns a reference to the map's maximum key and a mutable reference to its
-    /// associated value, or `None` if the map is empty.
+    /// Consumes the map and returns a map from each distinct value to all of the keys associated
+    /// with it, ordered according to the natural order of the values.
+    ///
+    /// The keys associated with each value are in ascending order according to this map's
+    /// comparator.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert_eq!(map.last(), None);
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
     ///
-    /// {
-    ///     let last = map.last_mut().unwrap();
-    ///     assert_eq!(last, (&3, &mut "c"));
-    ///     *last.1 = "cc";
-    /// }
+    /// map.insert("a", 2);
+    /// map.insert("b", 1);
+    /// map.insert("c", 2);
     ///
-    /// assert_eq!(map.last(), Some((&3, &"cc")));
+    /// let inverse = map.invert_multi();
+    /// assert_eq!(inverse.iter().collect::<Vec<_>>(), [(&1, &vec!["b"]), (&2, &vec!["a", "c"])]);
     /// ```
-    pub fn last_mut(&mut self) -> Option<(&K, &mut V)> {
-        Max::extreme(&mut self.root, GetMut::default())
+    pub fn invert_multi(self) -> Map<V, Vec<K>> where V: Ord {
+        self.invert_multi_with_cmp(Natural::default())
     }
 
-    /// Removes the map's maximum key and returns it and its associated value, or `None` if the map
-    /// is empty.
+    /// Consumes the map and returns a map from each distinct value to all of the keys associated
+    /// with it, ordered according to the given comparator.
+    ///
+    /// See [`invert_multi`](#method.invert_multi).
+    pub fn invert_multi_with_cmp<D>(self, cmp: D) -> Map<V, Vec<K>, D> where D: Compare<V> {
+        let mut map = Map::with_cmp(cmp);
+        for (key, value) in self { map.entry(value).or_insert_with(Vec::new).push(key); }
+        map
+    }
+
+    /// Consumes the map and returns a map from each distinct result of applying the given
+    /// function to the map's entries to the entries that produced it, ordered according to the
+    /// natural order of the results.
+    ///
+    /// The entries in each group are in ascending order according to this map's comparator. The
+    /// function is applied to the entries in that order, and consecutive entries that produce
+    /// equal results are grouped without searching the new map, so grouping by a function that
+    /// preserves the map's order into `g` groups takes `O(n + g log g)` time.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    /// assert_eq!(map.remove_last(), None);
+    /// let mut events = tree::Map::new();
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// events.insert(3_590, "a");
+    /// events.insert(3_610, "b");
+    /// events.insert(7_100, "c");
+    /// events.insert(7_300, "d");
     ///
-    /// assert_eq!(map.remove_last(), Some((3, "c")));
+    /// let hourly = events.group_by(|&seconds, _| seconds / 3_600);
+    ///
+    /// assert_eq!(hourly.iter().collect::<Vec<_>>(),
+    ///            [(&0, &vec![(3_590, "a")]), (&1, &vec![(3_610, "b"), (7_100, "c")]),
+    ///             (&2, &vec![(7_300, "d")])]);
     /// ```
-    pub fn remove_last(&mut self) -> Option<(K, V)> {
-        Max::extreme(&mut self.root, PathBuilder::default()).remove(&mut self.len)
+    pub fn group_by<G, F>(self, f: F) -> Map<G, Vec<(K, V)>> where G: Ord, F: FnMut(&K, &V) -> G {
+        self.group_by_with_cmp(Natural::default(), f)
     }
 
-    /// Returns the map's entry corresponding to its maximum key.
+    /// Consumes the map and returns a map from each distinct result of applying the given
+    /// function to the map's entries to the entries that produced it, ordered according to the
+    /// given comparator.
+    ///
+    /// See [`group_by`](#method.group_by) for details.
+    pub fn group_by_with_cmp<G, D, F>(self, cmp: D, mut f: F) -> Map<G, Vec<(K, V)>, D>
+        where D: Compare<G>, F: FnMut(&K, &V) -> G {
+
+        let mut groups = Map::with_cmp(cmp);
+        let mut run: Option<(G, Vec<(K, V)>)> = None;
+
+        for (key, value) in self {
+            let group = f(&key, &value);
+
+            if let Some((run_group, mut entries)) = run.take() {
+                if groups.cmp.compares_eq(&run_group, &group) {
+                    entries.push((key, value));
+                    run = Some((run_group, entries));
+                    continue;
+                }
+
+                groups.entry(run_group).or_insert_with(Vec::new).extend(entries);
+            }
+
+            run = Some((group, vec![(key, value)]));
+        }
+
+        if let Some((group, entries)) = run {
+            groups.entry(group).or_insert_with(Vec::new).extend(entries);
+        }
+
+        groups
+    }
+
+    /// Removes all entries from the map.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert!(map.last_entry().is_none());
     ///
     /// map.insert(2, "b");
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// {
-    ///     let mut e = map.last_entry().unwrap();
-    ///     assert_eq!(*e.key(), 3);
-    ///     assert_eq!(e.insert("cc"), "c");
-    /// }
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map.iter().next(), Some((&1, &"a")));
     ///
-    /// assert_eq!(map[&3], "cc");
+    /// map.clear();
+    ///
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.iter().next(), None);
     /// ```
-    pub fn last_entry(&mut self) -> Option<OccupiedEntry<K, V>> {
-        Max::extreme(&mut self.root, PathBuilder::default()).into_occupied_entry(&mut self.len)
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
     }
 
-    /// Returns a reference to the map's minimum key and a reference to its associated
-    /// value, or `None` if the map is empty.
+    /// Inserts an entry into the map, returning the previous value, if any, associated
+    /// with the key.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert_eq!(map.first(), None);
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
-    ///
-    /// assert_eq!(map.first(), Some((&1, &"a")));
+    /// assert_eq!(map.insert(1, "a"), None);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.insert(1, "b"), Some("a"));
+    /// assert_eq!(map.get(&1), Some(&"b"));
     /// ```
-    pub fn first(&self) -> Option<(&K, &V)> {
-        Min::extreme(&self.root, Get::default())
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_(key, value, false).map(|e| e.1)
     }
 
-    /// Returns a reference to the map's minimum key and a mutable reference to its
-    /// associated value, or `None` if the map is empty.
+    /// Inserts an entry into the map, returning the previous key and value, if any, that were
+    /// equal to the given key.
+    ///
+    /// Unlike [`insert`](#method.insert), this method replaces the key stored in the map as well
+    /// as its value. This matters when the map's comparator considers distinguishable keys equal.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    /// assert_eq!(map.first(), None);
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::Extract;
     ///
-    /// {
-    ///     let first = map.first_mut().unwrap();
-    ///     assert_eq!(first, (&1, &mut "a"));
-    ///     *first.1 = "aa";
-    /// }
+    /// let mut map = tree::Map::with_cmp(Extract::new(|s: &String| s.to_lowercase()));
     ///
-    /// assert_eq!(map.first(), Some((&1, &"aa")));
+    /// assert_eq!(map.insert_full("a".to_string(), 1), None);
+    /// assert_eq!(map.insert_full("A".to_string(), 2), Some(("a".to_string(), 1)));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&"A".to_string(), &2)]);
+    /// # }
     /// ```
-    pub fn first_mut(&mut self) -> Option<(&K, &mut V)> {
-        Min::extreme(&mut self.root, GetMut::default())
+    pub fn insert_full(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.insert_(key, value, true)
     }
 
-    /// Removes the map's minimum key and returns it and its associated value, or `None` if the map
-    /// is empty.
+    /// Inserts an entry whose key is greater than every key in the map.
+    ///
+    /// This compares the key only with the map's maximum key, and then descends along the tree's
+    /// right spine without further comparisons, which makes it cheaper than
+    /// [`insert`](#method.insert) for appending keys in ascending order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not greater than the map's maximum key.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert_eq!(map.remove_first(), None);
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// for i in 0..10 { map.insert_max(i, i * 10); }
     ///
-    /// assert_eq!(map.remove_first(), Some((1, "a")));
+    /// assert_eq!(map.len(), 10);
+    /// assert_eq!(map.last(), Some((&9, &90)));
     /// ```
-    pub fn remove_first(&mut self) -> Option<(K, V)> {
-        Min::extreme(&mut self.root, PathBuilder::default()).remove(&mut self.len)
-    }
+    pub fn insert_max(&mut self, key: K, value: V) { self.insert_extreme(key, value, true); }
 
-    /// Returns the map's entry corresponding to its minimum key.
+    /// Inserts an entry whose key is less than every key in the map.
+    ///
+    /// This compares the key only with the map's minimum key, and then descends along the tree's
+    /// left spine without further comparisons, which makes it cheaper than
+    /// [`insert`](#method.insert) for prepending keys in descending order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not less than the map's minimum key.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    /// assert!(map.first_entry().is_none());
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
     ///
-    /// {
-    ///     let mut e = map.first_entry().unwrap();
-    ///     assert_eq!(*e.key(), 1);
-    ///     assert_eq!(e.insert("aa"), "a");
-    /// }
+    /// for i in (0..10).rev() { map.insert_min(i, i * 10); }
     ///
-    /// assert_eq!(map[&1], "aa");
+    /// assert_eq!(map.len(), 10);
+    /// assert_eq!(map.first(), Some((&0, &0)));
     /// ```
-    pub fn first_entry(&mut self) -> Option<OccupiedEntry<K, V>> {
-        Min::extreme(&mut self.root, PathBuilder::default()).into_occupied_entry(&mut self.len)
-    }
+    pub fn insert_min(&mut self, key: K, value: V) { self.insert_extreme(key, value, false); }
 
-    /// Returns a reference to the predecessor of the given key and a
-    /// reference to its associated value, or `None` if no such key is present in the map.
+    /// Moves all entries from the given map into the map, leaving the given map empty.
     ///
-    /// If `inclusive` is `false`, this method finds the greatest key that is strictly less than
-    /// the given key. If `inclusive` is `true`, this method finds the greatest key that is less
-    /// than or equal to the given key.
+    /// If a key is present in both maps, the value from the given map replaces the one in this
+    /// map, as if the entries had been inserted one at a time. The larger map's tree is split at
+    /// each of the smaller map's keys and the pieces are joined back together, which takes
+    /// `O(m log(n/m + 1))` time for maps of sizes `m <= n` and reuses both maps' nodes.
     ///
-    /// The given key need not itself be present in the map.
+    /// The given map is assumed to share the map's comparator.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// let mut a: tree::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let mut b: tree::Map<_, _> = vec![(2, "bb"), (3, "cc")].into_iter().collect();
     ///
-    /// assert_eq!(map.pred(&0, false), None);
-    /// assert_eq!(map.pred(&1, false), None);
-    /// assert_eq!(map.pred(&2, false), Some((&1, &"a")));
-    /// assert_eq!(map.pred(&3, false), Some((&2, &"b")));
-    /// assert_eq!(map.pred(&4, false), Some((&3, &"c")));
+    /// a.append(&mut b);
     ///
-    /// assert_eq!(map.pred(&0, true), None);
-    /// assert_eq!(map.pred(&1, true), Some((&1, &"a")));
-    /// assert_eq!(map.pred(&2, true), Some((&2, &"b")));
-    /// assert_eq!(map.pred(&3, true), Some((&3, &"c")));
-    /// assert_eq!(map.pred(&4, true), Some((&3, &"c")));
+    /// assert!(b.is_empty());
+    /// assert_eq!(a.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"bb"), (&3, &"cc")]);
     /// ```
-    pub fn pred<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&K, &V)>
-        where C: Compare<Q, K> {
+    pub fn append(&mut self, other: &mut Self) {
+        let cmp = self.counters.count(&self.cmp);
+        let root = self.root.take();
+        let other_root = other.root.take();
+
+        // keep this map's key and the given map's value for keys in both, whichever tree is split
+        self.root = if self.len >= other.len {
+            node::union(root, other_root, &cmp, true)
+        } else {
+            node::union(other_root, root, &cmp, false)
+        };
 
-        Min::closest(&self.root, Get::default(), &self.cmp, key, inclusive)
+        self.len = node::size(&self.root);
+        other.len = 0;
     }
 
-    /// Returns a reference to the predecessor of the given key and a
-    /// mutable reference to its associated value, or `None` if no such key is present in the map.
-    ///
-    /// If `inclusive` is `false`, this method finds the greatest key that is strictly less than
-    /// the given key. If `inclusive` is `true`, this method finds the greatest key that is less
-    /// than or equal to the given key.
+    /// Splits the map in two at the given key, returning a map with the same comparator
+    /// containing the entries whose keys are greater than or equal to it.
     ///
-    /// The given key need not itself be present in the map.
+    /// This takes `O(log n)` time and reuses the map's nodes. Every key that the comparator
+    /// considers equal to the given key is moved to the returned map.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
+    /// let mut a: tree::Map<_, _> = vec![(1, "a"), (2, "b"), (3, "c")].into_iter().collect();
+    /// let b = a.split_off(&2);
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// assert_eq!(a.iter().collect::<Vec<_>>(), [(&1, &"a")]);
+    /// assert_eq!(b.iter().collect::<Vec<_>>(), [(&2, &"b"), (&3, &"c")]);
+    /// ```
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where C: Compare<Q, K> + Clone {
+        let (left, right) = {
+            let cmp = self.counters.count(&self.cmp);
+            node::split_by(self.root.take(), &mut |k| cmp.compares_gt(key, k))
+        };
+
+        self.root = left;
+        self.len = node::size(&self.root);
+
+        let len = node::size(&right);
+        Map { root: right, len: len, cmp: self.cmp.clone(), counters: Counters::default() }
+    }
+
+    /// Applies a batch of operations, given in strictly ascending order of their keys, to the
+    /// map.
     ///
-    /// {
-    ///     let pred = map.pred_mut(&2, false).unwrap();
-    ///     assert_eq!(pred, (&1, &mut "a"));
-    ///     *pred.1 = "aa";
-    /// }
+    /// Each `Upsert` inserts its key and value, replacing the value of an equal key that is
+    /// already present, and each `Delete` removes its key if it is present. If the batch is much
+    /// smaller than the map, its operations are applied one at a time in `O(m log n)` time.
+    /// Otherwise, the batch is merged with the map's entries and the tree is rebuilt in `O(m + n)`
+    /// time.
     ///
-    /// assert_eq!(map.pred(&2, false), Some((&1, &"aa")));
+    /// # Panics
     ///
-    /// {
-    ///     let pred_or_eq = map.pred_mut(&1, true).unwrap();
-    ///     assert_eq!(pred_or_eq, (&1, &mut "aa"));
-    ///     *pred_or_eq.1 = "aaa";
-    /// }
+    /// Panics if the batch's keys are not in strictly ascending order according to the map's
+    /// comparator. The map is left unmodified in that case.
     ///
-    /// {
-    ///     let pred_or_eq = map.pred_mut(&4, true).unwrap();
-    ///     assert_eq!(pred_or_eq, (&3, &mut "c"));
-    ///     *pred_or_eq.1 = "cc";
-    /// }
+    /// # Examples
     ///
-    /// assert_eq!(map.pred(&1, true), Some((&1, &"aaa")));
-    /// assert_eq!(map.pred(&4, true), Some((&3, &"cc")));
     /// ```
-    pub fn pred_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
-        where C: Compare<Q, K> {
+    /// use tree::map::BatchOp::{Delete, Upsert};
+    ///
+    /// let mut map: tree::Map<_, _> = vec![(1, "a"), (2, "b"), (3, "c")].into_iter().collect();
+    ///
+    /// map.apply_batch(vec![(0, Upsert("z")), (2, Delete), (3, Upsert("cc")), (4, Delete)]);
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&0, &"z"), (&1, &"a"), (&3, &"cc")]);
+    /// ```
+    pub fn apply_batch<I>(&mut self, ops: I) where I: IntoIterator<Item=(K, BatchOp<V>)> {
+        let ops = ops.into_iter().collect::<Vec<_>>();
+
+        assert!(ops.windows(2).all(|w| self.cmp.compares_lt(&w[0].0, &w[1].0)),
+                "batch keys are not in strictly ascending order");
+
+        if node::is_small(ops.len(), self.len) {
+            for (key, op) in ops {
+                match op {
+                    BatchOp::Upsert(value) => { self.insert(key, value); }
+                    BatchOp::Delete => { self.remove(&key); }
+                }
+            }
+
+            return;
+        }
+
+        let entries = IntoIter(node::Iter::new(self.root.take(), self.len));
+        let mut builder = node::Builder::new();
+
+        for (key, op) in node::Merge::new(entries, ops.into_iter(), &self.cmp) {
+            match op {
+                EitherOrBoth::Left(value) |
+                EitherOrBoth::Right(BatchOp::Upsert(value)) |
+                EitherOrBoth::Both(_, BatchOp::Upsert(value)) => builder.push(key, value),
+                EitherOrBoth::Right(BatchOp::Delete) | EitherOrBoth::Both(_, BatchOp::Delete) => {}
+            }
+        }
 
-        Min::closest(&mut self.root, GetMut::default(), &self.cmp, key, inclusive)
+        self.len = builder.len();
+        event!("rebuild: len={}", self.len);
+        self.root = builder.build();
     }
 
-    /// Removes the predecessor of the given key from the map and returns it and its associated
-    /// value, or `None` if no such key is present in the map.
-    ///
-    /// If `inclusive` is `false`, this method removes the greatest key that is strictly less than
-    /// the given key. If `inclusive` is `true`, this method removes the greatest key that is less
-    /// than or equal to the given key.
-    ///
-    /// The given key need not itself be present in the map.
+    fn insert_extreme(&mut self, key: K, value: V, max: bool) {
+        let cmp = self.counters.count(&self.cmp);
+
+        if let Some((extreme, _)) = if max { self.last() } else { self.first() } {
+            if max {
+                assert!(cmp.compares_gt(&key, extreme),
+                        "key is not greater than the map's maximum key");
+            } else {
+                assert!(cmp.compares_lt(&key, extreme),
+                        "key is not less than the map's minimum key");
+            }
+        }
+
+        node::insert_extreme(&mut self.root, key, value, max);
+        self.len += 1;
+        event!("insert: len={}", self.len);
+        self.counters.allocation();
+        self.check_sorted();
+    }
+
+    fn insert_(&mut self, key: K, value: V, replace_key: bool) -> Option<(K, V)> {
+        self.check_path(&key);
+        let cmp = self.counters.count(&self.cmp);
+        let old = node::insert(&mut self.root, &cmp, key, value, replace_key);
+
+        match old {
+            None => {
+                self.len += 1;
+                event!("insert: len={}", self.len);
+                self.counters.allocation();
+                self.check_sorted();
+            }
+            Some(_) => { event!("replace: len={}", self.len); }
+        }
+
+        old
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key, returning
+    /// `None` if the map does not contain the key.
     ///
     /// # Examples
     ///
@@ -535,187 +783,1620 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// assert_eq!(map.remove_pred(&1, false), None);
-    /// assert!(map.contains_key(&1));
-    ///
-    /// assert_eq!(map.remove_pred(&2, false), Some((1, "a")));
-    /// assert!(!map.contains_key(&1));
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.remove(&1), Some((1, "a")));
     ///
-    /// assert_eq!(map.remove_pred(&2, true), Some((2, "b")));
-    /// assert!(!map.contains_key(&2));
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map.get(&1), None);
+    /// assert_eq!(map.remove(&1), None);
     /// ```
-    pub fn remove_pred<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
         where C: Compare<Q, K> {
 
-        Min::closest(&mut self.root, PathBuilder::default(), &self.cmp, key, inclusive)
-            .remove(&mut self.len)
+        self.check_path(key);
+        let cmp = self.counters.count(&self.cmp);
+        node::find(&mut self.root, PathBuilder::default(), &cmp, key).remove(&mut self.len)
     }
 
-    /// Returns the entry corresponding to the predecessor of the given key.
-    ///
-    /// If `inclusive` is `false`, this method returns the entry corresponding to the greatest key
-    /// that is strictly less than the given key. If `inclusive` is `true`, this method returns
-    /// the entry corresponding to the greatest key that is less than or equal to the given key.
+    /// Removes and returns the entry whose key is equal to the given key if the given predicate
+    /// returns `true` for it, returning `None` if the map does not contain the key or the
+    /// predicate returns `false`.
     ///
-    /// The given key need not itself be present in the map.
+    /// The predicate is applied to the entry found by a single descent of the tree, which is also
+    /// used to remove it.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
     ///
-    /// assert!(map.pred_entry(&1, false).is_none());
+    /// assert_eq!(map.remove_if(&"a", |_, &value| value > 1), None);
+    /// assert_eq!(map.remove_if(&"b", |_, &value| value > 1), Some(("b", 2)));
+    /// assert_eq!(map.remove_if(&"c", |_, _| true), None);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&"a", &1)]);
+    /// ```
+    pub fn remove_if<Q: ?Sized, F>(&mut self, key: &Q, pred: F) -> Option<(K, V)>
+        where C: Compare<Q, K>, F: FnOnce(&K, &V) -> bool {
+
+        self.check_path(key);
+        let cmp = self.counters.count(&self.cmp);
+
+        node::find(&mut self.root, PathBuilder::default(), &cmp, key)
+            .into_occupied_entry(&mut self.len, &self.counters)
+            .and_then(|e| if pred(e.key(), e.get()) { Some(e.remove()) } else { None })
+    }
+
+    /// Returns the map's entry corresponding to the given key.
     ///
-    /// {
-    ///     let mut e = map.pred_entry(&4, true).unwrap();
-    ///     assert_eq!(*e.key(), 3);
-    ///     assert_eq!(e.insert("cc"), "c");
-    /// }
+    /// # Examples
     ///
-    /// assert_eq!(map[&3], "cc");
+    /// ```
+    /// let mut counts = tree::Map::new();
     ///
-    /// {
-    ///     let e = map.pred_entry(&3, false).unwrap();
-    ///     assert_eq!(e.remove(), (2, "b"));
+    /// for s in vec!["a", "b", "a", "c", "a", "b"] {
+    ///     *counts.entry(s).or_insert(0) += 1;
     /// }
     ///
-    /// assert!(!map.contains_key(&2));
+    /// assert_eq!(counts[&"a"], 3);
+    /// assert_eq!(counts[&"b"], 2);
+    /// assert_eq!(counts[&"c"], 1);
     /// ```
-    pub fn pred_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
-        -> Option<OccupiedEntry<K, V>> where C: Compare<Q, K> {
-
-        Min::closest(&mut self.root, PathBuilder::default(), &self.cmp, key, inclusive)
-            .into_occupied_entry(&mut self.len)
+    pub fn entry(&mut self, key: K) -> Entry<K, V> {
+        self.check_path(&key);
+        let cmp = self.counters.count(&self.cmp);
+        node::find(&mut self.root, PathBuilder::default(), &cmp, &key)
+            .into_entry(&mut self.len, &self.counters, key)
     }
 
-    /// Returns a reference to the successor of the given key and a
-    /// reference to its associated value, or `None` if no such key is present in the map.
-    ///
-    /// If `inclusive` is `false`, this method finds the smallest key that is strictly greater than
-    /// the given key. If `inclusive` is `true`, this method finds the smallest key that is greater
-    /// than or equal to the given key.
+    /// Inserts the given entries into the map, as with `Extend`, returning the number of keys that
+    /// were not already present.
     ///
-    /// The given key need not itself be present in the map.
+    /// Entries whose keys are already present replace their values, as with
+    /// [`insert`](#method.insert), and are not counted.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    ///
-    /// map.insert(2, "b");
     /// map.insert(1, "a");
-    /// map.insert(3, "c");
-    ///
-    /// assert_eq!(map.succ(&0, false), Some((&1, &"a")));
-    /// assert_eq!(map.succ(&1, false), Some((&2, &"b")));
-    /// assert_eq!(map.succ(&2, false), Some((&3, &"c")));
-    /// assert_eq!(map.succ(&3, false), None);
-    /// assert_eq!(map.succ(&4, false), None);
     ///
-    /// assert_eq!(map.succ(&0, true), Some((&1, &"a")));
-    /// assert_eq!(map.succ(&1, true), Some((&1, &"a")));
-    /// assert_eq!(map.succ(&2, true), Some((&2, &"b")));
-    /// assert_eq!(map.succ(&3, true), Some((&3, &"c")));
-    /// assert_eq!(map.succ(&4, true), None);
+    /// assert_eq!(map.insert_many(vec![(1, "aa"), (2, "b"), (3, "c"), (2, "bb")]), 2);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"aa"), (&2, &"bb"), (&3, &"c")]);
     /// ```
-    pub fn succ<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&K, &V)>
-        where C: Compare<Q, K> {
-
-        Max::closest(&self.root, Get::default(), &self.cmp, key, inclusive)
+    pub fn insert_many<I>(&mut self, entries: I) -> usize where I: IntoIterator<Item=(K, V)> {
+        let len = self.len;
+        self.extend(entries);
+        self.len - len
     }
 
-    /// Returns a reference to the successor of the given key and a
-    /// mutable reference to its associated value, or `None` if no such key is present in the map.
-    ///
-    /// If `inclusive` is `false`, this method finds the smallest key that is strictly greater than
-    /// the given key. If `inclusive` is `true`, this method finds the smallest key that is greater
-    /// than or equal to the given key.
+    /// Inserts the given key with the result of `insert` if the map does not contain an equal key,
+    /// or applies `update` to the equal key's value otherwise, returning `true` if the key was
+    /// inserted.
     ///
-    /// The given key need not itself be present in the map.
+    /// This is equivalent to matching on [`entry`](#method.entry), but searches the map without
+    /// recording the path to the key, and rebalances the tree only if the key is inserted.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut map = tree::Map::new();
-    ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// let mut counts = tree::Map::new();
     ///
-    /// {
-    ///     let succ = map.succ_mut(&2, false).unwrap();
-    ///     assert_eq!(succ, (&3, &mut "c"));
-    ///     *succ.1 = "cc";
+    /// for word in "a b a c a".split(' ') {
+    ///     counts.upsert(word, || 1, |count| *count += 1);
     /// }
     ///
-    /// assert_eq!(map.succ(&2, false), Some((&3, &"cc")));
+    /// assert_eq!(counts.iter().collect::<Vec<_>>(), [(&"a", &3), (&"b", &1), (&"c", &1)]);
+    /// assert!(!counts.upsert("a", || 1, |count| *count = 0));
+    /// assert_eq!(counts[&"a"], 0);
+    /// ```
+    pub fn upsert<I, U>(&mut self, key: K, insert: I, update: U) -> bool
+        where I: FnOnce() -> V, U: FnOnce(&mut V) {
+
+        self.check_path(&key);
+        let cmp = self.counters.count(&self.cmp);
+        let inserted = node::upsert(&mut self.root, &cmp, key, insert, update);
+
+        if inserted {
+            self.len += 1;
+            event!("insert: len={}", self.len);
+            self.counters.allocation();
+            self.check_sorted();
+        }
+
+        inserted
+    }
+
+    /// Inserts an entry into the map, replacing the value of an equal key if the map already
+    /// contains one, and returns the map's entry for the key.
     ///
-    /// {
-    ///     let succ_or_eq = map.succ_mut(&0, true).unwrap();
-    ///     assert_eq!(succ_or_eq, (&1, &mut "a"));
-    ///     *succ_or_eq.1 = "aa";
-    /// }
+    /// The returned entry can be used to inspect, replace, or remove the entry without searching
+    /// the map again. If the key is new, the map is searched once more after rebalancing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(1, "a");
     ///
     /// {
-    ///     let succ_or_eq = map.succ_mut(&3, true).unwrap();
-    ///     assert_eq!(succ_or_eq, (&3, &mut "cc"));
-    ///     *succ_or_eq.1 = "ccc";
+    ///     let mut e = map.insert_entry(1, "b");
+    ///     assert_eq!(e.insert("c"), "b");
     /// }
     ///
-    /// assert_eq!(map.succ(&0, true), Some((&1, &"aa")));
-    /// assert_eq!(map.succ(&3, true), Some((&3, &"ccc")));
+    /// assert_eq!(map.insert_entry(2, "d").remove(), (2, "d"));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"c")]);
     /// ```
-    pub fn succ_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
-        where C: Compare<Q, K> {
-
-        Max::closest(&mut self.root, GetMut::default(), &self.cmp, key, inclusive)
+    pub fn insert_entry(&mut self, key: K, value: V) -> OccupiedEntry<K, V> {
+        self.check_path(&key);
+        let cmp = self.counters.count(&self.cmp);
+        node::insert_entry(&mut self.root, &mut self.len, &self.counters, &cmp, key, value)
     }
 
-    /// Removes the successor of the given key from the map and returns it and its associated
-    /// value, or `None` if no such key is present in the map.
-    ///
-    /// If `inclusive` is `false`, this method removes the smallest key that is strictly greater
-    /// than the given key. If `inclusive` is `true`, this method removes the smallest key that is
-    /// greater than or equal to the given key.
-    ///
-    /// The given key need not itself be present in the map.
+    /// Checks if the map contains the given key.
     ///
     /// # Examples
     ///
     /// ```
     /// let mut map = tree::Map::new();
-    ///
-    /// map.insert(2, "b");
+    /// assert!(!map.contains_key(&1));
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// ```
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value associated with the given key, or `None` if the
+    /// map does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.get(&1), None);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// ```
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V> where C: Compare<Q, K> {
+        self.check_path(key);
+        let cmp = self.counters.count(&self.cmp);
+        node::find(&self.root, Get::default(), &cmp, key).map(|e| e.1)
+    }
+
+    /// Returns a mutable reference to the value associated with the given key, or `None`
+    /// if the map does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.get(&1), None);
+    /// map.insert(1, "a");
+    ///
+    /// {
+    ///     let value = map.get_mut(&1).unwrap();
+    ///     assert_eq!(*value, "a");
+    ///     *value = "b";
+    /// }
+    ///
+    /// assert_eq!(map.get(&1), Some(&"b"));
+    /// ```
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+        where C: Compare<Q, K> {
+
+        self.check_path(key);
+        let cmp = self.counters.count(&self.cmp);
+        node::find(&mut self.root, GetMut::default(), &cmp, key).map(|e| e.1)
+    }
+
+    /// Replaces the value associated with the given key with the given value, returning the old
+    /// value, or returns the given value back if the map does not contain the key.
+    ///
+    /// Unlike [`insert`](#method.insert), this never adds an entry to the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert("a", 1);
+    ///
+    /// assert_eq!(map.replace_value(&"a", 2), Ok(1));
+    /// assert_eq!(map.replace_value(&"b", 3), Err(3));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&"a", &2)]);
+    /// ```
+    pub fn replace_value<Q: ?Sized>(&mut self, key: &Q, value: V) -> Result<V, V>
+        where C: Compare<Q, K> {
+
+        match self.get_mut(key) {
+            None => Err(value),
+            Some(old) => Ok(mem::replace(old, value)),
+        }
+    }
+
+    /// Moves the value associated with the given key to the given new key.
+    ///
+    /// If the map does not contain the old key, this returns `RenameError::Missing`. If the map
+    /// contains a key equal to the new key, other than the old key itself, this returns
+    /// `RenameError::Occupied`. In either case, the map is left unmodified. Renaming a key to an
+    /// equal key replaces the stored key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::map::RenameError;
+    ///
+    /// let mut map = tree::Map::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// assert_eq!(map.rename_key(&"a", "c"), Ok(()));
+    /// assert_eq!(map.rename_key(&"a", "d"), Err(RenameError::Missing));
+    /// assert_eq!(map.rename_key(&"b", "c"), Err(RenameError::Occupied));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&"b", &2), (&"c", &1)]);
+    /// ```
+    pub fn rename_key<Q: ?Sized>(&mut self, old: &Q, new: K) -> Result<(), RenameError>
+        where C: Compare<Q, K> {
+
+        if !self.contains_key(old) { return Err(RenameError::Missing); }
+
+        if !self.cmp.compares_eq(old, &new) && self.contains_key(&new) {
+            return Err(RenameError::Occupied);
+        }
+
+        let (_, value) = self.remove(old).unwrap();
+        self.insert(new, value);
+        Ok(())
+    }
+
+    /// Swaps the values associated with the given keys, returning `false` without modifying the
+    /// map if either key is not present.
+    ///
+    /// The values are swapped in place, without being cloned or moving any entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// assert!(map.swap_values(&1, &2));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"b"), (&2, &"a")]);
+    ///
+    /// assert!(!map.swap_values(&1, &3));
+    /// assert_eq!(map[&1], "b");
+    /// ```
+    pub fn swap_values<Q: ?Sized>(&mut self, k1: &Q, k2: &Q) -> bool where C: Compare<Q, K> {
+        self.check_path(k1);
+        self.check_path(k2);
+        let cmp = self.counters.count(&self.cmp);
+        node::swap_values(&mut self.root, &cmp, k1, k2)
+    }
+
+    /// Applies the given function to the value associated with the given key, returning `true`
+    /// if the map contains the key and `false` otherwise.
+    ///
+    /// Unlike [`entry`](#method.entry), this does not require an owned key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert("a".to_string(), 1);
+    ///
+    /// assert!(map.update("a", |value| *value += 1));
+    /// assert!(!map.update("b", |value| *value += 1));
+    ///
+    /// assert_eq!(map.get("a"), Some(&2));
+    /// assert_eq!(map.get("b"), None);
+    /// ```
+    pub fn update<Q: ?Sized, F>(&mut self, key: &Q, f: F) -> bool
+        where C: Compare<Q, K>, F: FnOnce(&mut V) {
+
+        match self.get_mut(key) {
+            Some(value) => { f(value); true }
+            None => false,
+        }
+    }
+
+    /// Returns a reference to the map's maximum key and a reference to its associated
+    /// value, or `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.last(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.last(), Some((&3, &"c")));
+    /// ```
+    pub fn last(&self) -> Option<(&K, &V)> {
+        Max::extreme(&self.root, Get::default())
+    }
+
+    /// Returns a reference to the map's maximum key and a reference to its associated value, or
+    /// `None` if the map is empty.
+    ///
+    /// This is equivalent to [`last`](#method.last) and matches the name used by
+    /// `std::collections::BTreeMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.last_key_value(), Some((&3, &"c")));
+    /// ```
+    pub fn last_key_value(&self) -> Option<(&K, &V)> { self.last() }
+
+    /// Returns a reference to the map's maximum key and a mutable reference to its
+    /// associated value, or `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.last(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let last = map.last_mut().unwrap();
+    ///     assert_eq!(last, (&3, &mut "c"));
+    ///     *last.1 = "cc";
+    /// }
+    ///
+    /// assert_eq!(map.last(), Some((&3, &"cc")));
+    /// ```
+    pub fn last_mut(&mut self) -> Option<(&K, &mut V)> {
+        Max::extreme(&mut self.root, GetMut::default())
+    }
+
+    /// Removes the map's maximum key and returns it and its associated value, or `None` if the map
+    /// is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.remove_last(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_last(), Some((3, "c")));
+    /// ```
+    pub fn remove_last(&mut self) -> Option<(K, V)> {
+        Max::extreme(&mut self.root, PathBuilder::default()).remove(&mut self.len)
+    }
+
+    /// Removes the map's maximum key and returns it and its associated value, or `None` if the map
+    /// is empty.
+    ///
+    /// This is equivalent to [`remove_last`](#method.remove_last) and matches the name used by
+    /// `std::collections::BTreeMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.pop_last(), Some((3, "c")));
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn pop_last(&mut self) -> Option<(K, V)> { self.remove_last() }
+
+    /// Returns the map's entry corresponding to its maximum key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert!(map.last_entry().is_none());
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let mut e = map.last_entry().unwrap();
+    ///     assert_eq!(*e.key(), 3);
+    ///     assert_eq!(e.insert("cc"), "c");
+    /// }
+    ///
+    /// assert_eq!(map[&3], "cc");
+    /// ```
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<K, V>> {
+        Max::extreme(&mut self.root, PathBuilder::default())
+            .into_occupied_entry(&mut self.len, &self.counters)
+    }
+
+    /// Returns a reference to the map's minimum key and a reference to its associated
+    /// value, or `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.first(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.first(), Some((&1, &"a")));
+    /// ```
+    pub fn first(&self) -> Option<(&K, &V)> {
+        Min::extreme(&self.root, Get::default())
+    }
+
+    /// Returns a reference to the map's minimum key and a reference to its associated value, or
+    /// `None` if the map is empty.
+    ///
+    /// This is equivalent to [`first`](#method.first) and matches the name used by
+    /// `std::collections::BTreeMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.first_key_value(), Some((&1, &"a")));
+    /// ```
+    pub fn first_key_value(&self) -> Option<(&K, &V)> { self.first() }
+
+    /// Returns a reference to the map's minimum key and a mutable reference to its
+    /// associated value, or `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.first(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let first = map.first_mut().unwrap();
+    ///     assert_eq!(first, (&1, &mut "a"));
+    ///     *first.1 = "aa";
+    /// }
+    ///
+    /// assert_eq!(map.first(), Some((&1, &"aa")));
+    /// ```
+    pub fn first_mut(&mut self) -> Option<(&K, &mut V)> {
+        Min::extreme(&mut self.root, GetMut::default())
+    }
+
+    /// Removes the map's minimum key and returns it and its associated value, or `None` if the map
+    /// is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.remove_first(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_first(), Some((1, "a")));
+    /// ```
+    pub fn remove_first(&mut self) -> Option<(K, V)> {
+        Min::extreme(&mut self.root, PathBuilder::default()).remove(&mut self.len)
+    }
+
+    /// Removes the map's minimum key and returns it and its associated value, or `None` if the map
+    /// is empty.
+    ///
+    /// This is equivalent to [`remove_first`](#method.remove_first) and matches the name used by
+    /// `std::collections::BTreeMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.pop_first(), Some((1, "a")));
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn pop_first(&mut self) -> Option<(K, V)> { self.remove_first() }
+
+    /// Returns the map's entry corresponding to its minimum key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert!(map.first_entry().is_none());
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let mut e = map.first_entry().unwrap();
+    ///     assert_eq!(*e.key(), 1);
+    ///     assert_eq!(e.insert("aa"), "a");
+    /// }
+    ///
+    /// assert_eq!(map[&1], "aa");
+    /// ```
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<K, V>> {
+        Min::extreme(&mut self.root, PathBuilder::default())
+            .into_occupied_entry(&mut self.len, &self.counters)
+    }
+
+    /// Returns a reference to the predecessor of the given key and a
+    /// reference to its associated value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method finds the greatest key that is strictly less than
+    /// the given key. If `inclusive` is `true`, this method finds the greatest key that is less
+    /// than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.pred(&0, false), None);
+    /// assert_eq!(map.pred(&1, false), None);
+    /// assert_eq!(map.pred(&2, false), Some((&1, &"a")));
+    /// assert_eq!(map.pred(&3, false), Some((&2, &"b")));
+    /// assert_eq!(map.pred(&4, false), Some((&3, &"c")));
+    ///
+    /// assert_eq!(map.pred(&0, true), None);
+    /// assert_eq!(map.pred(&1, true), Some((&1, &"a")));
+    /// assert_eq!(map.pred(&2, true), Some((&2, &"b")));
+    /// assert_eq!(map.pred(&3, true), Some((&3, &"c")));
+    /// assert_eq!(map.pred(&4, true), Some((&3, &"c")));
+    /// ```
+    pub fn pred<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&K, &V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Min::closest(&self.root, Get::default(), &cmp, key, inclusive)
+    }
+
+    /// Returns a reference to the predecessor of the given key and a
+    /// mutable reference to its associated value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method finds the greatest key that is strictly less than
+    /// the given key. If `inclusive` is `true`, this method finds the greatest key that is less
+    /// than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let pred = map.pred_mut(&2, false).unwrap();
+    ///     assert_eq!(pred, (&1, &mut "a"));
+    ///     *pred.1 = "aa";
+    /// }
+    ///
+    /// assert_eq!(map.pred(&2, false), Some((&1, &"aa")));
+    ///
+    /// {
+    ///     let pred_or_eq = map.pred_mut(&1, true).unwrap();
+    ///     assert_eq!(pred_or_eq, (&1, &mut "aa"));
+    ///     *pred_or_eq.1 = "aaa";
+    /// }
+    ///
+    /// {
+    ///     let pred_or_eq = map.pred_mut(&4, true).unwrap();
+    ///     assert_eq!(pred_or_eq, (&3, &mut "c"));
+    ///     *pred_or_eq.1 = "cc";
+    /// }
+    ///
+    /// assert_eq!(map.pred(&1, true), Some((&1, &"aaa")));
+    /// assert_eq!(map.pred(&4, true), Some((&3, &"cc")));
+    /// ```
+    pub fn pred_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Min::closest(&mut self.root, GetMut::default(), &cmp, key, inclusive)
+    }
+
+    /// Removes the predecessor of the given key from the map and returns it and its associated
+    /// value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method removes the greatest key that is strictly less than
+    /// the given key. If `inclusive` is `true`, this method removes the greatest key that is less
+    /// than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_pred(&1, false), None);
+    /// assert!(map.contains_key(&1));
+    ///
+    /// assert_eq!(map.remove_pred(&2, false), Some((1, "a")));
+    /// assert!(!map.contains_key(&1));
+    ///
+    /// assert_eq!(map.remove_pred(&2, true), Some((2, "b")));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn remove_pred<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Min::closest(&mut self.root, PathBuilder::default(), &cmp, key, inclusive)
+            .remove(&mut self.len)
+    }
+
+    /// Returns the entry corresponding to the predecessor of the given key.
+    ///
+    /// If `inclusive` is `false`, this method returns the entry corresponding to the greatest key
+    /// that is strictly less than the given key. If `inclusive` is `true`, this method returns
+    /// the entry corresponding to the greatest key that is less than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert!(map.pred_entry(&1, false).is_none());
+    ///
+    /// {
+    ///     let mut e = map.pred_entry(&4, true).unwrap();
+    ///     assert_eq!(*e.key(), 3);
+    ///     assert_eq!(e.insert("cc"), "c");
+    /// }
+    ///
+    /// assert_eq!(map[&3], "cc");
+    ///
+    /// {
+    ///     let e = map.pred_entry(&3, false).unwrap();
+    ///     assert_eq!(e.remove(), (2, "b"));
+    /// }
+    ///
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn pred_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
+        -> Option<OccupiedEntry<K, V>> where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Min::closest(&mut self.root, PathBuilder::default(), &cmp, key, inclusive)
+            .into_occupied_entry(&mut self.len, &self.counters)
+    }
+
+    /// Returns a reference to the successor of the given key and a
+    /// reference to its associated value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method finds the smallest key that is strictly greater than
+    /// the given key. If `inclusive` is `true`, this method finds the smallest key that is greater
+    /// than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.succ(&0, false), Some((&1, &"a")));
+    /// assert_eq!(map.succ(&1, false), Some((&2, &"b")));
+    /// assert_eq!(map.succ(&2, false), Some((&3, &"c")));
+    /// assert_eq!(map.succ(&3, false), None);
+    /// assert_eq!(map.succ(&4, false), None);
+    ///
+    /// assert_eq!(map.succ(&0, true), Some((&1, &"a")));
+    /// assert_eq!(map.succ(&1, true), Some((&1, &"a")));
+    /// assert_eq!(map.succ(&2, true), Some((&2, &"b")));
+    /// assert_eq!(map.succ(&3, true), Some((&3, &"c")));
+    /// assert_eq!(map.succ(&4, true), None);
+    /// ```
+    pub fn succ<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&K, &V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Max::closest(&self.root, Get::default(), &cmp, key, inclusive)
+    }
+
+    /// Returns a reference to the successor of the given key and a
+    /// mutable reference to its associated value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method finds the smallest key that is strictly greater than
+    /// the given key. If `inclusive` is `true`, this method finds the smallest key that is greater
+    /// than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// {
+    ///     let succ = map.succ_mut(&2, false).unwrap();
+    ///     assert_eq!(succ, (&3, &mut "c"));
+    ///     *succ.1 = "cc";
+    /// }
+    ///
+    /// assert_eq!(map.succ(&2, false), Some((&3, &"cc")));
+    ///
+    /// {
+    ///     let succ_or_eq = map.succ_mut(&0, true).unwrap();
+    ///     assert_eq!(succ_or_eq, (&1, &mut "a"));
+    ///     *succ_or_eq.1 = "aa";
+    /// }
+    ///
+    /// {
+    ///     let succ_or_eq = map.succ_mut(&3, true).unwrap();
+    ///     assert_eq!(succ_or_eq, (&3, &mut "cc"));
+    ///     *succ_or_eq.1 = "ccc";
+    /// }
+    ///
+    /// assert_eq!(map.succ(&0, true), Some((&1, &"aa")));
+    /// assert_eq!(map.succ(&3, true), Some((&3, &"ccc")));
+    /// ```
+    pub fn succ_mut<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(&K, &mut V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Max::closest(&mut self.root, GetMut::default(), &cmp, key, inclusive)
+    }
+
+    /// Removes the successor of the given key from the map and returns it and its associated
+    /// value, or `None` if no such key is present in the map.
+    ///
+    /// If `inclusive` is `false`, this method removes the smallest key that is strictly greater
+    /// than the given key. If `inclusive` is `true`, this method removes the smallest key that is
+    /// greater than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_succ(&3, false), None);
+    /// assert!(map.contains_key(&3));
+    ///
+    /// assert_eq!(map.remove_succ(&2, false), Some((3, "c")));
+    /// assert!(!map.contains_key(&3));
+    ///
+    /// assert_eq!(map.remove_succ(&2, true), Some((2, "b")));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn remove_succ<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Max::closest(&mut self.root, PathBuilder::default(), &cmp, key, inclusive)
+            .remove(&mut self.len)
+    }
+
+    /// Returns the entry corresponding to the successor of the given key.
+    ///
+    /// If `inclusive` is `false`, this method returns the entry corresponding to the smallest key
+    /// that is strictly greater than the given key. If `inclusive` is `true`, this method returns
+    /// the entry corresponding to the smallest key that is greater than or equal to the given key.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert!(map.succ_entry(&3, false).is_none());
+    ///
+    /// {
+    ///     let mut e = map.succ_entry(&0, true).unwrap();
+    ///     assert_eq!(*e.key(), 1);
+    ///     assert_eq!(e.insert("aa"), "a");
+    /// }
+    ///
+    /// assert_eq!(map[&1], "aa");
+    ///
+    /// {
+    ///     let e = map.succ_entry(&1, false).unwrap();
+    ///     assert_eq!(e.remove(), (2, "b"));
+    /// }
+    ///
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn succ_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
+        -> Option<OccupiedEntry<K, V>> where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Max::closest(&mut self.root, PathBuilder::default(), &cmp, key, inclusive)
+            .into_occupied_entry(&mut self.len, &self.counters)
+    }
+
+    /// Returns a reference to the `n`th successor of the given key and a reference to its
+    /// associated value, or `None` if the map does not contain that many keys greater than the
+    /// given key.
+    ///
+    /// Like `Iterator::nth`, this counts from zero, so `nth_succ(key, 0)` is equivalent to
+    /// `succ(key, false)`. This takes `O(log n)` time regardless of `n`, since each node records
+    /// the size of its subtree.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    ///
+    /// assert_eq!(map.nth_succ(&30, 0), Some((&40, &4)));
+    /// assert_eq!(map.nth_succ(&30, 2), Some((&60, &6)));
+    /// assert_eq!(map.nth_succ(&35, 2), Some((&60, &6)));
+    /// assert_eq!(map.nth_succ(&30, 6), None);
+    /// ```
+    pub fn nth_succ<Q: ?Sized>(&self, key: &Q, n: usize) -> Option<(&K, &V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        let (rank, found) = node::rank(&self.root, &cmp, key);
+        let rank = if found { rank + 1 } else { rank };
+        rank.checked_add(n).and_then(|rank| node::select(&self.root, rank))
+    }
+
+    /// Returns a reference to the `n`th predecessor of the given key and a reference to its
+    /// associated value, or `None` if the map does not contain that many keys less than the
+    /// given key.
+    ///
+    /// Like `Iterator::nth`, this counts from zero, so `nth_pred(key, 0)` is equivalent to
+    /// `pred(key, false)`. This takes `O(log n)` time regardless of `n`, since each node records
+    /// the size of its subtree.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    ///
+    /// assert_eq!(map.nth_pred(&30, 0), Some((&20, &2)));
+    /// assert_eq!(map.nth_pred(&30, 2), Some((&0, &0)));
+    /// assert_eq!(map.nth_pred(&35, 2), Some((&10, &1)));
+    /// assert_eq!(map.nth_pred(&30, 3), None);
+    /// ```
+    pub fn nth_pred<Q: ?Sized>(&self, key: &Q, n: usize) -> Option<(&K, &V)>
+        where C: Compare<Q, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        let (rank, _) = node::rank(&self.root, &cmp, key);
+        let rank = n.checked_add(1).and_then(|n| rank.checked_sub(n));
+        rank.and_then(|rank| node::select(&self.root, rank))
+    }
+
+    /// Returns up to `k` entries surrounding the given key, in ascending order.
+    ///
+    /// Starting from the given key, this method alternately takes the next entry before it and
+    /// the next entry after it, beginning with the key's inclusive predecessor, until it has
+    /// taken `k` entries or the map is exhausted. If one side runs out of entries, the remaining
+    /// entries are taken from the other side. This takes `O(k log n)` time.
+    ///
+    /// The given key need not itself be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    ///
+    /// assert_eq!(map.nearest(&45, 3), [(&30, &3), (&40, &4), (&50, &5)]);
+    /// assert_eq!(map.nearest(&50, 2), [(&50, &5), (&60, &6)]);
+    /// assert_eq!(map.nearest(&0, 3), [(&0, &0), (&10, &1), (&20, &2)]);
+    /// assert_eq!(map.nearest(&95, 2), [(&80, &8), (&90, &9)]);
+    /// assert_eq!(map.nearest(&50, 20).len(), 10);
+    /// ```
+    pub fn nearest<Q: ?Sized>(&self, key: &Q, k: usize) -> Vec<(&K, &V)> where C: Compare<Q, K> {
+        let mut before = vec![];
+        let mut after = vec![];
+        let mut pred = self.pred(key, true);
+        let mut succ = self.succ(key, false);
+
+        while before.len() + after.len() < k {
+            let take_pred = match (pred, succ) {
+                (Some(_), Some(_)) => before.len() <= after.len(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            if take_pred {
+                let e = pred.unwrap();
+                before.push(e);
+                pred = self.pred(e.0, false);
+            } else {
+                let e = succ.unwrap();
+                after.push(e);
+                succ = self.succ(e.0, false);
+            }
+        }
+
+        before.reverse();
+        before.extend(after);
+        before
+    }
+
+    /// Returns the entry whose key is closest to the given key under the given distance
+    /// function, or `None` if the map is empty.
+    ///
+    /// Only the given key's inclusive predecessor and strict successor are considered, so the
+    /// distance function should grow as keys move away from the given key in either direction.
+    /// If both candidates are equally close, or their distances are incomparable, the predecessor
+    /// is returned. This takes `O(log n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    /// let dist = |l: &i32, r: &i32| (l - r).abs();
+    ///
+    /// assert_eq!(map.closest(&43, dist), Some((&40, &4)));
+    /// assert_eq!(map.closest(&47, dist), Some((&50, &5)));
+    /// assert_eq!(map.closest(&45, dist), Some((&40, &4)));
+    /// assert_eq!(map.closest(&-5, dist), Some((&0, &0)));
+    /// assert_eq!(map.closest(&500, dist), Some((&90, &9)));
+    ///
+    /// let map: tree::Map<i32, i32> = tree::Map::new();
+    /// assert_eq!(map.closest(&5, dist), None);
+    /// ```
+    pub fn closest<Q: ?Sized, D, F>(&self, key: &Q, mut dist: F) -> Option<(&K, &V)>
+        where C: Compare<Q, K>, D: PartialOrd, F: FnMut(&Q, &K) -> D {
+
+        match (self.pred(key, true), self.succ(key, false)) {
+            (Some(pred), Some(succ)) =>
+                if dist(key, succ.0) < dist(key, pred.0) { Some(succ) } else { Some(pred) },
+            (pred, succ) => pred.or(succ),
+        }
+    }
+
+    /// Returns an iterator over the entries whose ranks lie in the given range, in ascending
+    /// order.
+    ///
+    /// An entry's rank is the number of entries whose keys are less than its key, so that the
+    /// minimum key has rank `0`. Ranks that are not less than the map's length are ignored.
+    /// Positioning the iterator at the range's start takes `O(log n)` time, since each node
+    /// records the size of its subtree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..100).map(|i| (i * 2, i)).collect();
+    ///
+    /// assert_eq!(map.select_range(10..13).collect::<Vec<_>>(),
+    ///            [(&20, &10), (&22, &11), (&24, &12)]);
+    /// assert_eq!(map.select_range(98..110).collect::<Vec<_>>(), [(&196, &98), (&198, &99)]);
+    /// assert_eq!(map.select_range(200..210).count(), 0);
+    /// ```
+    pub fn select_range(&self, range: ops::Range<usize>) -> Select<K, V> {
+        let end = range.end.min(self.len);
+        let start = range.start.min(end);
+        Select(node::Select::new(&self.root, start, end - start))
+    }
+
+    /// Returns an iterator over the map's entries with immutable references to the values.
+    ///
+    /// The iterator yields the entries in ascending order according to the map's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&3, &"c")));
+    /// assert_eq!(it.next(), None);
+    /// ```
+    pub fn iter(&self) -> Iter<K, V> {
+        Iter(node::Iter::new(self.root.as_ref().map(MarkedNode::new), self.len))
+    }
+
+    /// Returns an iterator over the map's entries with immutable references to the values, in
+    /// descending order according to the map's comparator.
+    ///
+    /// This is equivalent to `iter().rev()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (1..4).map(|i| (i, i * 10)).collect();
+    /// assert_eq!(map.iter_rev().collect::<Vec<_>>(), [(&3, &30), (&2, &20), (&1, &10)]);
+    /// ```
+    pub fn iter_rev(&self) -> iter::Rev<Iter<K, V>> { self.iter().rev() }
+
+    /// Returns a chunked iterator over the map's entries, which yields them in ascending order as
+    /// slices of `size` consecutive entries each, except for the last, which may be shorter.
+    ///
+    /// The entries are collected into a buffer that is reused for each chunk, so the returned
+    /// `Chunks` is not an `Iterator`. Instead, call
+    /// [`Chunks::next_chunk`](struct.Chunks.html#method.next_chunk) to advance it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (1..6).map(|i| (i, i * 10)).collect();
+    /// let mut chunks = map.iter_chunks(2);
+    ///
+    /// assert_eq!(chunks.next_chunk(), Some(&[(&1, &10), (&2, &20)][..]));
+    /// assert_eq!(chunks.next_chunk(), Some(&[(&3, &30), (&4, &40)][..]));
+    /// assert_eq!(chunks.next_chunk(), Some(&[(&5, &50)][..]));
+    /// assert_eq!(chunks.next_chunk(), None);
+    /// ```
+    pub fn iter_chunks(&self, size: usize) -> Chunks<K, V> {
+        assert!(size != 0, "chunk size must be non-zero");
+        let buf = Vec::with_capacity(cmp::min(size, self.len));
+        Chunks { iter: self.iter(), buf: buf, size: size }
+    }
+
+    /// Returns an iterator over the map's entries with mutable references to the values.
+    ///
+    /// The iterator yields the entries in ascending order according to the map's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert("b", 2);
+    /// map.insert("a", 1);
+    /// map.insert("c", 3);
+    ///
+    /// let mut i = 1;
+    ///
+    /// for (_, value) in map.iter_mut() {
+    ///     assert_eq!(i, *value);
+    ///     *value *= 2;
+    ///     i += 1;
+    /// }
+    ///
+    /// assert_eq!(map[&"a"], 2);
+    /// assert_eq!(map[&"b"], 4);
+    /// assert_eq!(map[&"c"], 6);
+    /// ```
+    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+        IterMut(node::Iter::new(self.root.as_mut().map(MutMarkedNode::new), self.len))
+    }
+
+    /// Applies the given function to the map's entries in ascending order according to the
+    /// map's comparator.
+    ///
+    /// This traverses the tree directly and is faster than an equivalent loop over
+    /// [`iter`](#method.iter).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut entries = vec![];
+    /// map.for_each(|key, value| entries.push((*key, *value)));
+    /// assert_eq!(entries, [(1, "a"), (2, "b"), (3, "c")]);
+    /// ```
+    pub fn for_each<F>(&self, mut f: F) where F: FnMut(&K, &V) {
+        let _ = node::try_for_each::<_, _, (), _>(&self.root, &mut |key, value| {
+            f(key, value);
+            Ok(())
+        });
+    }
+
+    /// Applies the given function to the map's entries in ascending order according to the
+    /// map's comparator, stopping at and returning the first error it returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut keys = vec![];
+    /// let result = map.try_for_each(|key, value| {
+    ///     if *value == "b" { return Err(*key); }
+    ///     keys.push(*key);
+    ///     Ok(())
+    /// });
+    ///
+    /// assert_eq!(result, Err(2));
+    /// assert_eq!(keys, [1]);
+    /// ```
+    pub fn try_for_each<E, F>(&self, mut f: F) -> Result<(), E>
+        where F: FnMut(&K, &V) -> Result<(), E> {
+
+        node::try_for_each(&self.root, &mut f)
+    }
+
+    /// Applies the given function to the map's entries in ascending order according to the
+    /// map's comparator, with mutable access to the values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, 20);
+    /// map.insert(1, 10);
+    /// map.insert(3, 30);
+    ///
+    /// map.for_each_mut(|key, value| *value += *key);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &11), (&2, &22), (&3, &33)]);
+    /// ```
+    pub fn for_each_mut<F>(&mut self, mut f: F) where F: FnMut(&K, &mut V) {
+        let _ = node::try_for_each_mut::<_, _, (), _>(&mut self.root, &mut |key, value| {
+            f(key, value);
+            Ok(())
+        });
+    }
+
+    /// Applies the given function to the map's entries in ascending order according to the
+    /// map's comparator, with mutable access to the values, stopping at and returning the first
+    /// error it returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, 20);
+    /// map.insert(1, 10);
+    /// map.insert(3, 30);
+    ///
+    /// let result = map.try_for_each_mut(|key, value| {
+    ///     if *key == 3 { return Err("too big"); }
+    ///     *value += 1;
+    ///     Ok(())
+    /// });
+    ///
+    /// assert_eq!(result, Err("too big"));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &11), (&2, &21), (&3, &30)]);
+    /// ```
+    pub fn try_for_each_mut<E, F>(&mut self, mut f: F) -> Result<(), E>
+        where F: FnMut(&K, &mut V) -> Result<(), E> {
+
+        node::try_for_each_mut(&mut self.root, &mut f)
+    }
+
+    /// Checks if the map contains exactly the same keys as the given map, according to this
+    /// map's comparator, ignoring their values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut names = tree::Map::new();
+    /// names.insert(1, "a");
+    /// names.insert(2, "b");
+    ///
+    /// let mut counts = tree::Map::new();
+    /// counts.insert(2, 20);
+    /// counts.insert(1, 10);
+    ///
+    /// assert!(names.eq_keys(&counts));
+    ///
+    /// counts.insert(3, 30);
+    /// assert!(!names.eq_keys(&counts));
+    /// ```
+    pub fn eq_keys<W>(&self, other: &Map<K, W, C>) -> bool {
+        self.len() == other.len() &&
+            self.iter().zip(other.iter()).all(|(l, r)| self.cmp.compares_eq(&l.0, &r.0))
+    }
+
+    /// Checks if no key of the map is also a key of the given map, according to this map's
+    /// comparator, ignoring their values.
+    ///
+    /// If the maps' keys do not overlap, this takes `O(log n)` time. If one map is much smaller
+    /// than the other, this looks up each of its keys in the larger map. Otherwise, it merges the
+    /// maps' keys in `O(m + n)` time. In each case, it returns as soon as a shared key is found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Map<_, _> = vec![(1, "a"), (3, "c")].into_iter().collect();
+    /// let b: tree::Map<_, _> = vec![(2, 20), (4, 40)].into_iter().collect();
+    /// let c: tree::Map<_, _> = vec![(3, 30)].into_iter().collect();
+    ///
+    /// assert!(a.is_disjoint_keys(&b));
+    /// assert!(!a.is_disjoint_keys(&c));
+    /// ```
+    pub fn is_disjoint_keys<W>(&self, other: &Map<K, W, C>) -> bool {
+        match (self.first(), self.last(), other.first(), other.last()) {
+            (Some(first), Some(last), Some(other_first), Some(other_last)) =>
+                if self.cmp.compares_lt(last.0, other_first.0) ||
+                   self.cmp.compares_lt(other_last.0, first.0) {
+                    return true;
+                },
+            _ => return true,
+        }
+
+        if node::is_small(self.len, other.len) {
+            return !self.iter().any(|(key, _)| other.contains_key(key));
+        }
+
+        if node::is_small(other.len, self.len) {
+            return !other.iter().any(|(key, _)| self.contains_key(key));
+        }
+
+        !node::Merge::new(self.iter(), other.iter(), &self.cmp).any(|(_, values)| match values {
+            EitherOrBoth::Both(..) => true,
+            _ => false,
+        })
+    }
+
+    /// Returns an iterator over the keys of the map and of the given map, in ascending order
+    /// according to this map's comparator, with the value associated with each key in either or
+    /// both maps.
+    ///
+    /// The maps are merged in `O(m + n)` time. A key that is in both maps is yielded once, as it
+    /// is stored in this map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::map::EitherOrBoth::{Both, Left, Right};
+    ///
+    /// let names: tree::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let counts: tree::Map<_, _> = vec![(2, 20), (3, 30)].into_iter().collect();
+    ///
+    /// assert_eq!(names.union(&counts).collect::<Vec<_>>(),
+    ///            [(&1, Left(&"a")), (&2, Both(&"b", &20)), (&3, Right(&30))]);
+    /// ```
+    pub fn union<'a, W>(&'a self, other: &'a Map<K, W, C>) -> Union<'a, K, V, W, C> {
+        Union(node::Merge::new(self.iter(), other.iter(), &self.cmp))
+    }
+
+    /// Returns an iterator over the keys that are in both the map and the given map, in ascending
+    /// order according to this map's comparator, with the values associated with each key in both
+    /// maps.
+    ///
+    /// The maps are merged in `O(m + n)` time. Each key is yielded as it is stored in this map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let names: tree::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let counts: tree::Map<_, _> = vec![(2, 20), (3, 30)].into_iter().collect();
+    ///
+    /// assert_eq!(names.intersection(&counts).collect::<Vec<_>>(), [(&2, &"b", &20)]);
+    /// ```
+    pub fn intersection<'a, W>(&'a self, other: &'a Map<K, W, C>)
+        -> Intersection<'a, K, V, W, C> {
+
+        Intersection(node::Merge::new(self.iter(), other.iter(), &self.cmp))
+    }
+
+    /// Returns an iterator over the entries of the map whose keys are not in the given map, in
+    /// ascending order according to this map's comparator.
+    ///
+    /// The maps are merged in `O(m + n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let names: tree::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let counts: tree::Map<_, _> = vec![(2, 20), (3, 30)].into_iter().collect();
+    ///
+    /// assert_eq!(names.difference(&counts).collect::<Vec<_>>(), [(&1, &"a")]);
+    /// assert_eq!(counts.difference(&names).collect::<Vec<_>>(), [(&3, &30)]);
+    /// ```
+    pub fn difference<'a, W>(&'a self, other: &'a Map<K, W, C>) -> Difference<'a, K, V, W, C> {
+        Difference(node::Merge::new(self.iter(), other.iter(), &self.cmp))
+    }
+
+    /// Returns a value whose `Debug` implementation shows the structure of the map's underlying
+    /// tree, including the level of each node, rather than just its entries.
+    ///
+    /// As with the map's own `Debug` implementation, the alternate format (`{:#?}`) prints one
+    /// field per line, indented by depth.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(1, "a");
+    ///
+    /// assert_eq!(format!("{:?}", map), r#"{1: "a"}"#);
+    /// assert_eq!(format!("{:?}", map.debug_tree()),
+    ///            r#"Some(Node { key: 1, value: "a", level: 1, left: None, right: None })"#);
+    /// ```
+    pub fn debug_tree(&self) -> DebugTree<K, V> { DebugTree(&self.root) }
+
+    /// Returns the height of the map's underlying tree, which is the number of nodes on the
+    /// longest path from its root to a leaf, or `0` if the map is empty.
+    ///
+    /// This is the maximum number of keys that a lookup compares against. It runs in `O(n)`
+    /// time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.height(), 0);
+    ///
+    /// for i in 0..7 { map.insert(i, ()); }
+    /// assert!(map.height() >= 3);
+    /// assert!(map.height() <= 6);
+    /// ```
+    pub fn height(&self) -> usize { node::height(&self.root) }
+
+    /// Returns the number of nodes on the shortest path from the root of the map's underlying
+    /// tree to a leaf, or `0` if the map is empty.
+    ///
+    /// Together with [`height`](#method.height), this measures how far the tree is from being
+    /// perfectly balanced. It runs in `O(n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.min_depth(), 0);
+    ///
+    /// for i in 0..7 { map.insert(i, ()); }
+    /// assert!(map.min_depth() >= 1);
+    /// assert!(map.min_depth() <= map.height());
+    /// ```
+    pub fn min_depth(&self) -> usize { node::min_depth(&self.root) }
+
+    /// Returns the average depth of the nodes in the map's underlying tree, where the root has
+    /// depth `1`, or `0.0` if the map is empty.
+    ///
+    /// This is the average number of keys that a lookup of a key in the map compares against. It
+    /// runs in `O(n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// assert_eq!(map.average_depth(), 0.0);
+    ///
+    /// map.insert(1, ());
+    /// assert_eq!(map.average_depth(), 1.0);
+    ///
+    /// for i in 0..100 { map.insert(i, ()); }
+    /// assert!(map.average_depth() <= map.height() as f64);
+    /// ```
+    pub fn average_depth(&self) -> f64 {
+        if self.len == 0 { return 0.0; }
+        node::total_depth(&self.root, 1) as f64 / self.len as f64
+    }
+
+    /// Returns a snapshot of the map's instrumentation counters.
+    ///
+    /// The counters record the comparisons, rotations, and allocations performed by the map's
+    /// operations since it was created or its counters were last reset, including insertions and
+    /// removals performed through entries. Only available with the `stats` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// for i in 0..10 { map.insert(i, ()); }
+    /// map.get(&5);
+    ///
+    /// let stats = map.stats();
+    /// assert_eq!(stats.allocations, 10);
+    /// assert!(stats.rotations > 0);
+    /// assert!(stats.comparisons >= stats.max_comparisons);
+    /// assert!(stats.max_comparisons as usize <= map.height());
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats { self.counters.get() }
+
+    /// Returns an iterator over the nodes of the map's underlying tree, yielding the depth, key,
+    /// and level of each.
+    ///
+    /// The iterator yields the nodes in pre-order, i.e. each node before the nodes in its left
+    /// subtree and those before the nodes in its right subtree. The root has depth `1`, and the
+    /// nodes at the bottom of the tree have level `1`. Only available with the `stats` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// assert_eq!(map.remove_succ(&3, false), None);
-    /// assert!(map.contains_key(&3));
+    /// assert_eq!(map.nodes().collect::<Vec<_>>(), [(1, &2, 2), (2, &1, 1), (2, &3, 1)]);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn nodes(&self) -> Nodes<K, V> { Nodes(node::Nodes::new(&self.root, self.len)) }
+
+    /// Resets the map's instrumentation counters to zero.
+    ///
+    /// Only available with the `stats` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(1, "a");
+    /// assert_eq!(map.stats().allocations, 1);
+    ///
+    /// map.reset_stats();
+    /// assert_eq!(map.stats(), tree::Stats::default());
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) { self.counters.reset(); }
+
+    /// Checks that the map's underlying tree satisfies its invariants.
+    ///
+    /// This verifies that the tree's keys are in strictly ascending order according to the map's
+    /// comparator, that the levels of its nodes satisfy the rules of an Andersson tree, and that
+    /// the map's length matches the number of entries in the tree. It runs in `O(n)` time.
     ///
-    /// assert_eq!(map.remove_succ(&2, false), Some((3, "c")));
-    /// assert!(!map.contains_key(&3));
+    /// A map that is only modified through its public API always satisfies these invariants,
+    /// unless its comparator does not implement a total order or a key's ordering relative to
+    /// another key changes while it is in the map.
+    ///
+    /// # Examples
     ///
-    /// assert_eq!(map.remove_succ(&2, true), Some((2, "b")));
-    /// assert!(!map.contains_key(&2));
     /// ```
-    pub fn remove_succ<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Option<(K, V)>
-        where C: Compare<Q, K> {
+    /// use std::cell::Cell;
+    /// use tree::map::InvariantError;
+    ///
+    /// let reversed = Cell::new(false);
+    /// let cmp = |l: &u32, r: &u32| if reversed.get() { r.cmp(l) } else { l.cmp(r) };
+    ///
+    /// let mut map = tree::Map::with_cmp(cmp);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// assert_eq!(map.validate(), Ok(()));
+    ///
+    /// reversed.set(true);
+    /// assert_eq!(map.validate(), Err(InvariantError::Order));
+    /// ```
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        if node::check::validate(&self.root, &self.cmp)? == self.len {
+            Ok(())
+        } else {
+            Err(InvariantError::Len)
+        }
+    }
 
-        Max::closest(&mut self.root, PathBuilder::default(), &self.cmp, key, inclusive)
-            .remove(&mut self.len)
+    /// Returns the structure of the map's underlying tree, or `None` if the map is empty.
+    ///
+    /// This is the inverse of [`from_shape`](#method.from_shape).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::map::Shape;
+    ///
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// assert_eq!(map.to_shape(), Some(Shape::leaf(1, "a", 1).with_right(Shape::leaf(2, "b", 1))));
+    /// ```
+    pub fn to_shape(&self) -> Option<Shape<K, V>> where K: Clone, V: Clone {
+        node::to_shape(&self.root)
     }
 
-    /// Returns the entry corresponding to the successor of the given key.
+    /// Creates a map ordered according to the given comparator whose underlying tree has exactly
+    /// the given structure.
     ///
-    /// If `inclusive` is `false`, this method returns the entry corresponding to the smallest key
-    /// that is strictly greater than the given key. If `inclusive` is `true`, this method returns
-    /// the entry corresponding to the smallest key that is greater than or equal to the given key.
+    /// This is intended for tests that need a tree of a particular shape, e.g. to exercise a
+    /// specific rebalancing case. Returns an error if the tree would violate any of the
+    /// invariants checked by [`validate`](#method.validate).
     ///
-    /// The given key need not itself be present in the map.
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::Map;
+    /// use tree::cmp::Natural;
+    /// use tree::map::{InvariantError, Shape};
+    ///
+    /// let shape = Shape::leaf(2, "b", 2)
+    ///     .with_left(Shape::leaf(1, "a", 1))
+    ///     .with_right(Shape::leaf(3, "c", 1));
+    ///
+    /// let map = Map::from_shape(Natural::default(), Some(shape.clone())).unwrap();
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b"), (&3, &"c")]);
+    /// assert_eq!(map.to_shape(), Some(shape));
+    ///
+    /// // a left child may not have the same level as its parent
+    /// let skewed = Shape::leaf(2, "b", 1).with_left(Shape::leaf(1, "a", 1));
+    /// assert_eq!(Map::from_shape(Natural::default(), Some(skewed)).err(),
+    ///            Some(InvariantError::Level));
+    /// ```
+    pub fn from_shape(cmp: C, shape: Option<Shape<K, V>>) -> Result<Self, InvariantError> {
+        let root = node::from_shape(shape);
+        let len = node::check::validate(&root, &cmp)?;
+        Ok(Map { root: root, len: len, cmp: cmp, counters: Counters::default() })
+    }
+
+    /// Returns a view of the map in which its entries are in descending order according to the
+    /// map's comparator.
+    ///
+    /// The view supports the map's read-only queries, with `first` and `last`, `pred` and `succ`,
+    /// and the iteration order reversed. No comparator or tree needs to be rebuilt.
     ///
     /// # Examples
     ///
@@ -726,60 +2407,307 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     /// map.insert(1, "a");
     /// map.insert(3, "c");
     ///
-    /// assert!(map.succ_entry(&3, false).is_none());
+    /// let desc = map.descending();
+    ///
+    /// assert_eq!(desc.first(), Some((&3, &"c")));
+    /// assert_eq!(desc.succ(&3, false), Some((&2, &"b")));
+    /// assert_eq!(desc.pred(&0, false), Some((&1, &"a")));
+    /// assert_eq!(desc.iter().collect::<Vec<_>>(), [(&3, &"c"), (&2, &"b"), (&1, &"a")]);
+    /// ```
+    pub fn descending(&self) -> Descending<K, V, C> { Descending(self) }
+
+    /// Begins a transaction on the map.
+    ///
+    /// The returned guard supports inserting, removing, and updating entries, recording how to
+    /// undo each modification. Calling [`commit`](struct.Transaction.html#method.commit) keeps
+    /// the modifications, while calling [`rollback`](struct.Transaction.html#method.rollback) or
+    /// dropping the guard without committing undoes them in reverse order, restoring the map's
+    /// original entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(1, "a");
     ///
     /// {
-    ///     let mut e = map.succ_entry(&0, true).unwrap();
-    ///     assert_eq!(*e.key(), 1);
-    ///     assert_eq!(e.insert("aa"), "a");
+    ///     let mut txn = map.transaction();
+    ///     txn.insert(2, "b");
+    ///     txn.remove(&1);
+    ///     assert_eq!(txn.iter().collect::<Vec<_>>(), [(&2, &"b")]);
+    ///     txn.rollback();
     /// }
     ///
-    /// assert_eq!(map[&1], "aa");
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a")]);
     ///
     /// {
-    ///     let e = map.succ_entry(&1, false).unwrap();
-    ///     assert_eq!(e.remove(), (2, "b"));
+    ///     let mut txn = map.transaction();
+    ///     txn.insert(2, "b");
+    ///     txn.remove(&1);
+    ///     txn.commit();
     /// }
     ///
-    /// assert!(!map.contains_key(&2));
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&2, &"b")]);
     /// ```
-    pub fn succ_entry<Q: ?Sized>(&mut self, key: &Q, inclusive: bool)
-        -> Option<OccupiedEntry<K, V>> where C: Compare<Q, K> {
+    pub fn transaction(&mut self) -> Transaction<K, V, C> where K: Clone, V: Clone {
+        Transaction { map: self, undo: vec![] }
+    }
 
-        Max::closest(&mut self.root, PathBuilder::default(), &self.cmp, key, inclusive)
-            .into_occupied_entry(&mut self.len)
+    // Build a map from entries that are known to be in strictly ascending order according to
+    // the given comparator
+    fn from_sorted(cmp: C, entries: Vec<(K, V)>) -> Self {
+        let len = entries.len();
+        event!("rebuild: len={}", len);
+        let root = node::from_sorted(&mut entries.into_iter(), len);
+        Map { root: root, len: len, cmp: cmp, counters: Counters::default() }
     }
 
-    /// Returns an iterator over the map's entries with immutable references to the values.
+    #[cfg(test)]
+    pub fn root(&self) -> &node::Link<K, V> { &self.root }
+
+    #[cfg(test)]
+    pub fn root_mut(&mut self) -> &mut node::Link<K, V> { &mut self.root }
+
+    // With the `debug-validate` feature, verify that the comparator agrees with the tree's
+    // structure along the search path for the given key
+    #[cfg(feature = "debug-validate")]
+    fn check_path<Q: ?Sized>(&self, key: &Q) where C: Compare<Q, K> {
+        node::check::path(&self.root, &self.cmp, key);
+    }
+
+    #[cfg(not(feature = "debug-validate"))]
+    fn check_path<Q: ?Sized>(&self, _key: &Q) {}
+
+    // With the `debug-validate` feature, verify that the whole tree is sorted and spot-check the
+    // comparator's transitivity whenever its length reaches a power of two, which amortizes the
+    // cost of the check to `O(1)` per insertion
+    #[cfg(feature = "debug-validate")]
+    fn check_sorted(&self) {
+        if self.len.is_power_of_two() { node::check::sorted(&self.root, self.len, &self.cmp); }
+    }
+
+    #[cfg(not(feature = "debug-validate"))]
+    fn check_sorted(&self) {}
+}
+
+#[cfg(feature = "range")]
+impl<K, V, C> Map<K, V, C> where C: Compare<K> {
+    /// Returns an iterator that consumes the map, yielding only those entries whose keys lie in
+    /// the given range.
     ///
     /// The iterator yields the entries in ascending order according to the map's comparator.
     ///
     /// # Examples
     ///
     /// ```
+    /// use std::collections::Bound::{Excluded, Unbounded};
+    ///
     /// let mut map = tree::Map::new();
     ///
-    /// map.insert(2, "b");
-    /// map.insert(1, "a");
-    /// map.insert(3, "c");
+    /// map.insert("b", 2);
+    /// map.insert("a", 1);
+    /// map.insert("c", 3);
     ///
-    /// let mut it = map.iter();
-    /// assert_eq!(it.next(), Some((&1, &"a")));
-    /// assert_eq!(it.next(), Some((&2, &"b")));
-    /// assert_eq!(it.next(), Some((&3, &"c")));
-    /// assert_eq!(it.next(), None);
+    /// assert_eq!(map.into_range::<_, &str>(Excluded(&"a"), Unbounded).collect::<Vec<_>>(),
+    ///     [("b", 2), ("c", 3)]);
     /// ```
-    pub fn iter(&self) -> Iter<K, V> {
-        Iter(node::Iter::new(self.root.as_ref().map(MarkedNode::new), self.len))
+    pub fn into_range<Min: ?Sized, Max: ?Sized>(mut self, min: Bound<&Min>, max: Bound<&Max>)
+        -> IntoRange<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        IntoRange(node::Range::new(self.root.take(), self.len, &cmp, min, max))
     }
 
-    /// Returns an iterator over the map's entries with mutable references to the values.
+    /// Returns an iterator over the map's entries whose keys lie in the given range with immutable
+    /// references to the values.
+    ///
+    /// The iterator yields the entries in ascending order according to the map's comparator. If
+    /// `min` lies after `max`, or if both are excluded and equal, the iterator is empty. Because
+    /// the bounds may be of different types, they are not compared with each other; use
+    /// [`checked_range`](#method.checked_range) to reject such ranges instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Excluded, Unbounded};
+    ///
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert("b", 2);
+    /// map.insert("a", 1);
+    /// map.insert("c", 3);
+    ///
+    /// assert_eq!(map.range::<&str, &str>(Unbounded, Unbounded).collect::<Vec<_>>(),
+    ///     [(&"a", &1), (&"b", &2), (&"c", &3)]);
+    /// assert_eq!(map.range(Excluded(&"a"), Included(&"f")).collect::<Vec<_>>(),
+    ///     [(&"b", &2), (&"c", &3)]);
+    /// assert_eq!(map.range(Included(&"a"), Excluded(&"b")).collect::<Vec<_>>(),
+    ///     [(&"a", &1)]);
+    /// ```
+    pub fn range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> Range<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        Range(node::Range::new(self.root.as_ref().map(MarkedNode::new), self.len, &cmp, min,
+            max))
+    }
+
+    /// Returns an iterator over the map's entries whose keys lie in the given range with immutable
+    /// references to the values, or `None` if the range is invalid.
+    ///
+    /// The range is invalid if `min` is greater than `max` according to the map's comparator, or
+    /// if they are equal and both are excluded. The bounds are compared with each other before
+    /// the tree is searched, matching the conditions under which `BTreeMap::range` panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Excluded, Unbounded};
+    ///
+    /// let map: tree::Map<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    ///
+    /// assert_eq!(map.checked_range(Included(&1), Excluded(&3)).unwrap().collect::<Vec<_>>(),
+    ///     [(&1, &10), (&2, &20)]);
+    /// assert_eq!(map.checked_range(Included(&2), Included(&2)).unwrap().count(), 1);
+    /// assert!(map.checked_range(Included(&3), Excluded(&1)).is_none());
+    /// assert!(map.checked_range(Excluded(&2), Excluded(&2)).is_none());
+    /// assert_eq!(map.checked_range(Excluded(&2), Included(&2)).unwrap().count(), 0);
+    /// assert!(map.checked_range(Unbounded, Excluded(&9)).is_some());
+    /// ```
+    pub fn checked_range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> Option<Range<K, V>> where C: Compare<Min, K> + Compare<Max, K> + Compare<Min, Max> {
+
+        let valid = match (&min, &max) {
+            (&Bound::Excluded(min), &Bound::Excluded(max)) => self.cmp.compares_lt(min, max),
+            (&Bound::Included(min), &Bound::Included(max)) |
+            (&Bound::Included(min), &Bound::Excluded(max)) |
+            (&Bound::Excluded(min), &Bound::Included(max)) => self.cmp.compares_le(min, max),
+            _ => true,
+        };
+
+        if valid { Some(self.range(min, max)) } else { None }
+    }
+
+    /// Returns an iterator over the map's entries whose keys lie in the given range with immutable
+    /// references to the values, in descending order according to the map's comparator.
+    ///
+    /// This is equivalent to `range(min, max).rev()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Excluded};
+    ///
+    /// let map: tree::Map<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    ///
+    /// let it = map.range_rev(Included(&1), Excluded(&4));
+    /// assert_eq!(it.collect::<Vec<_>>(), [(&3, &30), (&2, &20), (&1, &10)]);
+    /// ```
+    pub fn range_rev<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> iter::Rev<Range<K, V>> where C: Compare<Min, K> + Compare<Max, K> {
+
+        self.range(min, max).rev()
+    }
+
+    /// Returns an iterator over the map's entries whose keys are greater than or equal to the
+    /// given key with immutable references to the values.
+    ///
+    /// This is equivalent to `range(Included(min), Unbounded)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    /// assert_eq!(map.range_from(&3).collect::<Vec<_>>(), [(&3, &30), (&4, &40)]);
+    /// ```
+    pub fn range_from<Min: ?Sized>(&self, min: &Min) -> Range<K, V> where C: Compare<Min, K> {
+        self.range::<Min, Min>(Bound::Included(min), Bound::Unbounded)
+    }
+
+    /// Returns an iterator over the map's entries whose keys are less than the given key with
+    /// immutable references to the values.
+    ///
+    /// This is equivalent to `range(Unbounded, Excluded(max))`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    /// assert_eq!(map.range_to(&2).collect::<Vec<_>>(), [(&0, &0), (&1, &10)]);
+    /// ```
+    pub fn range_to<Max: ?Sized>(&self, max: &Max) -> Range<K, V> where C: Compare<Max, K> {
+        self.range::<Max, Max>(Bound::Unbounded, Bound::Excluded(max))
+    }
+
+    /// Returns an iterator over the map's entries whose keys are less than or equal to the given
+    /// key with immutable references to the values.
+    ///
+    /// This is equivalent to `range(Unbounded, Included(max))`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    /// assert_eq!(map.range_to_inclusive(&1).collect::<Vec<_>>(), [(&0, &0), (&1, &10)]);
+    /// ```
+    pub fn range_to_inclusive<Max: ?Sized>(&self, max: &Max) -> Range<K, V>
+        where C: Compare<Max, K> {
+
+        self.range::<Max, Max>(Bound::Unbounded, Bound::Included(max))
+    }
+
+    /// Returns an iterator over the map's entries whose keys compare equal to the given key with
+    /// immutable references to the values.
+    ///
+    /// This is equivalent to `range(Included(key), Included(key))`. Since the map's keys are
+    /// unique, it yields more than one entry only if the comparator compares the given key more
+    /// coarsely than the map's keys, e.g. only by a prefix or field of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::Compare;
+    /// use std::cmp::Ordering;
+    ///
+    /// // orders `(user, seq)` keys lexicographically, and compares a `user` with their first part
+    /// struct ByUser;
+    ///
+    /// impl Compare<(u32, u32)> for ByUser {
+    ///     fn compare(&self, l: &(u32, u32), r: &(u32, u32)) -> Ordering { l.cmp(r) }
+    /// }
+    ///
+    /// impl Compare<u32, (u32, u32)> for ByUser {
+    ///     fn compare(&self, l: &u32, r: &(u32, u32)) -> Ordering { l.cmp(&r.0) }
+    /// }
+    ///
+    /// let mut map = tree::Map::with_cmp(ByUser);
+    ///
+    /// map.insert((1, 1), "a");
+    /// map.insert((2, 1), "b");
+    /// map.insert((2, 2), "c");
+    /// map.insert((3, 1), "d");
+    ///
+    /// assert_eq!(map.equal_range(&2).collect::<Vec<_>>(), [(&(2, 1), &"b"), (&(2, 2), &"c")]);
+    /// assert_eq!(map.equal_range(&4).count(), 0);
+    /// # }
+    /// ```
+    pub fn equal_range<Q: ?Sized>(&self, key: &Q) -> Range<K, V> where C: Compare<Q, K> {
+        self.range(Bound::Included(key), Bound::Included(key))
+    }
+
+    /// Returns an iterator over the map's entries whose keys lie in the given range with mutable
+    /// references to the values.
     ///
     /// The iterator yields the entries in ascending order according to the map's comparator.
     ///
     /// # Examples
     ///
     /// ```
+    /// use std::collections::Bound;
+    ///
     /// let mut map = tree::Map::new();
     ///
     /// map.insert("b", 2);
@@ -788,7 +2716,7 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     ///
     /// let mut i = 1;
     ///
-    /// for (_, value) in map.iter_mut() {
+    /// for (_, value) in map.range_mut::<&str, _>(Bound::Unbounded, Bound::Excluded(&"c")) {
     ///     assert_eq!(i, *value);
     ///     *value *= 2;
     ///     i += 1;
@@ -796,119 +2724,197 @@ impl<K, V, C> Map<K, V, C> where C: Compare<K> {
     ///
     /// assert_eq!(map[&"a"], 2);
     /// assert_eq!(map[&"b"], 4);
-    /// assert_eq!(map[&"c"], 6);
+    /// assert_eq!(map[&"c"], 3);
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
-        IterMut(node::Iter::new(self.root.as_mut().map(MutMarkedNode::new), self.len))
+    pub fn range_mut<Min: ?Sized, Max: ?Sized>(&mut self, min: Bound<&Min>, max: Bound<&Max>)
+        -> RangeMut<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+
+        let cmp = self.counters.count(&self.cmp);
+        RangeMut(node::Range::new(self.root.as_mut().map(MutMarkedNode::new), self.len, &cmp,
+            min, max))
     }
 
-    #[cfg(test)]
-    pub fn root(&self) -> &node::Link<K, V> { &self.root }
-}
+    /// Returns a cursor over the map's entries whose keys lie in the given range, through which
+    /// each entry can be modified or removed as the scan continues.
+    ///
+    /// The cursor visits the entries in ascending order according to the map's comparator, and
+    /// the bounds are interpreted as in [`range`](#method.range). Since the map's subtree sizes
+    /// locate each entry by its rank, the cursor remains valid across removals, and each step
+    /// takes `O(log n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Excluded, Included};
+    ///
+    /// let mut map: tree::Map<_, _> = (0..8).map(|i| (i, i * 10)).collect();
+    ///
+    /// {
+    ///     let mut entries = map.range_entries(Included(&2), Excluded(&6));
+    ///
+    ///     while let Some(mut entry) = entries.next_entry() {
+    ///         if entry.key() % 2 == 0 { entry.remove(); } else { *entry.get_mut() += 1; }
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(),
+    ///            [(&0, &0), (&1, &10), (&3, &31), (&5, &51), (&6, &60), (&7, &70)]);
+    /// ```
+    pub fn range_entries<Min: ?Sized, Max: ?Sized>(&mut self, min: Bound<&Min>, max: Bound<&Max>)
+        -> RangeEntries<K, V> where C: Compare<Min, K> + Compare<Max, K> {
 
-#[cfg(feature = "range")]
-impl<K, V, C> Map<K, V, C> where C: Compare<K> {
-    /// Returns an iterator that consumes the map, yielding only those entries whose keys lie in
-    /// the given range.
+        let (start, end) = {
+            let cmp = self.counters.count(&self.cmp);
+
+            let start = match min {
+                Bound::Unbounded => 0,
+                Bound::Included(key) => node::bound_rank(&self.root, &cmp, key, false),
+                Bound::Excluded(key) => node::bound_rank(&self.root, &cmp, key, true),
+            };
+
+            let end = match max {
+                Bound::Unbounded => self.len,
+                Bound::Included(key) => node::bound_rank(&self.root, &cmp, key, true),
+                Bound::Excluded(key) => node::bound_rank(&self.root, &cmp, key, false),
+            };
+
+            (start, cmp::max(start, end))
+        };
+
+        RangeEntries {
+            root: &mut self.root,
+            len: &mut self.len,
+            counters: &self.counters,
+            rank: start,
+            end: end,
+        }
+    }
+
+    /// Folds the map's entries whose keys lie in the given range into an accumulator, in
+    /// ascending order according to the map's comparator.
     ///
-    /// The iterator yields the entries in ascending order according to the map's comparator.
+    /// This descends directly to the entries in the range, and is faster than an equivalent
+    /// fold over [`range`](#method.range).
     ///
     /// # Examples
     ///
     /// ```
-    /// # #![feature(collections_bound)]
-    /// # extern crate tree;
-    /// # fn main() {
-    /// use std::collections::Bound::{Excluded, Unbounded};
+    /// use std::collections::Bound::{Included, Excluded};
     ///
     /// let mut map = tree::Map::new();
     ///
-    /// map.insert("b", 2);
-    /// map.insert("a", 1);
-    /// map.insert("c", 3);
+    /// for i in 0..10 { map.insert(i, i * 10); }
     ///
-    /// assert_eq!(map.into_range(Excluded(&"a"), Unbounded).collect::<Vec<_>>(),
-    ///     [("b", 2), ("c", 3)]);
-    /// # }
+    /// let sum = map.fold_range(Included(&2), Excluded(&5), 0, |sum, _, value| sum + value);
+    /// assert_eq!(sum, 20 + 30 + 40);
     /// ```
-    pub fn into_range<Min: ?Sized, Max: ?Sized>(mut self, min: Bound<&Min>, max: Bound<&Max>)
-        -> IntoRange<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+    pub fn fold_range<Min: ?Sized, Max: ?Sized, B, F>(&self, min: Bound<&Min>, max: Bound<&Max>,
+                                                      init: B, mut f: F) -> B
+        where C: Compare<Min, K> + Compare<Max, K>, F: FnMut(B, &K, &V) -> B {
 
-        IntoRange(node::Range::new(self.root.take(), self.len, &self.cmp, min, max))
+        let cmp = self.counters.count(&self.cmp);
+        node::fold_range(&self.root, &cmp, min, max, init, &mut f)
     }
+}
 
-    /// Returns an iterator over the map's entries whose keys lie in the given range with immutable
-    /// references to the values.
+#[cfg(feature = "range")]
+impl<K, V> Map<K, V> where K: Ord {
+    /// Returns an iterator over the map's entries whose keys start with the given prefix with
+    /// immutable references to the values.
     ///
-    /// The iterator yields the entries in ascending order according to the map's comparator.
+    /// This is equivalent to `range(Included(prefix), Excluded(end))`, where `end` is the least
+    /// string greater than every string that starts with the prefix, or to
+    /// `range(Included(prefix), Unbounded)` if there is no such string. It is only available for
+    /// maps ordered by the natural order of their keys, since the keys sharing a prefix need not
+    /// be adjacent under other orders.
     ///
     /// # Examples
     ///
     /// ```
-    /// # #![feature(collections_bound)]
-    /// # extern crate tree;
-    /// # fn main() {
-    /// use std::collections::Bound::{Included, Excluded, Unbounded};
-    ///
     /// let mut map = tree::Map::new();
     ///
-    /// map.insert("b", 2);
-    /// map.insert("a", 1);
-    /// map.insert("c", 3);
+    /// map.insert("app", 1);
+    /// map.insert("apple", 2);
+    /// map.insert("apply", 3);
+    /// map.insert("apq", 4);
+    /// map.insert("ap\u{10ffff}", 5);
     ///
-    /// assert_eq!(map.range(Unbounded, Unbounded).collect::<Vec<_>>(),
-    ///     [(&"a", &1), (&"b", &2), (&"c", &3)]);
-    /// assert_eq!(map.range(Excluded(&"a"), Included(&"f")).collect::<Vec<_>>(),
-    ///     [(&"b", &2), (&"c", &3)]);
-    /// assert_eq!(map.range(Included(&"a"), Excluded(&"b")).collect::<Vec<_>>(),
-    ///     [(&"a", &1)]);
-    /// # }
+    /// assert_eq!(map.range_prefix("appl").collect::<Vec<_>>(), [(&"apple", &2), (&"apply", &3)]);
+    /// assert_eq!(map.range_prefix("apq").collect::<Vec<_>>(), [(&"apq", &4)]);
+    /// assert_eq!(map.range_prefix("ap\u{10ffff}").collect::<Vec<_>>(), [(&"ap\u{10ffff}", &5)]);
+    /// assert_eq!(map.range_prefix("").count(), 5);
     /// ```
-    pub fn range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
-        -> Range<K, V> where C: Compare<Min, K> + Compare<Max, K> {
-
-        Range(node::Range::new(self.root.as_ref().map(MarkedNode::new), self.len, &self.cmp, min,
-            max))
+    pub fn range_prefix(&self, prefix: &str) -> Range<K, V> where K: Borrow<str> {
+        let end = str_prefix_end(prefix);
+        let max = end.as_ref().map_or(Bound::Unbounded, |end| Bound::Excluded(&**end));
+        self.range(Bound::Included(prefix), max)
     }
 
-    /// Returns an iterator over the map's entries whose keys lie in the given range with mutable
-    /// references to the values.
+    /// Returns an iterator over the map's entries whose keys start with the given byte prefix
+    /// with immutable references to the values.
     ///
-    /// The iterator yields the entries in ascending order according to the map's comparator.
+    /// This is the byte-slice analogue of [`range_prefix`](#method.range_prefix).
     ///
     /// # Examples
     ///
     /// ```
-    /// # #![feature(collections_bound)]
-    /// # extern crate tree;
-    /// # fn main() {
-    /// use std::collections::Bound;
-    ///
     /// let mut map = tree::Map::new();
     ///
-    /// map.insert("b", 2);
-    /// map.insert("a", 1);
-    /// map.insert("c", 3);
-    ///
-    /// let mut i = 1;
-    ///
-    /// for (_, value) in map.range_mut(Bound::Unbounded, Bound::Excluded(&"c")) {
-    ///     assert_eq!(i, *value);
-    ///     *value *= 2;
-    ///     i += 1;
-    /// }
+    /// map.insert(vec![1, 0xff], "a");
+    /// map.insert(vec![1, 0xff, 0], "b");
+    /// map.insert(vec![2], "c");
+    /// map.insert(vec![2, 0], "d");
     ///
-    /// assert_eq!(map[&"a"], 2);
-    /// assert_eq!(map[&"b"], 4);
-    /// assert_eq!(map[&"c"], 3);
-    /// # }
+    /// assert_eq!(map.range_prefix_bytes(&[1, 0xff]).map(|e| *e.1).collect::<Vec<_>>(),
+    ///     ["a", "b"]);
+    /// assert_eq!(map.range_prefix_bytes(&[2]).map(|e| *e.1).collect::<Vec<_>>(), ["c", "d"]);
+    /// assert_eq!(map.range_prefix_bytes(&[0xff]).count(), 0);
     /// ```
-    pub fn range_mut<Min: ?Sized, Max: ?Sized>(&mut self, min: Bound<&Min>, max: Bound<&Max>)
-        -> RangeMut<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+    pub fn range_prefix_bytes(&self, prefix: &[u8]) -> Range<K, V> where K: Borrow<[u8]> {
+        let end = bytes_prefix_end(prefix);
+        let max = end.as_ref().map_or(Bound::Unbounded, |end| Bound::Excluded(&**end));
+        self.range(Bound::Included(prefix), max)
+    }
+}
 
-        RangeMut(node::Range::new(self.root.as_mut().map(MutMarkedNode::new), self.len, &self.cmp,
-            min, max))
+// Returns the least string greater than every string that starts with the given prefix, or `None`
+// if the prefix consists only of `char::MAX`s
+//
+// This replaces the prefix's last character with the next valid `char`, dropping any trailing
+// `char::MAX`s first.
+#[cfg(feature = "range")]
+fn str_prefix_end(prefix: &str) -> Option<String> {
+    let mut end = prefix.to_owned();
+
+    while let Some(c) = end.pop() {
+        let next = match c {
+            '\u{d7ff}' => Some('\u{e000}'),
+            c => ::std::char::from_u32(c as u32 + 1),
+        };
+
+        if let Some(next) = next {
+            end.push(next);
+            return Some(end);
+        }
+    }
+
+    None
+}
+
+// Returns the least byte string greater than every byte string that starts with the given prefix,
+// or `None` if the prefix consists only of `0xff`s
+#[cfg(feature = "range")]
+fn bytes_prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+
+    while let Some(b) = end.pop() {
+        if b < 0xff {
+            end.push(b + 1);
+            return Some(end);
+        }
     }
+
+    None
 }
 
 impl<K, V, C> Debug for Map<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
@@ -927,6 +2933,12 @@ impl<K, V, C> Extend<(K, V)> for Map<K, V, C> where C: Compare<K> {
     }
 }
 
+impl<'a, K, V, C> Extend<(&'a K, &'a V)> for Map<K, V, C> where K: Copy, V: Copy, C: Compare<K> {
+    fn extend<I: IntoIterator<Item=(&'a K, &'a V)>>(&mut self, it: I) {
+        self.extend(it.into_iter().map(|(&k, &v)| (k, v)));
+    }
+}
+
 impl<K, V, C> iter::FromIterator<(K, V)> for Map<K, V, C>
     where C: Compare<K> + Default {
 
@@ -1042,6 +3054,120 @@ impl<K, V, C> Ord for Map<K, V, C> where V: Ord, C: Compare<K> {
     }
 }
 
+/// A builder that constructs a map from entries supplied in ascending order of their keys.
+///
+/// Each call to [`push`](#method.push) compares the key only with the previously pushed key and
+/// takes amortized `O(1)` time, and [`build`](#method.build) takes `O(log^2 n)` time, so building
+/// a map of `n` entries takes `O(n)` time overall. Unlike
+/// [`Map::from_parts`](struct.Map.html#method.from_parts), the builder never buffers the entries
+/// outside of the tree's nodes, which suits inputs that are streamed in sorted order.
+///
+/// # Examples
+///
+/// ```
+/// let mut builder = tree::map::Builder::new();
+///
+/// for i in 0..100 { builder.push(i, i * 10); }
+///
+/// let map = builder.build();
+/// assert_eq!(map.len(), 100);
+/// assert_eq!(map.get(&42), Some(&420));
+/// ```
+pub struct Builder<K, V, C = Natural<K>> where C: Compare<K> {
+    inner: node::Builder<K, V>,
+    cmp: C,
+}
+
+impl<K, V> Builder<K, V> where K: Ord {
+    /// Creates a builder for a map ordered according to the natural order of its keys.
+    pub fn new() -> Self { Builder::with_cmp(Natural::default()) }
+}
+
+impl<K, V, C> Builder<K, V, C> where C: Compare<K> {
+    /// Creates a builder for a map ordered according to the given comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut builder = tree::map::Builder::with_cmp(natural().rev());
+    ///
+    /// builder.push(3, "c");
+    /// builder.push(2, "b");
+    /// builder.push(1, "a");
+    ///
+    /// assert_eq!(builder.build().iter().next(), Some((&3, &"c")));
+    /// # }
+    /// ```
+    pub fn with_cmp(cmp: C) -> Self { Builder { inner: node::Builder::new(), cmp: cmp } }
+
+    /// Checks if no entries have been pushed.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Returns the number of entries that have been pushed.
+    pub fn len(&self) -> usize { self.inner.len() }
+
+    /// Returns a reference to the most recently pushed key, or `None` if no entries have been
+    /// pushed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut builder = tree::map::Builder::new();
+    /// assert_eq!(builder.last_key(), None);
+    ///
+    /// builder.push(1, "a");
+    /// builder.push(2, "b");
+    /// assert_eq!(builder.last_key(), Some(&2));
+    /// ```
+    pub fn last_key(&self) -> Option<&K> { self.inner.last() }
+
+    /// Pushes an entry whose key is greater than every key pushed so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not greater than the most recently pushed key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut builder = tree::map::Builder::new();
+    ///
+    /// builder.push(1, "a");
+    /// builder.push(2, "b");
+    ///
+    /// assert_eq!(builder.build().iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+    /// ```
+    pub fn push(&mut self, key: K, value: V) {
+        if let Some(last) = self.inner.last() {
+            assert!(self.cmp.compares_lt(last, &key), "key is not greater than the previous key");
+        }
+
+        self.inner.push(key, value);
+    }
+
+    /// Consumes the builder, returning a map containing the pushed entries.
+    pub fn build(self) -> Map<K, V, C> {
+        let len = self.inner.len();
+        event!("rebuild: len={}", len);
+        Map { root: self.inner.build(), len: len, cmp: self.cmp, counters: Counters::default() }
+    }
+}
+
+impl<K, V, C> Default for Builder<K, V, C> where C: Compare<K> + Default {
+    fn default() -> Self { Builder::with_cmp(C::default()) }
+}
+
+impl<K, V, C> Extend<(K, V)> for Builder<K, V, C> where C: Compare<K> {
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, it: I) {
+        for (k, v) in it { self.push(k, v); }
+    }
+}
+
 /// An iterator that consumes the map.
 ///
 /// The iterator yields the entries in ascending order according to the map's comparator.
@@ -1081,6 +3207,12 @@ impl<K, V> ExactSizeIterator for IntoIter<K, V> {
     fn len(&self) -> usize { self.0.len() }
 }
 
+// Drop the remaining entries one at a time through the iterator's explicit stack, rather than
+// recursively through the boxes of the remaining subtrees
+impl<K, V> Drop for IntoIter<K, V> {
+    fn drop(&mut self) { for _ in self.by_ref() {} }
+}
+
 /// An iterator over the map's entries with immutable references to the values.
 ///
 /// The iterator yields the entries in ascending order according to the map's comparator.
@@ -1106,6 +3238,66 @@ impl<'a, K, V> Clone for Iter<'a, K, V> {
     fn clone(&self) -> Self { Iter(self.0.clone()) }
 }
 
+impl<'a, K, V> Iter<'a, K, V> {
+    /// Returns the entry that the next call to `next` will yield, without consuming it, or `None`
+    /// if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    ///
+    /// let mut it = map.iter();
+    ///
+    /// assert_eq!(it.peek(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.peek(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.peek(), None);
+    /// ```
+    pub fn peek(&mut self) -> Option<(&'a K, &'a V)> { self.0.peek() }
+
+    /// Returns the entry that the next call to `next_back` will yield, without consuming it, or
+    /// `None` if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    ///
+    /// let mut it = map.iter();
+    ///
+    /// assert_eq!(it.peek_back(), Some((&2, &"b")));
+    /// assert_eq!(it.next_back(), Some((&2, &"b")));
+    /// assert_eq!(it.peek_back(), Some((&1, &"a")));
+    /// assert_eq!(it.next_back(), Some((&1, &"a")));
+    /// assert_eq!(it.peek_back(), None);
+    /// ```
+    pub fn peek_back(&mut self) -> Option<(&'a K, &'a V)> { self.0.peek_back() }
+
+    /// Returns an iterator that yields the remaining entries along with their ranks.
+    ///
+    /// An entry's rank is the number of entries in the map whose keys are less than its key, so
+    /// unlike with `enumerate`, ranks remain correct even after the iterator has been advanced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = vec![("a", 1), ("b", 2), ("c", 3)].into_iter().collect();
+    /// let mut it = map.iter();
+    /// it.next();
+    ///
+    /// assert_eq!(it.enumerate_ranked().collect::<Vec<_>>(), [(1, &"b", &2), (2, &"c", &3)]);
+    /// ```
+    pub fn enumerate_ranked(self) -> Ranked<Self> { Ranked { rank: self.0.rank(), iter: self } }
+}
+
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
     fn next(&mut self) -> Option<Self::Item> { self.0.next() }
@@ -1123,6 +3315,58 @@ impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {
     fn len(&self) -> usize { self.0.len() }
 }
 
+/// A chunked iterator over the map's entries with immutable references to the values.
+///
+/// The chunks are yielded in ascending order according to the map's comparator. Each one borrows
+/// the iterator's buffer, so it must be dropped before the next is requested.
+///
+/// Acquire through [`Map::iter_chunks`](struct.Map.html#method.iter_chunks).
+pub struct Chunks<'a, K: 'a, V: 'a> {
+    iter: Iter<'a, K, V>,
+    buf: Vec<(&'a K, &'a V)>,
+    size: usize,
+}
+
+impl<'a, K, V> Chunks<'a, K, V> {
+    /// Returns the next chunk of entries, or `None` if the iterator is exhausted.
+    ///
+    /// Every chunk but the last has the length given to `Map::iter_chunks`.
+    pub fn next_chunk(&mut self) -> Option<&[(&'a K, &'a V)]> {
+        self.buf.clear();
+        self.buf.extend(self.iter.by_ref().take(self.size));
+        if self.buf.is_empty() { None } else { Some(&self.buf) }
+    }
+
+    /// Returns the number of entries that have yet to be yielded.
+    pub fn len(&self) -> usize { self.iter.len() }
+
+    /// Checks if the iterator is exhausted.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+/// An iterator over the nodes of a map's underlying tree, yielding the depth, key, and level of
+/// each.
+///
+/// Acquire through [`Map::nodes`](struct.Map.html#method.nodes). Only available with the `stats`
+/// feature.
+#[cfg(feature = "stats")]
+pub struct Nodes<'a, K: 'a, V: 'a>(node::Nodes<'a, K, V>);
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> Clone for Nodes<'a, K, V> {
+    fn clone(&self) -> Self { Nodes(self.0.clone()) }
+}
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> Iterator for Nodes<'a, K, V> {
+    type Item = (usize, &'a K, usize);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> ExactSizeIterator for Nodes<'a, K, V> {}
+
 /// An iterator over the map's entries with mutable references to the values.
 ///
 /// The iterator yields the entries in ascending order according to the map's comparator.
@@ -1154,12 +3398,199 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
     fn last(mut self) -> Option<Self::Item> { self.next_back() }
 }
 
-impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
-    fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
+/// An iterator over the entries of a map whose ranks lie in a given range.
+///
+/// The iterator yields the entries in ascending order according to the map's comparator.
+///
+/// Acquire through [`Map::select_range`](struct.Map.html#method.select_range).
+pub struct Select<'a, K: 'a, V: 'a>(node::Select<'a, K, V>);
+
+impl<'a, K, V> Clone for Select<'a, K, V> {
+    fn clone(&self) -> Self { Select(self.0.clone()) }
+}
+
+impl<'a, K, V> Select<'a, K, V> {
+    /// Returns an iterator that yields the remaining entries along with their ranks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map: tree::Map<_, _> = (0..100).map(|i| (i * 2, i)).collect();
+    ///
+    /// assert_eq!(map.select_range(10..12).enumerate_ranked().collect::<Vec<_>>(),
+    ///            [(10, &20, &10), (11, &22, &11)]);
+    /// ```
+    pub fn enumerate_ranked(self) -> Ranked<Self> { Ranked { rank: self.0.rank(), iter: self } }
+}
+
+impl<'a, K, V> Iterator for Select<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, K, V> ExactSizeIterator for Select<'a, K, V> {}
+
+/// An iterator that yields a map's entries along with their ranks.
+///
+/// An entry's rank is the number of entries in the map whose keys are less than its key.
+///
+/// Acquire through [`Iter::enumerate_ranked`](struct.Iter.html#method.enumerate_ranked),
+/// [`Range::enumerate_ranked`](struct.Range.html#method.enumerate_ranked), or
+/// [`Select::enumerate_ranked`](struct.Select.html#method.enumerate_ranked).
+#[derive(Clone)]
+pub struct Ranked<I> {
+    iter: I,
+    // the rank of the next entry at the front
+    rank: usize,
+}
+
+impl<'a, K: 'a, V: 'a, I> Iterator for Ranked<I> where I: Iterator<Item=(&'a K, &'a V)> {
+    type Item = (usize, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| {
+            self.rank += 1;
+            (self.rank - 1, key, value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.iter.size_hint() }
+}
+
+impl<'a, K: 'a, V: 'a, I> DoubleEndedIterator for Ranked<I>
+    where I: DoubleEndedIterator<Item=(&'a K, &'a V)> + ExactSizeIterator {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, value)| (self.rank + self.iter.len(), key, value))
+    }
+}
+
+impl<'a, K: 'a, V: 'a, I> ExactSizeIterator for Ranked<I>
+    where I: ExactSizeIterator<Item=(&'a K, &'a V)> {}
+
+/// A value from one or both of two maps.
+///
+/// See [`Map::union`](struct.Map.html#method.union).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EitherOrBoth<L, R> {
+    /// A value that is only in the first map.
+    Left(L),
+    /// A value that is only in the second map.
+    Right(R),
+    /// The values from both maps.
+    Both(L, R),
+}
+
+impl<L, R> EitherOrBoth<L, R> {
+    /// Returns the value from the first map, if any.
+    pub fn left(self) -> Option<L> {
+        match self {
+            EitherOrBoth::Left(l) | EitherOrBoth::Both(l, _) => Some(l),
+            EitherOrBoth::Right(_) => None,
+        }
+    }
+
+    /// Returns the value from the second map, if any.
+    pub fn right(self) -> Option<R> {
+        match self {
+            EitherOrBoth::Right(r) | EitherOrBoth::Both(_, r) => Some(r),
+            EitherOrBoth::Left(_) => None,
+        }
+    }
+}
+
+// An iterator over the keys of two maps, yielding each with its values in either or both of them
+type Merge<'a, K, V, W, C> = node::Merge<'a, K, Iter<'a, K, V>, Iter<'a, K, W>, C>;
+
+/// An iterator over the keys of two maps, with the value associated with each key in either or
+/// both of them.
+///
+/// The iterator yields the keys in ascending order according to the first map's comparator.
+///
+/// Acquire through [`Map::union`](struct.Map.html#method.union).
+pub struct Union<'a, K: 'a, V: 'a, W: 'a, C: 'a = Natural<K>>(Merge<'a, K, V, W, C>)
+    where C: Compare<K>;
+
+impl<'a, K, V, W, C> Clone for Union<'a, K, V, W, C> where C: Compare<K> {
+    fn clone(&self) -> Self { Union(self.0.clone()) }
+}
+
+impl<'a, K, V, W, C> Iterator for Union<'a, K, V, W, C> where C: Compare<K> {
+    type Item = (&'a K, EitherOrBoth<&'a V, &'a W>);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+/// An iterator over the keys that are in both of two maps, with the values associated with each
+/// key in both of them.
+///
+/// The iterator yields the keys in ascending order according to the first map's comparator.
+///
+/// Acquire through [`Map::intersection`](struct.Map.html#method.intersection).
+pub struct Intersection<'a, K: 'a, V: 'a, W: 'a, C: 'a = Natural<K>>(Merge<'a, K, V, W, C>)
+    where C: Compare<K>;
+
+impl<'a, K, V, W, C> Clone for Intersection<'a, K, V, W, C> where C: Compare<K> {
+    fn clone(&self) -> Self { Intersection(self.0.clone()) }
+}
+
+impl<'a, K, V, W, C> Iterator for Intersection<'a, K, V, W, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V, &'a W);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                None => return None,
+                Some((key, EitherOrBoth::Both(l, r))) => return Some((key, l, r)),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (l, r) = self.0.lens();
+        (0, Some(cmp::min(l, r)))
+    }
+}
+
+/// An iterator over the entries of one map whose keys are not in another.
+///
+/// The iterator yields the entries in ascending order according to the first map's comparator.
+///
+/// Acquire through [`Map::difference`](struct.Map.html#method.difference).
+pub struct Difference<'a, K: 'a, V: 'a, W: 'a, C: 'a = Natural<K>>(Merge<'a, K, V, W, C>)
+    where C: Compare<K>;
+
+impl<'a, K, V, W, C> Clone for Difference<'a, K, V, W, C> where C: Compare<K> {
+    fn clone(&self) -> Self { Difference(self.0.clone()) }
 }
 
-impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {
-    fn len(&self) -> usize { self.0.len() }
+impl<'a, K, V, W, C> Iterator for Difference<'a, K, V, W, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                None => return None,
+                Some((key, EitherOrBoth::Left(l))) => return Some((key, l)),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (l, r) = self.0.lens();
+        (l.saturating_sub(r), Some(l))
+    }
 }
 
 /// An iterator that consumes the map, yielding only those entries whose keys lie in a given range.
@@ -1177,6 +3608,7 @@ impl<K, V> Iterator for IntoRange<K, V> {
     fn next(&mut self) -> Option<Self::Item> { self.0.next() }
     fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
 
+    fn count(self) -> usize { self.len() }
     fn last(mut self) -> Option<Self::Item> { self.next_back() }
 }
 
@@ -1185,6 +3617,17 @@ impl<K, V> DoubleEndedIterator for IntoRange<K, V> {
     fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
 }
 
+#[cfg(feature = "range")]
+impl<K, V> ExactSizeIterator for IntoRange<K, V> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
+// Drop the remaining entries one at a time, as `IntoIter` does
+#[cfg(feature = "range")]
+impl<K, V> Drop for IntoRange<K, V> {
+    fn drop(&mut self) { for _ in self.by_ref() {} }
+}
+
 /// An iterator over the map's entries whose keys lie in a given range with immutable references to
 /// the values.
 ///
@@ -1199,12 +3642,79 @@ impl<'a, K, V> Clone for Range<'a, K, V> {
     fn clone(&self) -> Self { Range(self.0.clone()) }
 }
 
+#[cfg(feature = "range")]
+impl<'a, K, V> Range<'a, K, V> {
+    /// Returns the entry that the next call to `next` will yield, without consuming it, or `None`
+    /// if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Unbounded};
+    ///
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.range(Included(&2), Unbounded);
+    ///
+    /// assert_eq!(it.peek(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.peek(), Some((&3, &"c")));
+    /// ```
+    pub fn peek(&mut self) -> Option<(&'a K, &'a V)> { self.0.peek() }
+
+    /// Returns the entry that the next call to `next_back` will yield, without consuming it, or
+    /// `None` if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Excluded, Unbounded};
+    ///
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.range(Unbounded, Excluded(&3));
+    ///
+    /// assert_eq!(it.peek_back(), Some((&2, &"b")));
+    /// assert_eq!(it.next_back(), Some((&2, &"b")));
+    /// assert_eq!(it.peek_back(), Some((&1, &"a")));
+    /// ```
+    pub fn peek_back(&mut self) -> Option<(&'a K, &'a V)> { self.0.peek_back() }
+
+    /// Returns an iterator that yields the remaining entries along with their ranks in the map.
+    ///
+    /// The rank of the range's first entry is found from the sizes of the subtrees skipped while
+    /// positioning the range, so this takes no additional time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Excluded, Unbounded};
+    ///
+    /// let map: tree::Map<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    ///
+    /// assert_eq!(map.range(Excluded(&45), Unbounded).enumerate_ranked().next_back(),
+    ///            Some((9, &90, &9)));
+    /// assert_eq!(map.range(Excluded(&45), Unbounded).enumerate_ranked().next(),
+    ///            Some((5, &50, &5)));
+    /// ```
+    pub fn enumerate_ranked(self) -> Ranked<Self> { Ranked { rank: self.0.rank(), iter: self } }
+}
+
 #[cfg(feature = "range")]
 impl<'a, K, V> Iterator for Range<'a, K, V> {
     type Item = (&'a K, &'a V);
     fn next(&mut self) -> Option<Self::Item> { self.0.next() }
     fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
 
+    fn count(self) -> usize { self.len() }
     fn last(mut self) -> Option<Self::Item> { self.next_back() }
 }
 
@@ -1213,6 +3723,11 @@ impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
 }
 
+#[cfg(feature = "range")]
+impl<'a, K, V> ExactSizeIterator for Range<'a, K, V> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
 /// An iterator over the map's entries whose keys lie in a given range with mutable references to
 /// the values.
 ///
@@ -1228,6 +3743,7 @@ impl<'a, K, V> Iterator for RangeMut<'a, K, V> {
     fn next(&mut self) -> Option<Self::Item> { self.0.next() }
     fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
 
+    fn count(self) -> usize { self.len() }
     fn last(mut self) -> Option<Self::Item> { self.next_back() }
 }
 
@@ -1236,6 +3752,412 @@ impl<'a, K, V> DoubleEndedIterator for RangeMut<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
 }
 
+#[cfg(feature = "range")]
+impl<'a, K, V> ExactSizeIterator for RangeMut<'a, K, V> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
+/// A cursor over the entries of a map whose keys lie in a given range, through which each entry
+/// can be modified or removed as the scan continues.
+///
+/// Acquire through [`Map::range_entries`](struct.Map.html#method.range_entries).
+#[cfg(feature = "range")]
+pub struct RangeEntries<'a, K: 'a, V: 'a> {
+    root: &'a mut node::Link<K, V>,
+    len: &'a mut usize,
+    counters: &'a Counters,
+    // the rank of the next entry to visit
+    rank: usize,
+    // one more than the rank of the last entry in the range
+    end: usize,
+}
+
+#[cfg(feature = "range")]
+impl<'a, K, V> RangeEntries<'a, K, V> {
+    /// Returns the next entry in the range, or `None` if no entries remain.
+    pub fn next_entry(&mut self) -> Option<RangeEntry<K, V>> {
+        if self.rank == self.end { return None; }
+
+        let entry = node::find_rank(&mut *self.root, PathBuilder::default(), self.rank)
+            .into_occupied_entry(&mut *self.len, self.counters)
+            .expect("range ends beyond the map");
+
+        self.rank += 1;
+        Some(RangeEntry { entry: entry, rank: &mut self.rank, end: &mut self.end })
+    }
+
+    /// Returns the number of entries remaining in the range.
+    pub fn len(&self) -> usize { self.end - self.rank }
+
+    /// Checks if no entries remain in the range.
+    pub fn is_empty(&self) -> bool { self.rank == self.end }
+}
+
+/// An entry visited by a [`RangeEntries`](struct.RangeEntries.html) cursor.
+#[cfg(feature = "range")]
+pub struct RangeEntry<'a, K: 'a, V: 'a> {
+    entry: OccupiedEntry<'a, K, V>,
+    rank: &'a mut usize,
+    end: &'a mut usize,
+}
+
+#[cfg(feature = "range")]
+impl<'a, K, V> RangeEntry<'a, K, V> {
+    /// Returns a reference to the entry's key.
+    pub fn key(&self) -> &K { self.entry.key() }
+
+    /// Returns a reference to the entry's value.
+    pub fn get(&self) -> &V { self.entry.get() }
+
+    /// Returns a mutable reference to the entry's value.
+    pub fn get_mut(&mut self) -> &mut V { self.entry.get_mut() }
+
+    /// Replaces the entry's value with the given value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V { self.entry.insert(value) }
+
+    /// Removes the entry from the map and returns its key and value.
+    ///
+    /// The cursor continues with the entry that followed this one.
+    pub fn remove(self) -> (K, V) {
+        *self.rank -= 1;
+        *self.end -= 1;
+        self.entry.remove()
+    }
+}
+
+/// A value whose `Debug` implementation shows the structure of a map's underlying tree.
+///
+/// Acquire through [`Map::debug_tree`](struct.Map.html#method.debug_tree).
+pub struct DebugTree<'a, K: 'a, V: 'a>(&'a node::Link<K, V>);
+
+impl<'a, K, V> Debug for DebugTree<'a, K, V> where K: Debug, V: Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt(f) }
+}
+
+/// The structure of a node in a map's underlying tree and of its subtrees.
+///
+/// Acquire through [`Map::to_shape`](struct.Map.html#method.to_shape), and convert back into a
+/// map with [`Map::from_shape`](struct.Map.html#method.from_shape).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shape<K, V> {
+    /// The node's key.
+    pub key: K,
+    /// The node's value.
+    pub value: V,
+    /// The node's level in the Andersson tree, which is `1` for the nodes at the bottom.
+    pub level: usize,
+    /// The node's left subtree, whose keys are less than the node's key.
+    pub left: Option<Box<Shape<K, V>>>,
+    /// The node's right subtree, whose keys are greater than the node's key.
+    pub right: Option<Box<Shape<K, V>>>,
+}
+
+impl<K, V> Shape<K, V> {
+    /// Creates a node with the given key, value, and level, and no subtrees.
+    pub fn leaf(key: K, value: V, level: usize) -> Self {
+        Shape { key: key, value: value, level: level, left: None, right: None }
+    }
+
+    /// Replaces the node's left subtree.
+    pub fn with_left(mut self, left: Shape<K, V>) -> Self {
+        self.left = Some(Box::new(left));
+        self
+    }
+
+    /// Replaces the node's right subtree.
+    pub fn with_right(mut self, right: Shape<K, V>) -> Self {
+        self.right = Some(Box::new(right));
+        self
+    }
+}
+
+/// A key type whose values are consecutive integers, so that gaps between keys can be counted.
+///
+/// See [`Map::first_absent_key`](struct.Map.html#method.first_absent_key).
+pub trait Discrete: Copy + Ord {
+    /// Returns the number of values that are at least `self` and less than `other`, which is not
+    /// less than `self`.
+    fn distance(self, other: Self) -> u64;
+
+    /// Returns the value `n` steps greater than `self`, or `None` if there is no such value.
+    fn forward(self, n: u64) -> Option<Self>;
+}
+
+macro_rules! discrete {
+    ($($T:ty: $U:ty),*) => {
+        $(
+            impl Discrete for $T {
+                fn distance(self, other: Self) -> u64 { other.wrapping_sub(self) as $U as u64 }
+
+                fn forward(self, n: u64) -> Option<Self> {
+                    if n > (<$T>::max_value().wrapping_sub(self) as $U) as u64 { return None; }
+                    Some(self.wrapping_add(n as $T))
+                }
+            }
+        )*
+    }
+}
+
+discrete! {
+    u8: u8, u16: u16, u32: u32, u64: u64, usize: usize,
+    i8: u8, i16: u16, i32: u32, i64: u64, isize: usize
+}
+
+/// An operation on a single key in a batch.
+///
+/// See [`Map::apply_batch`](struct.Map.html#method.apply_batch).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BatchOp<V> {
+    /// Insert the key with the value, replacing the value of an equal key that is already present.
+    Upsert(V),
+    /// Remove the key, if it is present.
+    Delete,
+}
+
+/// An error indicating that a map's underlying tree violates one of its invariants.
+///
+/// Returned by [`Map::validate`](struct.Map.html#method.validate).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantError {
+    /// A key is not less than its in-order successor according to the map's comparator.
+    Order,
+    /// A node's level violates the rules of an Andersson tree.
+    Level,
+    /// The map's length does not match the number of entries in its tree.
+    Len,
+    /// A node's recorded subtree size does not match the number of entries in its subtree.
+    Size,
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            InvariantError::Order => "keys are not in ascending order according to the comparator",
+            InvariantError::Level => "node levels violate the Andersson tree invariants",
+            InvariantError::Len => "length does not match the number of entries in the tree",
+            InvariantError::Size => "subtree sizes do not match the number of entries in them",
+        })
+    }
+}
+
+impl Error for InvariantError {}
+
+/// An error indicating why a key could not be renamed.
+///
+/// Returned by [`Map::rename_key`](struct.Map.html#method.rename_key).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenameError {
+    /// The map does not contain the key to be renamed.
+    Missing,
+    /// The map already contains a different key equal to the new key.
+    Occupied,
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RenameError::Missing => "the map does not contain the key to be renamed",
+            RenameError::Occupied => "the map already contains the new key",
+        })
+    }
+}
+
+impl Error for RenameError {}
+
+/// A view of a map in which its entries are in descending order according to the map's
+/// comparator.
+///
+/// Acquire through [`Map::descending`](struct.Map.html#method.descending).
+pub struct Descending<'a, K: 'a, V: 'a, C: 'a = Natural<K>>(&'a Map<K, V, C>)
+    where C: Compare<K>;
+
+impl<'a, K, V, C> Clone for Descending<'a, K, V, C> where C: Compare<K> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<'a, K, V, C> Copy for Descending<'a, K, V, C> where C: Compare<K> {}
+
+impl<'a, K, V, C> Descending<'a, K, V, C> where C: Compare<K> {
+    /// Checks if the map is empty.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Checks if the map contains the given key.
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.0.contains_key(key)
+    }
+
+    /// Returns a reference to the value associated with the given key, or `None` if the
+    /// map does not contain the key.
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&'a V> where C: Compare<Q, K> {
+        self.0.get(key)
+    }
+
+    /// Returns a reference to the first entry in descending order, which is the map's maximum
+    /// key and its associated value, or `None` if the map is empty.
+    pub fn first(&self) -> Option<(&'a K, &'a V)> { self.0.last() }
+
+    /// Returns a reference to the last entry in descending order, which is the map's minimum key
+    /// and its associated value, or `None` if the map is empty.
+    pub fn last(&self) -> Option<(&'a K, &'a V)> { self.0.first() }
+
+    /// Returns a reference to the predecessor of the given key in descending order, which is the
+    /// map's successor of the key, or `None` if no such key is present in the map.
+    ///
+    /// See [`Map::succ`](struct.Map.html#method.succ) for the meaning of `inclusive`.
+    pub fn pred<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&'a K, &'a V)>
+        where C: Compare<Q, K> {
+
+        self.0.succ(key, inclusive)
+    }
+
+    /// Returns a reference to the successor of the given key in descending order, which is the
+    /// map's predecessor of the key, or `None` if no such key is present in the map.
+    ///
+    /// See [`Map::pred`](struct.Map.html#method.pred) for the meaning of `inclusive`.
+    pub fn succ<Q: ?Sized>(&self, key: &Q, inclusive: bool) -> Option<(&'a K, &'a V)>
+        where C: Compare<Q, K> {
+
+        self.0.pred(key, inclusive)
+    }
+
+    /// Returns an iterator over the map's entries in descending order.
+    pub fn iter(&self) -> iter::Rev<Iter<'a, K, V>> { self.0.iter().rev() }
+}
+
+#[cfg(feature = "range")]
+impl<'a, K, V, C> Descending<'a, K, V, C> where C: Compare<K> {
+    /// Returns an iterator over the map's entries whose keys lie in the given range, in
+    /// descending order.
+    ///
+    /// `max` bounds the first key to be yielded and `min` the last.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Excluded};
+    ///
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.descending().range(Included(&3), Excluded(&1)).collect::<Vec<_>>(),
+    ///     [(&3, &"c"), (&2, &"b")]);
+    /// ```
+    pub fn range<Max: ?Sized, Min: ?Sized>(&self, max: Bound<&Max>, min: Bound<&Min>)
+        -> iter::Rev<Range<'a, K, V>> where C: Compare<Min, K> + Compare<Max, K> {
+
+        self.0.range(min, max).rev()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for Descending<'a, K, V, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = iter::Rev<Iter<'a, K, V>>;
+    fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+/// A guard that undoes its modifications of a map unless committed.
+///
+/// Acquire through [`Map::transaction`](struct.Map.html#method.transaction). The guard provides
+/// the map's read-only operations through `Deref`. Only modifications made through the guard are
+/// recorded, so the map cannot be modified through `entry` or `get_mut` during a transaction.
+pub struct Transaction<'a, K: 'a, V: 'a, C: 'a = Natural<K>>
+    where K: Clone, V: Clone, C: Compare<K> {
+
+    map: &'a mut Map<K, V, C>,
+    undo: Vec<Undo<K, V>>,
+}
+
+// An operation that reverts a single modification
+enum Undo<K, V> {
+    Insert(K, V),
+    Remove(K),
+}
+
+impl<'a, K, V, C> Transaction<'a, K, V, C> where K: Clone, V: Clone, C: Compare<K> {
+    /// Inserts an entry into the map, returning the previous value, if any, associated with the
+    /// key.
+    ///
+    /// See [`Map::insert`](struct.Map.html#method.insert).
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        // like `Map::insert`, this keeps the stored key of an equal entry
+        let undo_key = match self.map.pred(&key, true) {
+            Some((k, _)) if self.map.cmp().compares_eq(&key, k) => k.clone(),
+            _ => key.clone(),
+        };
+
+        let old = self.map.insert(key, value);
+
+        self.undo.push(match old {
+            None => Undo::Remove(undo_key),
+            Some(ref old) => Undo::Insert(undo_key, old.clone()),
+        });
+
+        old
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key, returning `None` if
+    /// the map does not contain the key.
+    ///
+    /// See [`Map::remove`](struct.Map.html#method.remove).
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)> where C: Compare<Q, K> {
+        let old = self.map.remove(key);
+
+        if let Some((ref key, ref value)) = old {
+            self.undo.push(Undo::Insert(key.clone(), value.clone()));
+        }
+
+        old
+    }
+
+    /// Applies the given function to the value associated with the given key, returning `true`
+    /// if the map contains the key and `false` otherwise.
+    ///
+    /// See [`Map::update`](struct.Map.html#method.update).
+    pub fn update<Q: ?Sized, F>(&mut self, key: &Q, f: F) -> bool
+        where C: Compare<Q, K>, F: FnOnce(&mut V) {
+
+        let old = match self.map.pred(key, true) {
+            Some((k, v)) if self.map.cmp().compares_eq(key, k) => (k.clone(), v.clone()),
+            _ => return false,
+        };
+
+        self.undo.push(Undo::Insert(old.0, old.1));
+        self.map.update(key, f)
+    }
+
+    /// Keeps the transaction's modifications, ending the transaction.
+    pub fn commit(mut self) { self.undo.clear(); }
+
+    /// Undoes the transaction's modifications, ending the transaction.
+    ///
+    /// This is equivalent to dropping the guard.
+    pub fn rollback(self) {}
+}
+
+impl<'a, K, V, C> ops::Deref for Transaction<'a, K, V, C>
+    where K: Clone, V: Clone, C: Compare<K> {
+
+    type Target = Map<K, V, C>;
+    fn deref(&self) -> &Map<K, V, C> { self.map }
+}
+
+impl<'a, K, V, C> Drop for Transaction<'a, K, V, C> where K: Clone, V: Clone, C: Compare<K> {
+    fn drop(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Insert(key, value) => { self.map.insert_full(key, value); }
+                Undo::Remove(key) => { self.map.remove(&key); }
+            }
+        }
+    }
+}
+
 /// An entry in the map.
 ///
 /// See [`Map::entry`](struct.Map.html#method.entry) for an example.
@@ -1247,6 +4169,27 @@ pub enum Entry<'a, K: 'a, V: 'a> {
 }
 
 impl<'a, K, V> Entry<'a, K, V> {
+    /// Returns a reference to the entry's key.
+    ///
+    /// For an occupied entry, this is the key stored in the map, which may differ from the key
+    /// used to look up the entry if the comparator considers them equal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert("a", 1);
+    ///
+    /// assert_eq!(*map.entry("a").key(), "a");
+    /// assert_eq!(*map.entry("b").key(), "b");
+    /// ```
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref e) => e.key(),
+            Entry::Vacant(ref e) => e.key(),
+        }
+    }
+
     /// Returns the entry's value, inserting the given default if the entry is vacant.
     pub fn or_insert(self, default: V) -> &'a mut V {
         match self {
@@ -1262,4 +4205,28 @@ impl<'a, K, V> Entry<'a, K, V> {
             Entry::Vacant(e) => e.insert(default()),
         }
     }
+
+    /// Returns the entry's value, inserting the given function's result if the entry is vacant
+    /// and the function succeeds.
+    ///
+    /// If the function fails, nothing is inserted and its error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// assert_eq!(map.entry("a").or_try_insert_with(|| "1".parse::<i32>()), Ok(&mut 1));
+    /// assert!(map.entry("b").or_try_insert_with(|| "x".parse::<i32>()).is_err());
+    /// assert_eq!(map.entry("a").or_try_insert_with(|| "x".parse::<i32>()), Ok(&mut 1));
+    /// assert!(!map.contains_key(&"b"));
+    /// ```
+    pub fn or_try_insert_with<E, F>(self, default: F) -> Result<&'a mut V, E>
+        where F: FnOnce() -> Result<V, E> {
+
+        match self {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => Ok(e.insert(default()?)),
+        }
+    }
 }
@@ -0,0 +1,491 @@
+//! An ordered multimap based on a binary search tree.
+
+use compare::Compare;
+#[cfg(feature = "range")] use std::collections::Bound;
+use std::fmt::{self, Debug};
+use std::iter;
+use std::slice;
+use super::cmp::Natural;
+use super::map::{self, Map};
+
+/// An ordered multimap based on a binary search tree.
+///
+/// A multimap associates each of its keys with one or more values. The values associated with a
+/// key are stored together, in the order in which they were inserted, so that looking up or
+/// removing all of them takes a single search.
+///
+/// The behavior of this multimap is undefined if a key's ordering relative to any other key
+/// changes while the key is in the multimap. This is normally only possible through `Cell`,
+/// `RefCell`, or unsafe code.
+#[derive(Clone)]
+pub struct MultiMap<K, V, C = Natural<K>> where C: Compare<K> {
+    // every key present in the map has at least one value
+    map: Map<K, Vec<V>, C>,
+    len: usize,
+}
+
+impl<K, V> MultiMap<K, V> where K: Ord {
+    /// Creates an empty multimap ordered according to the natural order of its keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(2, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&2, &"c")));
+    /// assert_eq!(it.next(), None);
+    /// ```
+    pub fn new() -> Self { MultiMap::with_cmp(Natural::default()) }
+}
+
+impl<K, V, C> MultiMap<K, V, C> where C: Compare<K> {
+    /// Creates an empty multimap ordered according to the given comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut map = tree::MultiMap::with_cmp(natural().rev());
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&1, &"c")));
+    /// assert_eq!(it.next(), None);
+    /// # }
+    /// ```
+    pub fn with_cmp(cmp: C) -> Self { MultiMap { map: Map::with_cmp(cmp), len: 0 } }
+
+    /// Checks if the multimap is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    /// assert!(map.is_empty());
+    ///
+    /// map.insert(2, "b");
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Returns the number of values in the multimap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    /// assert_eq!(map.len(), 0);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(2, "c");
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns the number of distinct keys in the multimap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(2, "c");
+    /// map.insert(1, "a");
+    /// assert_eq!(map.keys_len(), 2);
+    /// ```
+    pub fn keys_len(&self) -> usize { self.map.len() }
+
+    /// Returns a reference to the multimap's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let map: tree::MultiMap<i32, &str> = tree::MultiMap::new();
+    /// assert!(map.cmp().compares_lt(&1, &2));
+    ///
+    /// let map: tree::MultiMap<i32, &str, _> = tree::MultiMap::with_cmp(natural().rev());
+    /// assert!(map.cmp().compares_gt(&1, &2));
+    /// # }
+    /// ```
+    pub fn cmp(&self) -> &C { self.map.cmp() }
+
+    /// Removes all values from the multimap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(2, "c");
+    ///
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// assert_eq!(map.get_all(&2), &[] as &[&str]);
+    /// ```
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len = 0;
+    }
+
+    /// Inserts a value into the multimap, after any values already associated with the key.
+    ///
+    /// If the key is already present, the given key is dropped and the stored key is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.get_all(&1), &["a", "b"]);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) {
+        self.map.entry(key).or_insert_with(Vec::new).push(value);
+        self.len += 1;
+    }
+
+    /// Removes all values associated with the given key from the multimap, returning them in the
+    /// order in which they were inserted.
+    ///
+    /// The returned vector is empty if the multimap does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    ///
+    /// assert_eq!(map.remove_all(&1), ["a", "c"]);
+    /// assert_eq!(map.remove_all(&1), [] as [&str; 0]);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn remove_all<Q: ?Sized>(&mut self, key: &Q) -> Vec<V> where C: Compare<Q, K> {
+        let values = self.map.remove(key).map_or_else(Vec::new, |e| e.1);
+        self.len -= values.len();
+        values
+    }
+
+    /// Checks if the multimap contains the given key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    /// assert!(!map.contains_key(&1));
+    ///
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// ```
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.map.contains_key(key)
+    }
+
+    /// Returns a reference to the first value associated with the given key, or `None` if the
+    /// multimap does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    /// assert_eq!(map.get(&1), None);
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// ```
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V> where C: Compare<Q, K> {
+        self.map.get(key).map(|values| &values[0])
+    }
+
+    /// Returns the values associated with the given key, in the order in which they were
+    /// inserted.
+    ///
+    /// The returned slice is empty if the multimap does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    /// assert_eq!(map.get_all(&1), &[] as &[&str]);
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.get_all(&1), &["a", "b"]);
+    /// ```
+    pub fn get_all<Q: ?Sized>(&self, key: &Q) -> &[V] where C: Compare<Q, K> {
+        self.map.get(key).map_or(&[], |values| values)
+    }
+
+    /// Returns an iterator over the multimap's keys and values.
+    ///
+    /// The iterator yields the keys in ascending order according to the multimap's comparator,
+    /// and the values associated with each key in the order in which they were inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "c");
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&1, &"b"), (&2, &"c")]);
+    /// ```
+    pub fn iter(&self) -> Iter<K, V> {
+        Iter { inner: Flatten::new(self.map.iter()), len: self.len }
+    }
+
+    /// Returns an iterator over the multimap's keys, each with all of its values.
+    ///
+    /// The iterator yields the keys in ascending order according to the multimap's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "c");
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    ///
+    /// assert_eq!(map.groups().collect::<Vec<_>>(), [(&1, &["a", "b"][..]), (&2, &["c"][..])]);
+    /// ```
+    pub fn groups(&self) -> Groups<K, V> { Groups(self.map.iter()) }
+}
+
+#[cfg(feature = "range")]
+impl<K, V, C> MultiMap<K, V, C> where C: Compare<K> {
+    /// Returns an iterator over the multimap's keys that lie in the given range and their
+    /// values.
+    ///
+    /// The iterator yields the keys in ascending order according to the multimap's comparator,
+    /// and the values associated with each key in the order in which they were inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Unbounded};
+    ///
+    /// let mut map = tree::MultiMap::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(2, "c");
+    ///
+    /// assert_eq!(map.range(Included(&2), Unbounded).collect::<Vec<_>>(),
+    ///            [(&2, &"b"), (&2, &"c")]);
+    /// ```
+    pub fn range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> Range<K, V> where C: Compare<Min, K> + Compare<Max, K> {
+
+        Range(Flatten::new(self.map.range(min, max)))
+    }
+}
+
+impl<K, V, C> Debug for MultiMap<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<K, V, C> Default for MultiMap<K, V, C> where C: Compare<K> + Default {
+    fn default() -> Self { MultiMap::with_cmp(C::default()) }
+}
+
+impl<K, V, C> Extend<(K, V)> for MultiMap<K, V, C> where C: Compare<K> {
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, it: I) {
+        for (k, v) in it { self.insert(k, v); }
+    }
+}
+
+impl<K, V, C> iter::FromIterator<(K, V)> for MultiMap<K, V, C> where C: Compare<K> + Default {
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(it: I) -> Self {
+        let mut map = MultiMap::default();
+        map.extend(it);
+        map
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a MultiMap<K, V, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> { self.iter() }
+}
+
+impl<K, V, C> PartialEq for MultiMap<K, V, C> where V: PartialEq, C: Compare<K> {
+    fn eq(&self, other: &Self) -> bool { self.len == other.len && self.map == other.map }
+}
+
+impl<K, V, C> Eq for MultiMap<K, V, C> where V: Eq, C: Compare<K> {}
+
+// Flattens an iterator over a map's keys and their groups of values into one over the keys and
+// the individual values, from either end
+struct Flatten<'a, K: 'a, V: 'a, I> {
+    groups: I,
+    front: Option<(&'a K, slice::Iter<'a, V>)>,
+    back: Option<(&'a K, slice::Iter<'a, V>)>,
+}
+
+impl<'a, K, V, I> Flatten<'a, K, V, I> {
+    fn new(groups: I) -> Self { Flatten { groups: groups, front: None, back: None } }
+}
+
+impl<'a, K, V, I> Clone for Flatten<'a, K, V, I> where I: Clone {
+    fn clone(&self) -> Self {
+        Flatten { groups: self.groups.clone(), front: self.front.clone(), back: self.back.clone() }
+    }
+}
+
+impl<'a, K, V, I> Iterator for Flatten<'a, K, V, I> where I: Iterator<Item=(&'a K, &'a Vec<V>)> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(&mut (key, ref mut values)) = self.front.as_mut() {
+                if let Some(value) = values.next() { return Some((key, value)); }
+            }
+
+            match self.groups.next() {
+                Some((key, values)) => self.front = Some((key, values.iter())),
+                None => return self.back.as_mut().and_then(|&mut (key, ref mut values)| {
+                    values.next().map(|value| (key, value))
+                }),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let front = self.front.as_ref().map_or(0, |e| e.1.len());
+        let back = self.back.as_ref().map_or(0, |e| e.1.len());
+        let (groups, max_groups) = self.groups.size_hint();
+        (front + back + groups, if max_groups == Some(0) { Some(front + back) } else { None })
+    }
+}
+
+impl<'a, K, V, I> DoubleEndedIterator for Flatten<'a, K, V, I>
+    where I: DoubleEndedIterator<Item=(&'a K, &'a Vec<V>)> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(&mut (key, ref mut values)) = self.back.as_mut() {
+                if let Some(value) = values.next_back() { return Some((key, value)); }
+            }
+
+            match self.groups.next_back() {
+                Some((key, values)) => self.back = Some((key, values.iter())),
+                None => return self.front.as_mut().and_then(|&mut (key, ref mut values)| {
+                    values.next_back().map(|value| (key, value))
+                }),
+            }
+        }
+    }
+}
+
+/// An iterator over the multimap's keys and values.
+///
+/// The iterator yields the keys in ascending order according to the multimap's comparator, and
+/// the values associated with each key in the order in which they were inserted.
+///
+/// Acquire through [`MultiMap::iter`](struct.MultiMap.html#method.iter) or the `IntoIterator`
+/// trait.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    inner: Flatten<'a, K, V, map::Iter<'a, K, Vec<V>>>,
+    len: usize,
+}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self { Iter { inner: self.inner.clone(), len: self.len } }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| { self.len -= 1; e })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.len, Some(self.len)) }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| { self.len -= 1; e })
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+/// An iterator over the multimap's keys that lie in a given range and their values.
+///
+/// The iterator yields the keys in ascending order according to the multimap's comparator, and
+/// the values associated with each key in the order in which they were inserted.
+///
+/// Acquire through [`MultiMap::range`](struct.MultiMap.html#method.range).
+#[cfg(feature = "range")]
+pub struct Range<'a, K: 'a, V: 'a>(Flatten<'a, K, V, map::Range<'a, K, Vec<V>>>);
+
+#[cfg(feature = "range")]
+impl<'a, K, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Self { Range(self.0.clone()) }
+}
+
+#[cfg(feature = "range")]
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+#[cfg(feature = "range")]
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back() }
+}
+
+/// An iterator over the multimap's keys, each with all of its values.
+///
+/// The iterator yields the keys in ascending order according to the multimap's comparator.
+///
+/// Acquire through [`MultiMap::groups`](struct.MultiMap.html#method.groups).
+pub struct Groups<'a, K: 'a, V: 'a>(map::Iter<'a, K, Vec<V>>);
+
+impl<'a, K, V> Clone for Groups<'a, K, V> {
+    fn clone(&self) -> Self { Groups(self.0.clone()) }
+}
+
+impl<'a, K, V> Iterator for Groups<'a, K, V> {
+    type Item = (&'a K, &'a [V]);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|(k, v)| (k, &v[..])) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Groups<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back().map(|(k, v)| (k, &v[..])) }
+}
+
+impl<'a, K, V> ExactSizeIterator for Groups<'a, K, V> {}
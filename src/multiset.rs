@@ -0,0 +1,577 @@
+//! An ordered multiset based on a binary search tree.
+
+use compare::Compare;
+use std::cmp;
+use std::fmt::{self, Debug};
+use std::iter;
+use super::cmp::Natural;
+use super::map::{self, EitherOrBoth, Map};
+use super::node;
+
+/// An ordered multiset based on a binary search tree.
+///
+/// A multiset may contain several occurrences of an item. Each distinct item is stored once, along
+/// with its multiplicity, which is the number of times it occurs in the multiset.
+///
+/// The behavior of this multiset is undefined if an item's ordering relative to any other item
+/// changes while the item is in the multiset. This is normally only possible through `Cell`,
+/// `RefCell`, or unsafe code.
+#[derive(Clone)]
+pub struct Multiset<T, C = Natural<T>> where C: Compare<T> {
+    // every item present in the map has a non-zero multiplicity
+    map: Map<T, usize, C>,
+    len: usize,
+}
+
+impl<T> Multiset<T> where T: Ord {
+    /// Creates an empty multiset ordered according to the natural order of its items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(2);
+    ///
+    /// let mut it = set.iter();
+    /// assert_eq!(it.next(), Some(&1));
+    /// assert_eq!(it.next(), Some(&2));
+    /// assert_eq!(it.next(), Some(&2));
+    /// assert_eq!(it.next(), None);
+    /// ```
+    pub fn new() -> Self { Multiset::with_cmp(Natural::default()) }
+}
+
+impl<T, C> Multiset<T, C> where C: Compare<T> {
+    /// Creates an empty multiset ordered according to the given comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut set = tree::Multiset::with_cmp(natural().rev());
+    ///
+    /// set.insert(1);
+    /// set.insert(2);
+    /// set.insert(1);
+    ///
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [&2, &1, &1]);
+    /// # }
+    /// ```
+    pub fn with_cmp(cmp: C) -> Self { Multiset { map: Map::with_cmp(cmp), len: 0 } }
+
+    /// Checks if the multiset is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// assert!(set.is_empty());
+    ///
+    /// set.insert(2);
+    /// assert!(!set.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Returns the number of items in the multiset, counting each occurrence of an item
+    /// separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// assert_eq!(set.len(), 0);
+    ///
+    /// set.insert_n(2, 3);
+    /// assert_eq!(set.len(), 3);
+    /// ```
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns the number of distinct items in the multiset.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    ///
+    /// set.insert_n(2, 3);
+    /// set.insert(1);
+    /// assert_eq!(set.distinct_len(), 2);
+    /// ```
+    pub fn distinct_len(&self) -> usize { self.map.len() }
+
+    /// Returns a reference to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let set: tree::Multiset<i32> = tree::Multiset::new();
+    /// assert!(set.cmp().compares_lt(&1, &2));
+    ///
+    /// let set: tree::Multiset<i32, _> = tree::Multiset::with_cmp(natural().rev());
+    /// assert!(set.cmp().compares_gt(&1, &2));
+    /// # }
+    /// ```
+    pub fn cmp(&self) -> &C { self.map.cmp() }
+
+    /// Removes all items from the multiset.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    ///
+    /// set.insert_n(2, 3);
+    ///
+    /// set.clear();
+    /// assert!(set.is_empty());
+    /// assert_eq!(set.count(&2), 0);
+    /// ```
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len = 0;
+    }
+
+    /// Inserts an occurrence of the given item into the multiset, returning the item's previous
+    /// multiplicity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// assert_eq!(set.insert(1), 0);
+    /// assert_eq!(set.insert(1), 1);
+    /// assert_eq!(set.count(&1), 2);
+    /// ```
+    pub fn insert(&mut self, item: T) -> usize { self.insert_n(item, 1) }
+
+    /// Inserts `n` occurrences of the given item into the multiset, returning the item's previous
+    /// multiplicity.
+    ///
+    /// If `n` is zero, the multiset is left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item's multiplicity would overflow a `usize`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// assert_eq!(set.insert_n(1, 3), 0);
+    /// assert_eq!(set.insert_n(1, 2), 3);
+    /// assert_eq!(set.insert_n(2, 0), 0);
+    /// assert_eq!(set.count(&1), 5);
+    /// assert!(!set.contains(&2));
+    /// ```
+    pub fn insert_n(&mut self, item: T, n: usize) -> usize {
+        if n == 0 { return self.count(&item); }
+
+        let count = self.map.entry(item).or_insert(0);
+        let prev = *count;
+        *count = prev.checked_add(n).expect("multiplicity overflow");
+        self.len += n;
+        prev
+    }
+
+    /// Removes an occurrence of the given item from the multiset, returning `true` if the
+    /// multiset contained the item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// set.insert_n(1, 2);
+    ///
+    /// assert!(set.remove(&1));
+    /// assert!(set.remove(&1));
+    /// assert!(!set.remove(&1));
+    /// ```
+    pub fn remove<Q: ?Sized>(&mut self, item: &Q) -> bool where C: Compare<Q, T> {
+        self.remove_n(item, 1) == 1
+    }
+
+    /// Removes up to `n` occurrences of the given item from the multiset, returning the number of
+    /// occurrences removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// set.insert_n(1, 5);
+    ///
+    /// assert_eq!(set.remove_n(&1, 3), 3);
+    /// assert_eq!(set.remove_n(&1, 3), 2);
+    /// assert!(!set.contains(&1));
+    /// ```
+    pub fn remove_n<Q: ?Sized>(&mut self, item: &Q, n: usize) -> usize where C: Compare<Q, T> {
+        let (removed, emptied) = match self.map.get_mut(item) {
+            None => return 0,
+            Some(count) => {
+                let removed = cmp::min(*count, n);
+                *count -= removed;
+                (removed, *count == 0)
+            }
+        };
+
+        if emptied { self.map.remove(item); }
+        self.len -= removed;
+        removed
+    }
+
+    /// Removes all occurrences of the given item from the multiset, returning the item's previous
+    /// multiplicity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// set.insert_n(1, 5);
+    ///
+    /// assert_eq!(set.remove_all(&1), 5);
+    /// assert_eq!(set.remove_all(&1), 0);
+    /// ```
+    pub fn remove_all<Q: ?Sized>(&mut self, item: &Q) -> usize where C: Compare<Q, T> {
+        let removed = self.map.remove(item).map_or(0, |e| e.1);
+        self.len -= removed;
+        removed
+    }
+
+    /// Checks if the multiset contains at least one occurrence of the given item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Multiset::new();
+    /// assert!(!set.contains(&1));
+    ///
+    /// set.insert(1);
+    /// assert!(set.contains(&1));
+    /// ```
+    pub fn contains<Q: ?Sized>(&self, item: &Q) -> bool where C: Compare<Q, T> {
+        self.map.contains_key(item)
+    }
+
+    /// Returns the multiplicity of the given item, which is zero if the multiset does not contain
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Multiset<_> = vec![1, 2, 1, 1].into_iter().collect();
+    ///
+    /// assert_eq!(set.count(&1), 3);
+    /// assert_eq!(set.count(&2), 1);
+    /// assert_eq!(set.count(&3), 0);
+    /// ```
+    pub fn count<Q: ?Sized>(&self, item: &Q) -> usize where C: Compare<Q, T> {
+        self.map.get(item).map_or(0, |&count| count)
+    }
+
+    /// Returns an iterator over the multiset, yielding each item as many times as it occurs.
+    ///
+    /// The iterator yields the items in ascending order according to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Multiset<_> = vec![2, 1, 2].into_iter().collect();
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [&1, &2, &2]);
+    /// ```
+    pub fn iter(&self) -> Iter<T> {
+        Iter { counts: self.map.iter(), front: None, back: None, len: self.len }
+    }
+
+    /// Returns an iterator over the multiset's distinct items and their multiplicities.
+    ///
+    /// The iterator yields the items in ascending order according to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Multiset<_> = vec![2, 1, 2].into_iter().collect();
+    /// assert_eq!(set.counts().collect::<Vec<_>>(), [(&1, 1), (&2, 2)]);
+    /// ```
+    pub fn counts(&self) -> Counts<T> { Counts(self.map.iter()) }
+
+    /// Returns an iterator over the union of the multiset and the given multiset, yielding each
+    /// distinct item with the larger of its multiplicities in the two.
+    ///
+    /// The iterator yields the items in ascending order according to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Multiset<_> = vec![1, 1, 2].into_iter().collect();
+    /// let b: tree::Multiset<_> = vec![1, 3, 3].into_iter().collect();
+    ///
+    /// assert_eq!(a.union(&b).collect::<Vec<_>>(), [(&1, 2), (&2, 1), (&3, 2)]);
+    /// ```
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, C> {
+        Union(Merge::new(self, other))
+    }
+
+    /// Returns an iterator over the intersection of the multiset and the given multiset, yielding
+    /// each item that is in both with the smaller of its multiplicities in the two.
+    ///
+    /// The iterator yields the items in ascending order according to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Multiset<_> = vec![1, 1, 2].into_iter().collect();
+    /// let b: tree::Multiset<_> = vec![1, 3, 3].into_iter().collect();
+    ///
+    /// assert_eq!(a.intersection(&b).collect::<Vec<_>>(), [(&1, 1)]);
+    /// ```
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, C> {
+        Intersection(Merge::new(self, other))
+    }
+
+    /// Returns an iterator over the sum of the multiset and the given multiset, yielding each
+    /// distinct item with the total of its multiplicities in the two.
+    ///
+    /// The iterator yields the items in ascending order according to the multiset's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Multiset<_> = vec![1, 1, 2].into_iter().collect();
+    /// let b: tree::Multiset<_> = vec![1, 3, 3].into_iter().collect();
+    ///
+    /// assert_eq!(a.sum(&b).collect::<Vec<_>>(), [(&1, 3), (&2, 1), (&3, 2)]);
+    /// ```
+    pub fn sum<'a>(&'a self, other: &'a Self) -> Sum<'a, T, C> { Sum(Merge::new(self, other)) }
+}
+
+impl<T, C> Debug for Multiset<T, C> where T: Debug, C: Compare<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<T, C> Default for Multiset<T, C> where C: Compare<T> + Default {
+    fn default() -> Self { Multiset::with_cmp(C::default()) }
+}
+
+impl<T, C> Extend<T> for Multiset<T, C> where C: Compare<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for item in it { self.insert(item); }
+    }
+}
+
+impl<T, C> iter::FromIterator<T> for Multiset<T, C> where C: Compare<T> + Default {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Self {
+        let mut set = Multiset::default();
+        set.extend(it);
+        set
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a Multiset<T, C> where C: Compare<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> { self.iter() }
+}
+
+impl<T, C> PartialEq for Multiset<T, C> where C: Compare<T> {
+    fn eq(&self, other: &Self) -> bool { self.len == other.len && self.map == other.map }
+}
+
+impl<T, C> Eq for Multiset<T, C> where C: Compare<T> {}
+
+/// An iterator over the multiset, yielding each item as many times as it occurs.
+///
+/// The iterator yields the items in ascending order according to the multiset's comparator.
+///
+/// Acquire through [`Multiset::iter`](struct.Multiset.html#method.iter) or the `IntoIterator`
+/// trait.
+pub struct Iter<'a, T: 'a> {
+    counts: map::Iter<'a, T, usize>,
+    // the items at either end whose occurrences have been partly yielded, and the number of their
+    // occurrences that remain
+    front: Option<(&'a T, usize)>,
+    back: Option<(&'a T, usize)>,
+    len: usize,
+}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter { counts: self.counts.clone(), front: self.front, back: self.back, len: self.len }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 { return None; }
+        self.len -= 1;
+
+        let (item, count) = match self.front.take() {
+            Some(front) => front,
+            None => match self.counts.next() {
+                Some((item, &count)) => (item, count),
+                None => self.back.take().expect("iterator length is inconsistent"),
+            },
+        };
+
+        if count > 1 { self.front = Some((item, count - 1)); }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.len, Some(self.len)) }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.len == 0 { return None; }
+        self.len -= 1;
+
+        let (item, count) = match self.back.take() {
+            Some(back) => back,
+            None => match self.counts.next_back() {
+                Some((item, &count)) => (item, count),
+                None => self.front.take().expect("iterator length is inconsistent"),
+            },
+        };
+
+        if count > 1 { self.back = Some((item, count - 1)); }
+        Some(item)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// An iterator over the multiset's distinct items and their multiplicities.
+///
+/// The iterator yields the items in ascending order according to the multiset's comparator.
+///
+/// Acquire through [`Multiset::counts`](struct.Multiset.html#method.counts).
+pub struct Counts<'a, T: 'a>(map::Iter<'a, T, usize>);
+
+impl<'a, T> Clone for Counts<'a, T> {
+    fn clone(&self) -> Self { Counts(self.0.clone()) }
+}
+
+impl<'a, T> Iterator for Counts<'a, T> {
+    type Item = (&'a T, usize);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|(item, &count)| (item, count)) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, T> DoubleEndedIterator for Counts<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(item, &count)| (item, count))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Counts<'a, T> {}
+
+// An iterator over the distinct items of two multisets, yielding each with its multiplicities in
+// both, one of which may be zero
+struct Merge<'a, T: 'a, C: 'a>(node::Merge<'a, T, Counts<'a, T>, Counts<'a, T>, C>)
+    where C: Compare<T>;
+
+impl<'a, T, C> Merge<'a, T, C> where C: Compare<T> {
+    fn new(l: &'a Multiset<T, C>, r: &'a Multiset<T, C>) -> Self {
+        Merge(node::Merge::new(l.counts(), r.counts(), l.cmp()))
+    }
+}
+
+impl<'a, T, C> Clone for Merge<'a, T, C> where C: Compare<T> {
+    fn clone(&self) -> Self { Merge(self.0.clone()) }
+}
+
+impl<'a, T, C> Iterator for Merge<'a, T, C> where C: Compare<T> {
+    type Item = (&'a T, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(item, counts)| match counts {
+            EitherOrBoth::Left(l) => (item, l, 0),
+            EitherOrBoth::Both(l, r) => (item, l, r),
+            EitherOrBoth::Right(r) => (item, 0, r),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+/// An iterator over the union of two multisets.
+///
+/// The iterator yields each distinct item with the larger of its multiplicities in the two, in
+/// ascending order according to the multisets' comparator.
+///
+/// Acquire through [`Multiset::union`](struct.Multiset.html#method.union).
+pub struct Union<'a, T: 'a, C: 'a = Natural<T>>(Merge<'a, T, C>) where C: Compare<T>;
+
+impl<'a, T, C> Clone for Union<'a, T, C> where C: Compare<T> {
+    fn clone(&self) -> Self { Union(self.0.clone()) }
+}
+
+impl<'a, T, C> Iterator for Union<'a, T, C> where C: Compare<T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(item, l, r)| (item, cmp::max(l, r)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+/// An iterator over the intersection of two multisets.
+///
+/// The iterator yields each item that is in both with the smaller of its multiplicities in the
+/// two, in ascending order according to the multisets' comparator.
+///
+/// Acquire through [`Multiset::intersection`](struct.Multiset.html#method.intersection).
+pub struct Intersection<'a, T: 'a, C: 'a = Natural<T>>(Merge<'a, T, C>) where C: Compare<T>;
+
+impl<'a, T, C> Clone for Intersection<'a, T, C> where C: Compare<T> {
+    fn clone(&self) -> Self { Intersection(self.0.clone()) }
+}
+
+impl<'a, T, C> Iterator for Intersection<'a, T, C> where C: Compare<T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                None => return None,
+                Some((item, l, r)) => if l != 0 && r != 0 { return Some((item, cmp::min(l, r))); },
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (l, r) = (self.0).0.lens();
+        (0, Some(cmp::min(l, r)))
+    }
+}
+
+/// An iterator over the sum of two multisets.
+///
+/// The iterator yields each distinct item with the total of its multiplicities in the two, in
+/// ascending order according to the multisets' comparator.
+///
+/// Acquire through [`Multiset::sum`](struct.Multiset.html#method.sum).
+pub struct Sum<'a, T: 'a, C: 'a = Natural<T>>(Merge<'a, T, C>) where C: Compare<T>;
+
+impl<'a, T, C> Clone for Sum<'a, T, C> where C: Compare<T> {
+    fn clone(&self) -> Self { Sum(self.0.clone()) }
+}
+
+impl<'a, T, C> Iterator for Sum<'a, T, C> where C: Compare<T> {
+    type Item = (&'a T, usize);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|(item, l, r)| (item, l + r)) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
@@ -0,0 +1,144 @@
+use compare::Compare;
+#[cfg(feature = "debug-validate")] use std::cmp::Ordering::*;
+#[cfg(feature = "debug-validate")] use super::{Iter, MarkedNode, size};
+use super::{Link, Node};
+use super::super::map::InvariantError;
+
+// Verify that the keys along the search path for the given key are ordered by the comparator as
+// the tree's structure says they are, and that the comparator is antisymmetric on each pair of
+// adjacent keys along the path
+#[cfg(feature = "debug-validate")]
+pub fn path<K, V, C: ?Sized, Q: ?Sized>(mut link: &Link<K, V>, cmp: &C, key: &Q)
+    where C: Compare<K> + Compare<Q, K> {
+
+    while let Some(ref node) = *link {
+        let order = cmp.compare(key, &node.key);
+
+        link = match order {
+            Less => &node.left,
+            Equal => return,
+            Greater => &node.right,
+        };
+
+        if let Some(ref child) = *link {
+            let forward = cmp.compare(&child.key, &node.key);
+            let backward = cmp.compare(&node.key, &child.key);
+
+            if forward != backward.reverse() {
+                panic!("comparator is not antisymmetric: compare(a, b) returned {:?}, but \
+                        compare(b, a) returned {:?}", forward, backward);
+            }
+
+            if forward != order {
+                panic!("comparator is inconsistent with the tree's structure: a key stored to \
+                        the {} of another compared {:?} to it",
+                       if order == Less { "left" } else { "right" }, forward);
+            }
+        }
+    }
+}
+
+// Verify that an in-order traversal of the tree yields strictly ascending keys, and spot-check the
+// comparator's transitivity on each triple of consecutive keys and on each key together with the
+// root's, which every key must compare to as its in-order position says it does
+#[cfg(feature = "debug-validate")]
+pub fn sorted<K, V, C: ?Sized>(root: &Link<K, V>, len: usize, cmp: &C) where C: Compare<K> {
+    let root_index = root.as_ref().map_or(0, |node| size(&node.left));
+    let it = Iter::new(root.as_ref().map(MarkedNode::new), len);
+    let mut prevs: (Option<&K>, Option<&K>) = (None, None);
+
+    for (index, (key, _)) in it.enumerate() {
+        if let Some(prev) = prevs.1 {
+            if !cmp.compares_lt(prev, key) {
+                panic!("comparator is inconsistent with the tree's structure: in-order keys {} \
+                        and {} are not in ascending order", index - 1, index);
+            }
+        }
+
+        if let Some(prev) = prevs.0 {
+            if !cmp.compares_lt(prev, key) {
+                panic!("comparator is not transitive: in-order keys {} < {} < {}, but key {} \
+                        is not less than key {}", index - 2, index - 1, index, index - 2, index);
+            }
+        }
+
+        if let Some(ref root) = *root {
+            let order = cmp.compare(key, &root.key);
+
+            if order != index.cmp(&root_index) {
+                panic!("comparator is not transitive: in-order key {} compared {:?} to the root's \
+                        key, which is in-order key {}", index, order, root_index);
+            }
+        }
+
+        prevs = (prevs.1, Some(key));
+    }
+}
+
+// The parts of a node that the invariant walk inspects, so that the owned and persistent trees
+// share a single definition of a valid tree
+pub trait Inspect {
+    type Key;
+    fn left(&self) -> Option<&Self>;
+    fn right(&self) -> Option<&Self>;
+    fn level(&self) -> usize;
+    fn key(&self) -> &Self::Key;
+
+    // Return the number of entries that the node records for its subtree, if it records one
+    fn size(&self) -> Option<usize>;
+}
+
+impl<K, V> Inspect for Node<K, V> {
+    type Key = K;
+    fn left(&self) -> Option<&Self> { self.left.as_ref().map(|node| &**node) }
+    fn right(&self) -> Option<&Self> { self.right.as_ref().map(|node| &**node) }
+    fn level(&self) -> usize { self.level }
+    fn key(&self) -> &K { &self.key }
+    fn size(&self) -> Option<usize> { Some(self.size) }
+}
+
+// Verify that the tree's keys are in strictly ascending order according to the comparator, that
+// its levels satisfy the Andersson tree invariants, and that any sizes its nodes record match
+// those of their subtrees, returning the number of entries
+pub fn walk<N, C: ?Sized>(root: Option<&N>, cmp: &C) -> Result<usize, InvariantError>
+    where N: Inspect, C: Compare<N::Key> {
+
+    fn visit<'a, N, C: ?Sized>(node: Option<&'a N>, cmp: &C, prev: &mut Option<&'a N::Key>)
+        -> Result<usize, InvariantError> where N: Inspect, C: Compare<N::Key> {
+
+        let node = match node {
+            None => return Ok(0),
+            Some(node) => node,
+        };
+
+        let level = |node: Option<&N>| node.map_or(0, N::level);
+        let right_right = node.right().map_or(0, |right| level(right.right()));
+
+        if level(node.left()) + 1 != node.level() ||
+           level(node.right()) + 1 < node.level() || level(node.right()) > node.level() ||
+           right_right >= node.level() {
+            return Err(InvariantError::Level);
+        }
+
+        let left_len = visit(node.left(), cmp, prev)?;
+
+        if let Some(prev) = *prev {
+            if !cmp.compares_lt(prev, node.key()) { return Err(InvariantError::Order); }
+        }
+
+        *prev = Some(node.key());
+        let len = left_len + 1 + visit(node.right(), cmp, prev)?;
+
+        if node.size().map_or(false, |size| size != len) { return Err(InvariantError::Size); }
+        Ok(len)
+    }
+
+    visit(root, cmp, &mut None)
+}
+
+// Verify the invariants of an owned tree, whose nodes all record their sizes
+pub fn validate<K, V, C: ?Sized>(root: &Link<K, V>, cmp: &C) -> Result<usize, InvariantError>
+    where C: Compare<K> {
+
+    walk(root.as_ref().map(|node| &**node), cmp)
+}
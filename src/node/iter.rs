@@ -1,9 +1,14 @@
-#[cfg(feature = "range")] use compare::Compare;
-#[cfg(feature = "range")] use std::cmp::Ordering::*;
+use compare::Compare;
+use std::borrow::Borrow;
+use std::cmp;
+use std::cmp::Ordering::*;
 #[cfg(feature = "range")] use std::collections::Bound;
 use std::collections::VecDeque;
+use std::iter::Peekable;
 use std::marker::PhantomData;
+use super::super::map::EitherOrBoth;
 use super::Node;
+#[cfg(feature = "range")] use super::size;
 
 pub trait NodeRef: Sized {
     type Key;
@@ -12,6 +17,12 @@ pub trait NodeRef: Sized {
     fn item(self) -> Self::Item;
     fn left(&mut self) -> Option<Self>;
     fn right(&mut self) -> Option<Self>;
+    // The number of entries in the node's left subtree that have not yet been taken by `left`
+    #[cfg(feature = "range")]
+    fn left_size(&self) -> usize;
+    // The number of entries in the node's right subtree that have not yet been taken by `right`
+    #[cfg(feature = "range")]
+    fn right_size(&self) -> usize;
 }
 
 pub struct MarkedNode<'a, K: 'a, V: 'a> {
@@ -55,6 +66,11 @@ impl<'a, K, V> NodeRef for MarkedNode<'a, K, V> {
             self.node.right.as_ref().map(MarkedNode::new)
         }
     }
+
+    #[cfg(feature = "range")]
+    fn left_size(&self) -> usize { if self.seen_l { 0 } else { size(&self.node.left) } }
+    #[cfg(feature = "range")]
+    fn right_size(&self) -> usize { if self.seen_r { 0 } else { size(&self.node.right) } }
 }
 
 pub struct MutMarkedNode<'a, K: 'a, V: 'a> {
@@ -101,6 +117,16 @@ impl<'a, K, V> NodeRef for MutMarkedNode<'a, K, V> {
             unsafe { &mut *self.node}.right.as_mut().map(MutMarkedNode::new)
         }
     }
+
+    #[cfg(feature = "range")]
+    fn left_size(&self) -> usize {
+        if self.seen_l { 0 } else { size(&unsafe { &*self.node }.left) }
+    }
+
+    #[cfg(feature = "range")]
+    fn right_size(&self) -> usize {
+        if self.seen_r { 0 } else { size(&unsafe { &*self.node }.right) }
+    }
 }
 
 impl<K, V> NodeRef for Box<Node<K, V>> {
@@ -110,12 +136,20 @@ impl<K, V> NodeRef for Box<Node<K, V>> {
     fn item(self) -> Self::Item { let node = *self; (node.key, node.value) }
     fn left(&mut self) -> Option<Self> { self.left.take() }
     fn right(&mut self) -> Option<Self> { self.right.take() }
+
+    #[cfg(feature = "range")]
+    fn left_size(&self) -> usize { size(&self.left) }
+
+    #[cfg(feature = "range")]
+    fn right_size(&self) -> usize { size(&self.right) }
 }
 
 #[derive(Clone)]
 pub struct Iter<N> where N: NodeRef {
     nodes: VecDeque<N>,
     size: usize,
+    // the rank of the next entry at the front, which is the number of entries preceding it
+    rank: usize,
 }
 
 macro_rules! bound {
@@ -126,6 +160,7 @@ macro_rules! bound {
      $ordering_post:ident,
      $pre:ident,
      $post:ident,
+     $pre_size:ident,
      $mut_:ident,
      $pop:ident,
      $push:ident
@@ -134,15 +169,16 @@ macro_rules! bound {
             loop {
                 let op = match $iter.nodes.$mut_() {
                     None => break,
+                    // keep descending past a key equal to the bound, since the comparator may
+                    // compare the bound equal to several keys
                     Some(node) => match $cmp.compare(key, node.key()) {
                         Equal =>
                             if inc {
-                                if node.$pre().is_some() { $iter.size -= 1; }
-                                break;
+                                Op::Push(node.$pre())
                             } else {
-                                Op::PopPush(node.$post(), true)
+                                Op::PopPush(node.$post(), 1 + node.$pre_size())
                             },
-                        $ordering_post => Op::PopPush(node.$post(), false),
+                        $ordering_post => Op::PopPush(node.$post(), 1 + node.$pre_size()),
                         $ordering_pre => Op::Push(node.$pre()),
                     },
                 };
@@ -152,11 +188,12 @@ macro_rules! bound {
                         None => break,
                         Some(node) => $iter.nodes.$push(node),
                     },
-                    Op::PopPush(node_ref, terminate) => {
+                    // the popped node and its unvisited subtree on the near side of the bound are
+                    // excluded from the range, so the iterator's size remains exact
+                    Op::PopPush(node_ref, excluded) => {
                         $iter.nodes.$pop();
-                        $iter.size -= 1;
+                        $iter.size -= excluded;
                         if let Some(node) = node_ref { $iter.nodes.$push(node); }
-                        if terminate { break; }
                     }
                 }
             }
@@ -166,7 +203,31 @@ macro_rules! bound {
 
 impl<N> Iter<N> where N: NodeRef {
     pub fn new(root: Option<N>, size: usize) -> Self {
-        Iter { nodes: root.into_iter().collect(), size: size }
+        Iter { nodes: root.into_iter().collect(), size: size, rank: 0 }
+    }
+
+    pub fn rank(&self) -> usize { self.rank }
+}
+
+// Peeking descends to the next node on the given end exactly as iteration would, leaving it in the
+// deque instead of popping it, so it requires that node references be copyable
+impl<N> Iter<N> where N: NodeRef + Copy {
+    pub fn peek(&mut self) -> Option<N::Item> {
+        loop {
+            match self.nodes.back_mut().and_then(N::left) {
+                None => return self.nodes.back().map(|&node| node.item()),
+                Some(left) => self.nodes.push_back(left),
+            }
+        }
+    }
+
+    pub fn peek_back(&mut self) -> Option<N::Item> {
+        loop {
+            match self.nodes.front_mut().and_then(N::right) {
+                None => return self.nodes.front().map(|&node| node.item()),
+                Some(right) => self.nodes.push_front(right),
+            }
+        }
     }
 }
 
@@ -180,6 +241,7 @@ impl<N> Iterator for Iter<N> where N: NodeRef {
             match push {
                 None => return self.nodes.pop_back().map(|mut node| {
                     self.size -= 1;
+                    self.rank += 1;
                     if let Some(right) = node.right() { self.nodes.push_back(right); }
                     node.item()
                 }),
@@ -231,27 +293,101 @@ impl<N> Range<N> where N: NodeRef {
         }
 
         enum Op<T> {
-            PopPush(Option<T>, bool),
+            PopPush(Option<T>, usize),
             Push(Option<T>),
         }
 
         let mut it = Iter::new(root, size);
 
-        bound!(it, cmp, min, Less, Greater, left, right, back_mut, pop_back, push_back);
-        bound!(it, cmp, max, Greater, Less, right, left, front_mut, pop_front, push_front);
+        bound!(it, cmp, min, Less, Greater, left, right, left_size, back_mut, pop_back, push_back);
+        it.rank = size - it.size;
+        bound!(it, cmp, max, Greater, Less, right, left, right_size, front_mut, pop_front,
+               push_front);
 
         Range(it)
     }
+
+    pub fn rank(&self) -> usize { self.0.rank }
+}
+
+#[cfg(feature = "range")]
+impl<N> Range<N> where N: NodeRef + Copy {
+    pub fn peek(&mut self) -> Option<N::Item> { self.0.peek() }
+    pub fn peek_back(&mut self) -> Option<N::Item> { self.0.peek_back() }
 }
 
 #[cfg(feature = "range")]
 impl<N> Iterator for Range<N> where N: NodeRef {
     type Item = N::Item;
     fn next(&mut self) -> Option<N::Item> { self.0.next() }
-    fn size_hint(&self) -> (usize, Option<usize>) { (self.0.nodes.len(), Some(self.0.size)) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
 }
 
 #[cfg(feature = "range")]
 impl<N> DoubleEndedIterator for Range<N> where N: NodeRef {
     fn next_back(&mut self) -> Option<N::Item> { self.0.next_back() }
 }
+
+#[cfg(feature = "range")]
+impl<N> ExactSizeIterator for Range<N> where N: NodeRef {
+    fn len(&self) -> usize { self.0.size }
+}
+
+// An iterator over the entries of two iterators that each yield entries in strictly ascending
+// order of their keys, yielding the entries of both in ascending order and pairing up the values
+// of keys that compare equal, in which case the left key is kept
+pub struct Merge<'a, K, L, R, C: 'a + ?Sized> where L: Iterator, R: Iterator {
+    l: Peekable<L>,
+    r: Peekable<R>,
+    cmp: &'a C,
+    _marker: PhantomData<fn(&K)>,
+}
+
+impl<'a, K, L, R, C: ?Sized> Merge<'a, K, L, R, C> where L: Iterator, R: Iterator {
+    pub fn new(l: L, r: R, cmp: &'a C) -> Self {
+        Merge { l: l.peekable(), r: r.peekable(), cmp: cmp, _marker: PhantomData }
+    }
+
+    // Return the number of entries remaining in each iterator
+    pub fn lens(&self) -> (usize, usize) where L: ExactSizeIterator, R: ExactSizeIterator {
+        (self.l.len(), self.r.len())
+    }
+}
+
+impl<'a, K, L, R, C: ?Sized> Clone for Merge<'a, K, L, R, C>
+    where L: Iterator + Clone, R: Iterator + Clone, L::Item: Clone, R::Item: Clone {
+
+    fn clone(&self) -> Self {
+        Merge { l: self.l.clone(), r: self.r.clone(), cmp: self.cmp, _marker: PhantomData }
+    }
+}
+
+impl<'a, K, A, V, W, L, R, C: ?Sized> Iterator for Merge<'a, K, L, R, C>
+    where L: Iterator<Item=(A, V)>, R: Iterator<Item=(A, W)>, A: Borrow<K>, C: Compare<K> {
+
+    type Item = (A, EitherOrBoth<V, W>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = match (self.l.peek(), self.r.peek()) {
+            (Some(a), Some(b)) => self.cmp.compare(a.0.borrow(), b.0.borrow()),
+            (Some(_), None) => Less,
+            (None, Some(_)) => Greater,
+            (None, None) => return None,
+        };
+
+        Some(match order {
+            Less => { let (key, l) = self.l.next().unwrap(); (key, EitherOrBoth::Left(l)) }
+            Equal => {
+                let (key, l) = self.l.next().unwrap();
+                (key, EitherOrBoth::Both(l, self.r.next().unwrap().1))
+            }
+            Greater => { let (key, r) = self.r.next().unwrap(); (key, EitherOrBoth::Right(r)) }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (l_min, l_max) = self.l.size_hint();
+        let (r_min, r_max) = self.r.size_hint();
+        (cmp::max(l_min, r_min), l_max.and_then(|l| r_max.and_then(|r| l.checked_add(r))))
+    }
+}
@@ -1,15 +1,21 @@
 mod iter;
 
+pub mod check;
+
 #[cfg(test)]
 mod test;
 
 use compare::Compare;
 use self::build::{Build, PathBuilder};
+use std::cmp;
 use std::cmp::Ordering::*;
+#[cfg(feature = "range")] use std::collections::Bound;
+use std::fmt::{self, Debug};
 use std::mem::{self, replace, swap};
-use super::map::Entry;
+use super::map::{Entry, Shape};
+use super::stats::{self, Counters};
 
-pub use self::iter::{Iter, MarkedNode, MutMarkedNode};
+pub use self::iter::{Iter, MarkedNode, Merge, MutMarkedNode};
 #[cfg(feature = "range")] pub use self::iter::Range;
 
 pub type Link<K, V> = Option<Box<Node<K, V>>>;
@@ -19,16 +25,53 @@ pub struct Node<K, V> {
     left: Link<K, V>,
     right: Link<K, V>,
     level: usize,
+    size: usize,
     key: K,
     value: V,
 }
 
+impl<K, V> Debug for Node<K, V> where K: Debug, V: Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("key", &self.key)
+            .field("value", &self.value)
+            .field("level", &self.level)
+            .field("left", &self.left)
+            .field("right", &self.right)
+            .finish()
+    }
+}
+
 impl<K, V> Node<K, V> {
     fn new(key: K, value: V) -> Self {
-        Node { left: None, right: None, level: 1, key: key, value: value }
+        Node { left: None, right: None, level: 1, size: 1, key: key, value: value }
+    }
+
+    // Recompute the number of entries in the node's subtree from those of its children
+    fn update_size(&mut self) { self.size = size(&self.left) + 1 + size(&self.right); }
+
+    // Return the levels of the node and of its right child, which are all that the rebalancing of
+    // the node's parent after an insertion depends on
+    fn levels(&self) -> (usize, usize) { (self.level, self.right.as_ref().map_or(0, |x| x.level)) }
+
+    // Restore the invariants at the node after an entry has been inserted into one of its
+    // subtrees, given the node's levels from before the insertion
+    //
+    // Once the node's levels are unchanged, every ancestor of the node still satisfies the
+    // invariants, so `settled` is set and the ancestors only have their sizes updated.
+    fn fix_insert(node: &mut Box<Self>, levels: (usize, usize), settled: &mut bool) {
+        node.update_size();
+
+        if !*settled {
+            Node::skew(node);
+            Node::split(node);
+            *settled = node.levels() == levels;
+        }
     }
 
     fn rebalance(node: &mut Box<Self>) {
+        node.update_size();
+
         let left_level = node.left.as_ref().map_or(0, |node| node.level);
         let right_level = node.right.as_ref().map_or(0, |node| node.level);
 
@@ -60,8 +103,12 @@ impl<K, V> Node<K, V> {
         if node.left.as_ref().map_or(false, |x| x.level == node.level) {
             let mut save = node.left.take().unwrap();
             swap(&mut node.left, &mut save.right); // save.right now None
+            node.update_size();
             swap(node, &mut save);
             node.right = Some(save);
+            node.update_size();
+            stats::rotation();
+            event!("skew: level={}", node.level);
         }
     }
 
@@ -75,14 +122,38 @@ impl<K, V> Node<K, V> {
             let mut save = node.right.take().unwrap();
             swap(&mut node.right, &mut save.left); // save.left now None
             save.level += 1;
+            node.update_size();
             swap(node, &mut save);
             node.left = Some(save);
+            node.update_size();
+            stats::rotation();
+            event!("split: level={}", node.level);
         }
     }
 }
 
-pub fn insert<K, V, C>(link: &mut Link<K, V>, cmp: &C, key: K, value: V) -> Option<V>
-    where C: Compare<K> {
+// Return the number of entries in the tree
+pub fn size<K, V>(link: &Link<K, V>) -> usize { link.as_ref().map_or(0, |node| node.size) }
+
+// Check if an operand of the given size is small enough that looking up each of its entries in an
+// operand of the given larger size is cheaper than merging them
+pub fn is_small(small: usize, large: usize) -> bool {
+    let log = (0usize.leading_zeros() - large.leading_zeros()) as usize;
+    small.saturating_mul(log) < large
+}
+
+// Insert an entry into the tree, returning the key and value that were replaced, if any
+//
+// If the tree already contains an equal key, its value is replaced and, if `replace_key` is
+// `true`, so is the key itself. Otherwise, the given key is returned along with the old value.
+pub fn insert<K, V, C>(link: &mut Link<K, V>, cmp: &C, key: K, value: V, replace_key: bool)
+    -> Option<(K, V)> where C: Compare<K> {
+
+    insert_(link, cmp, key, value, replace_key, &mut false)
+}
+
+fn insert_<K, V, C>(link: &mut Link<K, V>, cmp: &C, key: K, value: V, replace_key: bool,
+                    settled: &mut bool) -> Option<(K, V)> where C: Compare<K> {
 
     match *link {
         None => {
@@ -90,19 +161,735 @@ pub fn insert<K, V, C>(link: &mut Link<K, V>, cmp: &C, key: K, value: V) -> Opti
             None
         }
         Some(ref mut node) => {
-            let old_value = match cmp.compare(&key, &node.key) {
-                Equal => return Some(mem::replace(&mut node.value, value)),
-                Less => insert(&mut node.left, cmp, key, value),
-                Greater => insert(&mut node.right, cmp, key, value),
+            let levels = node.levels();
+
+            let old = match cmp.compare(&key, &node.key) {
+                Equal => {
+                    let key = if replace_key { mem::replace(&mut node.key, key) } else { key };
+                    return Some((key, mem::replace(&mut node.value, value)));
+                }
+                Less => insert_(&mut node.left, cmp, key, value, replace_key, settled),
+                Greater => insert_(&mut node.right, cmp, key, value, replace_key, settled),
             };
 
-            Node::skew(node);
-            Node::split(node);
-            old_value
+            Node::fix_insert(node, levels, settled);
+            old
         },
     }
 }
 
+// Insert the given key with the result of `insert` if the tree does not contain an equal key, or
+// apply `update` to the equal key's value otherwise, returning `true` if the key was inserted
+pub fn upsert<K, V, C, I, U>(link: &mut Link<K, V>, cmp: &C, key: K, insert: I, update: U) -> bool
+    where C: Compare<K>, I: FnOnce() -> V, U: FnOnce(&mut V) {
+
+    upsert_(link, cmp, key, insert, update, &mut false)
+}
+
+fn upsert_<K, V, C, I, U>(link: &mut Link<K, V>, cmp: &C, key: K, insert: I, update: U,
+                          settled: &mut bool) -> bool
+    where C: Compare<K>, I: FnOnce() -> V, U: FnOnce(&mut V) {
+
+    match *link {
+        None => {
+            *link = Some(Box::new(Node::new(key, insert())));
+            true
+        }
+        Some(ref mut node) => {
+            let levels = node.levels();
+
+            let inserted = match cmp.compare(&key, &node.key) {
+                Equal => { update(&mut node.value); return false; }
+                Less => upsert_(&mut node.left, cmp, key, insert, update, settled),
+                Greater => upsert_(&mut node.right, cmp, key, insert, update, settled),
+            };
+
+            if inserted { Node::fix_insert(node, levels, settled); }
+            inserted
+        },
+    }
+}
+
+// Insert an entry whose key is greater than every key in the tree if `max` is `true`, or less than
+// every key in the tree otherwise, descending along the corresponding spine without comparing keys
+pub fn insert_extreme<K, V>(link: &mut Link<K, V>, key: K, value: V, max: bool) {
+    insert_extreme_(link, key, value, max, &mut false);
+}
+
+fn insert_extreme_<K, V>(link: &mut Link<K, V>, key: K, value: V, max: bool, settled: &mut bool) {
+    match *link {
+        None => *link = Some(Box::new(Node::new(key, value))),
+        Some(ref mut node) => {
+            let levels = node.levels();
+            let child = if max { &mut node.right } else { &mut node.left };
+            insert_extreme_(child, key, value, max, settled);
+            Node::fix_insert(node, levels, settled);
+        }
+    }
+}
+
+// Swap the values associated with the given keys, returning `false` without modifying the tree if
+// either key is not present
+//
+// The search descends while both keys lie on the same side of a node, and then borrows the
+// disjoint parts of the node in which they diverge, so that the two values are found through
+// separate borrows.
+pub fn swap_values<K, V, C: ?Sized, Q: ?Sized>(link: &mut Link<K, V>, cmp: &C, k1: &Q, k2: &Q)
+    -> bool where C: Compare<Q, K> {
+
+    let node = match *link {
+        None => return false,
+        Some(ref mut node) => &mut **node,
+    };
+
+    let orders = (cmp.compare(k1, &node.key), cmp.compare(k2, &node.key));
+
+    match orders {
+        (Less, Less) => return swap_values(&mut node.left, cmp, k1, k2),
+        (Equal, Equal) => return true,
+        (Greater, Greater) => return swap_values(&mut node.right, cmp, k1, k2),
+        _ => {}
+    }
+
+    let mut parts = (Some(&mut node.left), Some(&mut node.value), Some(&mut node.right));
+    let v1 = get_part(&mut parts, orders.0, cmp, k1);
+    let v2 = get_part(&mut parts, orders.1, cmp, k2);
+
+    match (v1, v2) {
+        (Some(v1), Some(v2)) => { mem::swap(v1, v2); true }
+        _ => false,
+    }
+}
+
+// The separately borrowed left subtree, value and right subtree of a node, each of which can be
+// taken once
+type Parts<'a, K, V> = (Option<&'a mut Link<K, V>>, Option<&'a mut V>, Option<&'a mut Link<K, V>>);
+
+// Take the part of the node on the given side of it, and return the value of the given key in it
+fn get_part<'a, K, V, C: ?Sized, Q: ?Sized>(parts: &mut Parts<'a, K, V>, order: cmp::Ordering,
+                                            cmp: &C, key: &Q) -> Option<&'a mut V>
+    where C: Compare<Q, K> {
+
+    let link = match order {
+        Less => parts.0.take(),
+        Equal => return parts.1.take(),
+        Greater => parts.2.take(),
+    };
+
+    find(link.unwrap(), build::GetMut::default(), cmp, key).map(|e| e.1)
+}
+
+// Insert an entry into the tree, replacing the value of an equal key, and return the occupied
+// entry for the key
+//
+// If the key is new, the path to it is rebuilt after rebalancing by searching for its rank, which
+// is found from the original path, so no key in the tree is read while it is mutably borrowed.
+pub fn insert_entry<'a, K, V, C: ?Sized>(root: &'a mut Link<K, V>, len: &'a mut usize,
+                                         counters: &'a Counters, cmp: &C, key: K, value: V)
+    -> OccupiedEntry<'a, K, V> where C: Compare<K> {
+
+    let root: *mut Link<K, V> = root;
+    let len: *mut usize = len;
+
+    let rank = unsafe {
+        let path = find(&mut *root, PathBuilder::default(), cmp, &key);
+
+        match path.into_entry(&mut *len, counters, key) {
+            Entry::Occupied(mut e) => { e.insert(value); return e; }
+            Entry::Vacant(e) => {
+                let rank = e.path.rank();
+                e.insert_node(value);
+                rank
+            }
+        }
+    };
+
+    unsafe {
+        find_rank(&mut *root, PathBuilder::default(), rank)
+            .into_occupied_entry(&mut *len, counters)
+            .unwrap()
+    }
+}
+
+// Build a balanced tree from the first `len` entries of the given iterator, which must yield
+// them in ascending order
+pub fn from_sorted<K, V, I>(it: &mut I, len: usize) -> Link<K, V>
+    where I: Iterator<Item=(K, V)> {
+
+    build_sorted(it, len, &mut |left, right, level, size, key, value| {
+        Box::new(Node {
+            left: left, right: right, level: level, size: size, key: key, value: value,
+        })
+    }).0
+}
+
+// Build a balanced tree from the first `len` entries of the given iterator, which must yield
+// them in ascending order, using `node` to make each node from its subtrees, level, size and
+// entry, and return the tree along with its level
+//
+// The left subtree of every node is never larger than its right subtree, which ensures that the
+// levels assigned here satisfy the Andersson tree invariants without any rotations.
+pub fn build_sorted<T, K, V, I, F>(it: &mut I, len: usize, node: &mut F) -> (Option<T>, usize)
+    where I: Iterator<Item=(K, V)>, F: FnMut(Option<T>, Option<T>, usize, usize, K, V) -> T {
+
+    if len == 0 { return (None, 0); }
+
+    let left_len = (len - 1) / 2;
+    let (left, left_level) = build_sorted(it, left_len, node);
+    let (key, value) = it.next().expect("iterator yielded fewer entries than expected");
+    let (right, _) = build_sorted(it, len - 1 - left_len, node);
+
+    let level = left_level + 1;
+    (Some(node(left, right, level, len, key, value)), level)
+}
+
+// Builds a balanced tree from entries that are supplied one at a time in ascending order
+//
+// Like a binary counter, the builder keeps a stack of perfect subtrees of strictly decreasing
+// height, each of which but possibly the last is followed by the node that will join it to the
+// next subtree of the same height, so pushing an entry takes amortized constant time. Building
+// then joins the remaining subtrees along the right spine of the tallest one, which takes
+// `O(log^2 n)` time.
+pub struct Builder<K, V> {
+    stack: Vec<(Box<Node<K, V>>, Option<Box<Node<K, V>>>)>,
+    len: usize,
+}
+
+impl<K, V> Builder<K, V> {
+    pub fn new() -> Self { Builder { stack: vec![], len: 0 } }
+
+    pub fn len(&self) -> usize { self.len }
+
+    // Return the most recently pushed key
+    //
+    // This descends the last subtree when it has no joining node, which takes amortized constant
+    // time over a sequence of pushes, since most of the stack's subtrees are short.
+    pub fn last(&self) -> Option<&K> {
+        self.stack.last().map(|&(ref tree, ref mid)| match *mid {
+            Some(ref mid) => &mid.key,
+            None => {
+                let mut node = tree;
+                while let Some(ref right) = node.right { node = right; }
+                &node.key
+            }
+        })
+    }
+
+    // Push an entry whose key is greater than every key pushed so far
+    pub fn push(&mut self, key: K, value: V) { self.push_node(Box::new(Node::new(key, value))); }
+
+    // Push a node detached from another tree, whose key is greater than every key pushed so far
+    fn push_node(&mut self, mut node: Box<Node<K, V>>) {
+        node.level = 1;
+        node.size = 1;
+        self.len += 1;
+
+        if let Some(last) = self.stack.last_mut() {
+            if last.1.is_none() { last.1 = Some(node); return; }
+        }
+
+        loop {
+            let joins = match self.stack.last() {
+                Some(&(ref tree, Some(_))) => tree.level == node.level,
+                _ => false,
+            };
+
+            if !joins { break; }
+
+            let (left, mid) = self.stack.pop().unwrap();
+            let mut mid = mid.unwrap();
+            mid.level = node.level + 1;
+            mid.left = Some(left);
+            mid.right = Some(node);
+            mid.update_size();
+            node = mid;
+        }
+
+        self.stack.push((node, None));
+    }
+
+    pub fn build(self) -> Link<K, V> {
+        let mut root = None;
+        let mut pending = None;
+
+        for (tree, mid) in self.stack {
+            root = match pending {
+                None => Some(tree),
+                Some(pending) => join(root, pending, Some(tree)),
+            };
+
+            pending = mid;
+        }
+
+        match pending {
+            None => root,
+            Some(pending) => join(root, pending, None),
+        }
+    }
+}
+
+fn level<K, V>(link: &Link<K, V>) -> usize { link.as_ref().map_or(0, |node| node.level) }
+
+// Join two trees and a node whose key lies between theirs into a single tree
+//
+// The node is placed on the inner spine of the taller tree, at the level of the shorter tree, and
+// the nodes above it are rebalanced as they would be after an insertion, which takes time
+// proportional to the difference between the trees' levels.
+fn join<K, V>(left: Link<K, V>, mut mid: Box<Node<K, V>>, right: Link<K, V>) -> Link<K, V> {
+    let (left_level, right_level) = (level(&left), level(&right));
+
+    if left_level == right_level {
+        mid.left = left;
+        mid.right = right;
+        mid.level = left_level + 1;
+        mid.update_size();
+        Some(mid)
+    } else if left_level > right_level {
+        let mut left = left;
+        join_right(left.as_mut().unwrap(), mid, right);
+        left
+    } else {
+        let mut right = right;
+        join_left(left, mid, right.as_mut().unwrap());
+        right
+    }
+}
+
+// Join the given node and shorter tree onto the right spine of the given node's tree
+fn join_right<K, V>(node: &mut Box<Node<K, V>>, mid: Box<Node<K, V>>, right: Link<K, V>) {
+    if level(&node.right) == level(&right) {
+        let left = node.right.take();
+        node.right = join(left, mid, right);
+    } else {
+        join_right(node.right.as_mut().unwrap(), mid, right);
+    }
+
+    node.update_size();
+    Node::skew(node);
+    Node::split(node);
+}
+
+// Join the given shorter tree and node onto the left spine of the given node's tree
+fn join_left<K, V>(left: Link<K, V>, mid: Box<Node<K, V>>, node: &mut Box<Node<K, V>>) {
+    if level(&node.left) == level(&left) {
+        let right = node.left.take();
+        node.left = join(left, mid, right);
+    } else {
+        join_left(left, mid, node.left.as_mut().unwrap());
+    }
+
+    node.update_size();
+    Node::skew(node);
+    Node::split(node);
+}
+
+// Split the tree into the entries whose keys are less than the given key, the detached node whose
+// key is equal to it, if any, and the entries whose keys are greater than it
+fn split_at<K, V, C: ?Sized>(link: Link<K, V>, cmp: &C, key: &K)
+    -> (Link<K, V>, Option<Box<Node<K, V>>>, Link<K, V>) where C: Compare<K> {
+
+    let mut node = match link {
+        None => return (None, None, None),
+        Some(node) => node,
+    };
+
+    let (left, right) = (node.left.take(), node.right.take());
+
+    match cmp.compare(key, &node.key) {
+        Less => {
+            let (left, found, mid) = split_at(left, cmp, key);
+            (left, found, join(mid, node, right))
+        }
+        Equal => (left, Some(node), right),
+        Greater => {
+            let (mid, found, right) = split_at(right, cmp, key);
+            (join(left, node, mid), found, right)
+        }
+    }
+}
+
+// Split the tree into the entries for whose keys the given predicate returns `true` and the rest
+//
+// The predicate must return `true` for all entries before some entry and `false` for that entry
+// and all entries after it.
+pub fn split_by<K, V, F>(link: Link<K, V>, pred: &mut F) -> (Link<K, V>, Link<K, V>)
+    where F: FnMut(&K) -> bool {
+
+    let mut node = match link {
+        None => return (None, None),
+        Some(node) => node,
+    };
+
+    let (left, right) = (node.left.take(), node.right.take());
+
+    if pred(&node.key) {
+        let (mid, right) = split_by(right, pred);
+        (join(left, node, mid), right)
+    } else {
+        let (left, mid) = split_by(left, pred);
+        (left, join(mid, node, right))
+    }
+}
+
+// Return the union of the trees, keeping the key of one tree and the value of the other for keys
+// in both: the key of `large` and the value of `small` if `large_key` is `true`, and the reverse
+// otherwise
+//
+// `large` is split at each of the keys in `small`, which takes `O(m log(n/m + 1))` time when
+// `small` has `m` entries and `large` has `n`. The trees' nodes are reused.
+pub fn union<K, V, C: ?Sized>(large: Link<K, V>, small: Link<K, V>, cmp: &C, large_key: bool)
+    -> Link<K, V> where C: Compare<K> {
+
+    let mut node = match small {
+        None => return large,
+        Some(node) => node,
+    };
+
+    if large.is_none() { return Some(node); }
+
+    let (small_left, small_right) = (node.left.take(), node.right.take());
+    let (left, found, right) = split_at(large, cmp, &node.key);
+    let left = union(left, small_left, cmp, large_key);
+    let right = union(right, small_right, cmp, large_key);
+
+    let node = match found {
+        None => node,
+        Some(mut found) => {
+            swap(&mut found.value, &mut node.value);
+            if large_key { found } else { node }
+        }
+    };
+
+    join(left, node, right)
+}
+
+// Map the values of the tree in ascending order of their keys, preserving its shape
+pub fn map_values<K, V, U, F>(link: Link<K, V>, f: &mut F) -> Link<K, U>
+    where F: FnMut(&K, V) -> U {
+
+    link.map(|node| {
+        let node = *node;
+        let left = map_values(node.left, f);
+        let value = f(&node.key, node.value);
+        let right = map_values(node.right, f);
+        Box::new(Node {
+            left: left,
+            right: right,
+            level: node.level,
+            size: node.size,
+            key: node.key,
+            value: value,
+        })
+    })
+}
+
+// Partition the tree's nodes into the trees built by two builders according to whether the given
+// predicate returns `true` for their entries, applying it in ascending order of their keys
+//
+// The nodes are moved rather than reallocated.
+pub fn partition<K, V, F>(link: Link<K, V>, pred: &mut F, pass: &mut Builder<K, V>,
+                          fail: &mut Builder<K, V>) where F: FnMut(&K, &V) -> bool {
+
+    if let Some(mut node) = link {
+        partition(node.left.take(), pred, pass, fail);
+        let right = node.right.take();
+        if pred(&node.key, &node.value) { pass.push_node(node); } else { fail.push_node(node); }
+        partition(right, pred, pass, fail);
+    }
+}
+
+// Apply the given function to the entries of the tree in ascending order of their keys, stopping
+// at the first error
+pub fn try_for_each<K, V, E, F>(link: &Link<K, V>, f: &mut F) -> Result<(), E>
+    where F: FnMut(&K, &V) -> Result<(), E> {
+
+    match *link {
+        None => Ok(()),
+        Some(ref node) => {
+            try_for_each(&node.left, f)?;
+            f(&node.key, &node.value)?;
+            try_for_each(&node.right, f)
+        }
+    }
+}
+
+// Like `try_for_each`, but with mutable access to the values
+pub fn try_for_each_mut<K, V, E, F>(link: &mut Link<K, V>, f: &mut F) -> Result<(), E>
+    where F: FnMut(&K, &mut V) -> Result<(), E> {
+
+    match *link {
+        None => Ok(()),
+        Some(ref mut node) => {
+            try_for_each_mut(&mut node.left, f)?;
+            f(&node.key, &mut node.value)?;
+            try_for_each_mut(&mut node.right, f)
+        }
+    }
+}
+
+// Fold the entries of the tree whose keys lie in the given range in ascending order of their
+// keys, visiting only those subtrees that may contain such entries
+//
+// Once a node is known to lie above the minimum, so does its entire right subtree, so the minimum
+// is dropped when descending into it, and likewise for the maximum and the left subtree.
+#[cfg(feature = "range")]
+pub fn fold_range<K, V, C: ?Sized, Min: ?Sized, Max: ?Sized, B, F>(link: &Link<K, V>, cmp: &C,
+                                                                    min: Bound<&Min>,
+                                                                    max: Bound<&Max>, init: B,
+                                                                    f: &mut F) -> B
+    where C: Compare<Min, K> + Compare<Max, K>, F: FnMut(B, &K, &V) -> B {
+
+    let node = match *link {
+        None => return init,
+        Some(ref node) => node,
+    };
+
+    let above_min = match min {
+        Bound::Unbounded => true,
+        Bound::Included(min) => cmp.compares_le(min, &node.key),
+        Bound::Excluded(min) => cmp.compares_lt(min, &node.key),
+    };
+
+    let below_max = match max {
+        Bound::Unbounded => true,
+        Bound::Included(max) => cmp.compares_ge(max, &node.key),
+        Bound::Excluded(max) => cmp.compares_gt(max, &node.key),
+    };
+
+    let mut acc = init;
+
+    if above_min {
+        let max = if below_max { Bound::Unbounded } else { max };
+        acc = fold_range(&node.left, cmp, min, max, acc, f);
+    }
+
+    if above_min && below_max { acc = f(acc, &node.key, &node.value); }
+
+    if below_max {
+        let min = if above_min { Bound::Unbounded } else { min };
+        acc = fold_range(&node.right, cmp, min, max, acc, f);
+    }
+
+    acc
+}
+
+// Return the number of nodes on the longest path from the root of the tree to a leaf
+pub fn height<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| 1 + cmp::max(height(&node.left), height(&node.right)))
+}
+
+// Return the number of nodes on the shortest path from the root of the tree to a leaf
+pub fn min_depth<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| 1 + match (&node.left, &node.right) {
+        (&None, right) => min_depth(right),
+        (left, &None) => min_depth(left),
+        (left, right) => cmp::min(min_depth(left), min_depth(right)),
+    })
+}
+
+// Return the sum of the depths of the tree's nodes, where the root of the tree has the given depth
+pub fn total_depth<K, V>(link: &Link<K, V>, depth: usize) -> usize {
+    link.as_ref().map_or(0, |node| {
+        depth + total_depth(&node.left, depth + 1) + total_depth(&node.right, depth + 1)
+    })
+}
+
+// Return the structure of the tree
+pub fn to_shape<K, V>(link: &Link<K, V>) -> Option<Shape<K, V>> where K: Clone, V: Clone {
+    link.as_ref().map(|node| Shape {
+        key: node.key.clone(),
+        value: node.value.clone(),
+        level: node.level,
+        left: to_shape(&node.left).map(Box::new),
+        right: to_shape(&node.right).map(Box::new),
+    })
+}
+
+// Build a tree with the given structure, which is not validated
+pub fn from_shape<K, V>(shape: Option<Shape<K, V>>) -> Link<K, V> {
+    shape.map(|shape| {
+        let mut node = Box::new(Node {
+            left: from_shape(shape.left.map(|left| *left)),
+            right: from_shape(shape.right.map(|right| *right)),
+            level: shape.level,
+            size: 0,
+            key: shape.key,
+            value: shape.value,
+        });
+
+        node.update_size();
+        node
+    })
+}
+
+// Return the number of entries in the tree whose keys are less than the given key, and whether
+// the tree contains the key
+pub fn rank<K, V, C: ?Sized, Q: ?Sized>(mut link: &Link<K, V>, cmp: &C, key: &Q) -> (usize, bool)
+    where C: Compare<Q, K> {
+
+    let mut rank = 0;
+
+    while let Some(ref node) = *link {
+        link = match cmp.compare(key, &node.key) {
+            Less => &node.left,
+            Equal => return (rank + size(&node.left), true),
+            Greater => { rank += size(&node.left) + 1; &node.right }
+        };
+    }
+
+    (rank, false)
+}
+
+// Return the number of entries in the tree whose keys are less than the given key, also counting
+// those that compare equal to it if `inclusive` is `true`
+//
+// Unlike `rank`, this does not stop at the first equal key, so it counts every key that a coarse
+// comparator compares equal to the given one.
+#[cfg(feature = "range")]
+pub fn bound_rank<K, V, C: ?Sized, Q: ?Sized>(link: &Link<K, V>, cmp: &C, key: &Q, inclusive: bool)
+    -> usize where C: Compare<Q, K> {
+
+    partition_point(link, |_, k| match cmp.compare(key, k) {
+        Less => false,
+        Equal => inclusive,
+        Greater => true,
+    })
+}
+
+// Return the entry with the given rank, which is the number of entries whose keys are less than
+// its key
+pub fn select<K, V>(mut link: &Link<K, V>, mut rank: usize) -> Option<(&K, &V)> {
+    while let Some(ref node) = *link {
+        let left_size = size(&node.left);
+
+        link = match rank.cmp(&left_size) {
+            Less => &node.left,
+            Equal => return Some((&node.key, &node.value)),
+            Greater => { rank -= left_size + 1; &node.right }
+        };
+    }
+
+    None
+}
+
+// Return the rank of the first entry for which the given predicate, called with each visited
+// entry's rank and key, returns `false`, or the size of the tree if there is no such entry
+//
+// The predicate must return `true` for all entries before that entry and `false` for all entries
+// after it.
+pub fn partition_point<K, V, F>(mut link: &Link<K, V>, mut pred: F) -> usize
+    where F: FnMut(usize, &K) -> bool {
+
+    let mut offset = 0;
+
+    while let Some(ref node) = *link {
+        let rank = offset + size(&node.left);
+
+        link = if pred(rank, &node.key) {
+            offset = rank + 1;
+            &node.right
+        } else {
+            &node.left
+        };
+    }
+
+    offset
+}
+
+// An iterator over a given number of the tree's entries in ascending order, starting at the
+// entry with a given rank
+pub struct Select<'a, K: 'a, V: 'a> {
+    stack: Vec<&'a Node<K, V>>,
+    size: usize,
+    rank: usize,
+}
+
+impl<'a, K, V> Select<'a, K, V> {
+    pub fn new(mut link: &'a Link<K, V>, start: usize, size: usize) -> Self {
+        let mut stack = vec![];
+        let mut rank = start;
+
+        while let Some(ref node) = *link {
+            let left_size = self::size(&node.left);
+
+            link = match rank.cmp(&left_size) {
+                Less => { stack.push(&**node); &node.left }
+                Equal => { stack.push(&**node); break; }
+                Greater => { rank -= left_size + 1; &node.right }
+            };
+        }
+
+        Select { stack: stack, size: size, rank: start }
+    }
+
+    pub fn rank(&self) -> usize { self.rank }
+}
+
+impl<'a, K, V> Clone for Select<'a, K, V> {
+    fn clone(&self) -> Self {
+        Select { stack: self.stack.clone(), size: self.size, rank: self.rank }
+    }
+}
+
+impl<'a, K, V> Iterator for Select<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.size == 0 { return None; }
+
+        self.stack.pop().map(|node| {
+            self.size -= 1;
+            self.rank += 1;
+
+            let mut link = &node.right;
+
+            while let Some(ref node) = *link {
+                self.stack.push(node);
+                link = &node.left;
+            }
+
+            (&node.key, &node.value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.size, Some(self.size)) }
+}
+
+// An iterator over the nodes of a tree in pre-order, yielding the depth, key, and level of each
+#[cfg(feature = "stats")]
+pub struct Nodes<'a, K: 'a, V: 'a> {
+    stack: Vec<(usize, &'a Node<K, V>)>,
+    size: usize,
+}
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> Nodes<'a, K, V> {
+    pub fn new(root: &'a Link<K, V>, size: usize) -> Self {
+        Nodes { stack: root.as_ref().map(|node| (1, &**node)).into_iter().collect(), size: size }
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> Clone for Nodes<'a, K, V> {
+    fn clone(&self) -> Self { Nodes { stack: self.stack.clone(), size: self.size } }
+}
+
+#[cfg(feature = "stats")]
+impl<'a, K, V> Iterator for Nodes<'a, K, V> {
+    type Item = (usize, &'a K, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stack.pop().map(|(depth, node)| {
+            self.size -= 1;
+            if let Some(ref right) = node.right { self.stack.push((depth + 1, right)); }
+            if let Some(ref left) = node.left { self.stack.push((depth + 1, left)); }
+            (depth, &node.key, node.level)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.size, Some(self.size)) }
+}
+
 pub mod build {
     use std::marker::PhantomData;
     use super::{Link, Node, Path};
@@ -262,6 +1049,29 @@ pub fn find<'a, B, C: ?Sized, Q: ?Sized>(mut link: B::Link, mut build: B, cmp: &
     }
 }
 
+// Build the output for the entry with the given rank, or for the vacant link at which the search
+// ends if the tree does not contain that many entries
+pub fn find_rank<'a, B>(mut link: B::Link, mut build: B, mut rank: usize) -> B::Output
+    where B: Build<'a> {
+
+    loop {
+        let closed = B::closed(&link);
+
+        link = match B::into_option(link) {
+            None => return build.build_closed(closed),
+            Some(node) => {
+                let left_size = size(&node.left);
+
+                match rank.cmp(&left_size) {
+                    Less => build.left(node),
+                    Equal => return build.build_closed(closed),
+                    Greater => { rank -= left_size + 1; build.right(node) }
+                }
+            }
+        };
+    }
+}
+
 pub trait Extreme: Sized {
     type Opposite: Extreme<Opposite = Self>;
 
@@ -355,22 +1165,66 @@ pub struct Path<'a, K: 'a, V: 'a> {
 }
 
 impl<'a, K, V> Path<'a, K, V> {
-    pub fn into_entry(self, len: &'a mut usize, key: K) -> Entry<'a, K, V> {
+    pub fn into_entry(self, len: &'a mut usize, counters: &'a Counters, key: K)
+        -> Entry<'a, K, V> {
+
         if self.link.is_some() {
-            Entry::Occupied(OccupiedEntry { path: self, len: len })
+            Entry::Occupied(OccupiedEntry { path: self, len: len, counters: counters })
         } else {
-            Entry::Vacant(VacantEntry { path: self, len: len, key: key })
+            Entry::Vacant(VacantEntry { path: self, len: len, counters: counters, key: key })
         }
     }
 
-    pub fn into_occupied_entry(self, len: &'a mut usize) -> Option<OccupiedEntry<'a, K, V>> {
+    pub fn into_occupied_entry(self, len: &'a mut usize, counters: &'a Counters)
+        -> Option<OccupiedEntry<'a, K, V>> {
+
         if self.link.is_some() {
-            Some(OccupiedEntry { path: self, len: len })
+            Some(OccupiedEntry { path: self, len: len, counters: counters })
         } else {
             None
         }
     }
 
+    // Return the number of entries whose keys are less than that of the path's link, which must
+    // be vacant
+    fn rank(&self) -> usize {
+        let link: *const Link<K, V> = &*self.link;
+        let mut rank = 0;
+
+        unsafe {
+            for (i, &node) in self.path.iter().enumerate() {
+                let right: *const Link<K, V> = &(*node).right;
+
+                let went_right = match self.path.get(i + 1) {
+                    Some(&next) => (*right).as_ref().map_or(false, |r| r as *const _ == next),
+                    None => right == link,
+                };
+
+                if went_right { rank += size(&(*node).left) + 1; }
+            }
+        }
+
+        rank
+    }
+
+    // Extend the path from its occupied link to the vacant link that immediately precedes it in
+    // the tree's order if `before` is `true`, or that immediately follows it otherwise
+    fn into_adjacent(self, before: bool) -> Self {
+        let mut path = self.path;
+        let mut link: *mut Link<K, V> = self.link;
+        let mut left = before;
+
+        unsafe {
+            while let Some(ref mut node) = *link {
+                path.push(node);
+                link = if left { &mut node.left } else { &mut node.right };
+                left = !before;
+            }
+
+            Path { path: path, link: &mut *link }
+        }
+    }
+
     fn remove_(self) -> Option<(K, V)> {
         let key_value = match *self.link {
             None => return None,
@@ -398,7 +1252,12 @@ impl<'a, K, V> Path<'a, K, V> {
 
     pub fn remove(self, len: &mut usize) -> Option<(K, V)> {
         let key_value = self.remove_();
-        if key_value.is_some() { *len -= 1; }
+
+        if key_value.is_some() {
+            *len -= 1;
+            event!("remove: len={}", *len);
+        }
+
         key_value
     }
 }
@@ -412,6 +1271,7 @@ unsafe impl<'a, K, V> Sync for Path<'a, K, V> where K: Sync, V: Sync {}
 pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
     path: Path<'a, K, V>,
     len: &'a mut usize,
+    counters: &'a Counters,
 }
 
 impl<'a, K, V> OccupiedEntry<'a, K, V> {
@@ -427,12 +1287,84 @@ impl<'a, K, V> OccupiedEntry<'a, K, V> {
     /// Returns a mutable reference to the entry's value with the same lifetime as the map.
     pub fn into_mut(self) -> &'a mut V { &mut self.path.link.as_mut().unwrap().value }
 
+    /// Returns references to the entry's key and value.
+    pub fn get_key_value(&self) -> (&K, &V) {
+        let node = self.path.link.as_ref().unwrap();
+        (&node.key, &node.value)
+    }
+
+    /// Returns a reference to the entry's key and a mutable reference to its value.
+    pub fn get_key_value_mut(&mut self) -> (&K, &mut V) {
+        let node = &mut **self.path.link.as_mut().unwrap();
+        (&node.key, &mut node.value)
+    }
+
+    /// Returns a reference to the entry's key and a mutable reference to its value with the same
+    /// lifetime as the map.
+    pub fn into_key_value_mut(self) -> (&'a K, &'a mut V) {
+        let node = &mut **self.path.link.as_mut().unwrap();
+        (&node.key, &mut node.value)
+    }
+
     /// Replaces the entry's value with the given value, returning the old one.
     pub fn insert(&mut self, value: V) -> V { replace(self.get_mut(), value) }
 
     /// Removes the entry from the map and returns its key and value.
     pub fn remove(self) -> (K, V) {
-        self.path.remove(self.len).unwrap()
+        let (path, len) = (self.path, self.len);
+        self.counters.rebalance(|| path.remove(len)).unwrap()
+    }
+
+    /// Inserts an entry into the map immediately before this one in the map's order, without
+    /// comparing any keys, and returns a mutable reference to its value with the same lifetime as
+    /// the map.
+    ///
+    /// This is useful for loading keys whose positions are already known, such as by
+    /// [`Map::succ_entry`](struct.Map.html#method.succ_entry), because the insertion skips the
+    /// search from the root.
+    ///
+    /// # Safety
+    ///
+    /// The given key must be greater than the key of the entry's predecessor, if any, and less
+    /// than the entry's key according to the map's comparator. This is not checked. Otherwise,
+    /// the map's keys will be out of order, and the map's subsequent operations may panic, fail to
+    /// find its keys, or corrupt it further.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    /// map.insert(1, "a");
+    /// map.insert(4, "d");
+    ///
+    /// for &(key, value) in &[(2, "b"), (3, "c")] {
+    ///     let e = map.succ_entry(&key, false).unwrap();
+    ///     unsafe { e.insert_before(key, value); }
+    /// }
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(),
+    ///     [(&1, &"a"), (&2, &"b"), (&3, &"c"), (&4, &"d")]);
+    /// ```
+    pub unsafe fn insert_before(self, key: K, value: V) -> &'a mut V {
+        let path = self.path.into_adjacent(true);
+        VacantEntry { path: path, len: self.len, counters: self.counters, key: key }.insert(value)
+    }
+
+    /// Inserts an entry into the map immediately after this one in the map's order, without
+    /// comparing any keys, and returns a mutable reference to its value with the same lifetime as
+    /// the map.
+    ///
+    /// See [`insert_before`](#method.insert_before).
+    ///
+    /// # Safety
+    ///
+    /// The given key must be greater than the entry's key and less than the key of the entry's
+    /// successor, if any, according to the map's comparator. This is not checked. Otherwise, the
+    /// map's keys will be out of order, and the map's subsequent operations may panic, fail to find
+    /// its keys, or corrupt it further.
+    pub unsafe fn insert_after(self, key: K, value: V) -> &'a mut V {
+        let path = self.path.into_adjacent(false);
+        VacantEntry { path: path, len: self.len, counters: self.counters, key: key }.insert(value)
     }
 }
 
@@ -442,25 +1374,43 @@ impl<'a, K, V> OccupiedEntry<'a, K, V> {
 pub struct VacantEntry<'a, K: 'a, V: 'a> {
     path: Path<'a, K, V>,
     len: &'a mut usize,
+    counters: &'a Counters,
     key: K,
 }
 
 impl<'a, K, V> VacantEntry<'a, K, V> {
+    /// Returns a reference to the key that would be used when inserting the entry.
+    pub fn key(&self) -> &K { &self.key }
+
+    /// Returns the key that would be used when inserting the entry, without inserting it.
+    pub fn into_key(self) -> K { self.key }
+
     /// Inserts the entry into the map with its key and the given value, returning a mutable
     /// reference to the value with the same lifetime as the map.
-    pub fn insert(self, value: V) -> &'a mut V {
+    pub fn insert(self, value: V) -> &'a mut V { unsafe { &mut (*self.insert_node(value)).value } }
+
+    // Insert the entry as `insert` does, returning a pointer to its node, which is valid for the
+    // lifetime of the map unless the entry is removed
+    fn insert_node(self, value: V) -> *mut Node<K, V> {
         *self.len += 1;
+        event!("insert: len={}", *self.len);
 
-        *self.path.link = Some(Box::new(Node::new(self.key, value)));
-        let value = &mut self.path.link.as_mut().unwrap().value;
+        self.counters.allocation();
 
-        for node in self.path.path.into_iter().rev() {
-            unsafe {
-                Node::skew(&mut *node);
-                Node::split(&mut *node);
+        *self.path.link = Some(Box::new(Node::new(self.key, value)));
+        let node: *mut Node<K, V> = &mut **self.path.link.as_mut().unwrap();
+        let path = self.path.path;
+
+        self.counters.rebalance(|| {
+            for node in path.into_iter().rev() {
+                unsafe {
+                    (*node).update_size();
+                    Node::skew(&mut *node);
+                    Node::split(&mut *node);
+                }
             }
-        }
+        });
 
-        value
+        node
     }
 }
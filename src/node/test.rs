@@ -1,8 +1,10 @@
 extern crate quickcheck;
 
+use cmp::Natural;
 use self::quickcheck::{Arbitrary, Gen, Rng, TestResult, quickcheck};
 use super::{Link, Node};
 use Map;
+use map;
 
 /// An operation on a `Map`.
 #[derive(Clone, Debug)]
@@ -19,17 +21,32 @@ enum Op<K> where K: Clone + Ord {
     EntryInsert(K),
     /// Remove the key at index `n % map.len()` from the map using the entry API.
     EntryRemove(usize),
+    /// Insert a key into the map with `insert_max` if it is greater than the maximum key.
+    InsertMax(K),
+    /// Insert a key into the map with `insert_min` if it is less than the minimum key.
+    InsertMin(K),
+    /// Insert a key into the map next to its successor or predecessor using the entry API.
+    EntryInsertAdjacent(K),
+    /// Insert a key into the map with `insert_entry`, checking the returned entry's key.
+    InsertEntry(K),
+    /// Insert a key into the map with `upsert`.
+    Upsert(K),
 }
 
 impl<K> Arbitrary for Op<K> where K: Arbitrary + Ord {
     fn arbitrary<G: Gen>(gen: &mut G) -> Self {
-        match gen.gen_range(0, 6) {
+        match gen.gen_range(0, 11) {
             0 => Op::Insert(K::arbitrary(gen)),
             1 => Op::Remove(usize::arbitrary(gen)),
             2 => Op::RemoveMax,
             3 => Op::RemoveMin,
             4 => Op::EntryInsert(K::arbitrary(gen)),
-            _ => Op::EntryRemove(usize::arbitrary(gen)),
+            5 => Op::EntryRemove(usize::arbitrary(gen)),
+            6 => Op::InsertMax(K::arbitrary(gen)),
+            7 => Op::InsertMin(K::arbitrary(gen)),
+            8 => Op::EntryInsertAdjacent(K::arbitrary(gen)),
+            9 => Op::InsertEntry(K::arbitrary(gen)),
+            _ => Op::Upsert(K::arbitrary(gen)),
         }
     }
 
@@ -40,6 +57,12 @@ impl<K> Arbitrary for Op<K> where K: Arbitrary + Ord {
             Op::RemoveMax | Op::RemoveMin => Box::new(None.into_iter()),
             Op::EntryInsert(ref key) => Box::new(key.shrink().map(Op::EntryInsert)),
             Op::EntryRemove(index) => Box::new(index.shrink().map(Op::EntryRemove)),
+            Op::InsertMax(ref key) => Box::new(key.shrink().map(Op::InsertMax)),
+            Op::InsertMin(ref key) => Box::new(key.shrink().map(Op::InsertMin)),
+            Op::EntryInsertAdjacent(ref key) =>
+                Box::new(key.shrink().map(Op::EntryInsertAdjacent)),
+            Op::InsertEntry(ref key) => Box::new(key.shrink().map(Op::InsertEntry)),
+            Op::Upsert(ref key) => Box::new(key.shrink().map(Op::Upsert)),
         }
     }
 }
@@ -66,6 +89,23 @@ impl<K> Op<K> where K: Clone + Ord {
                     Entry::Vacant(_) => panic!("expected an occupied entry"),
                 }
             },
+            Op::InsertMax(key) => if map.last().map_or(true, |max| key > *max.0) {
+                map.insert_max(key, ());
+            },
+            Op::InsertMin(key) => if map.first().map_or(true, |min| key < *min.0) {
+                map.insert_min(key, ());
+            },
+            Op::EntryInsertAdjacent(key) => if !map.contains_key(&key) {
+                if map.succ(&key, false).is_some() {
+                    unsafe { map.succ_entry(&key, false).unwrap().insert_before(key, ()); }
+                } else if map.pred(&key, false).is_some() {
+                    unsafe { map.pred_entry(&key, false).unwrap().insert_after(key, ()); }
+                } else {
+                    map.insert(key, ());
+                }
+            },
+            Op::InsertEntry(key) => assert!(*map.insert_entry(key.clone(), ()).key() == key),
+            Op::Upsert(key) => { map.upsert(key, || (), |_| ()); }
         }
     }
 }
@@ -111,8 +151,141 @@ fn test_andersson() {
         let mut map = Map::new();
         for op in ops { op.exec(&mut map); }
         assert_andersson_tree(&map);
+        assert_eq!(map.validate(), Ok(()));
+        assert_eq!(Map::from_shape(Natural::default(), map.to_shape()).unwrap().to_shape(),
+                   map.to_shape());
+
+        // an Andersson tree's height is at most twice its minimum depth
+        assert!(map.min_depth() <= map.height() && map.height() <= 2 * map.min_depth());
+        assert!(map.average_depth() <= map.height() as f64);
         TestResult::passed()
     }
 
     quickcheck(check as fn(Vec<Op<u32>>) -> TestResult);
 }
+
+#[test]
+#[allow(trivial_casts)]
+fn test_andersson_from_sorted() {
+    fn check(ops: Vec<Op<u32>>, more_ops: Vec<Op<u32>>) -> TestResult {
+        let mut map = Map::new();
+        for op in ops { op.exec(&mut map); }
+
+        let mut map = map.into_with_cmp(Natural::default());
+        assert_andersson_tree(&map);
+
+        for op in more_ops { op.exec(&mut map); }
+        assert_andersson_tree(&map);
+        TestResult::passed()
+    }
+
+    quickcheck(check as fn(Vec<Op<u32>>, Vec<Op<u32>>) -> TestResult);
+}
+
+#[test]
+fn test_andersson_builder() {
+    for len in 0..300 {
+        let mut builder = map::Builder::new();
+        for i in 0..len { builder.push(i, ()); }
+
+        let map = builder.build();
+        assert_andersson_tree(&map);
+        assert!(map.validate().is_ok());
+        assert!(map.iter().map(|e| *e.0).eq(0..len));
+    }
+}
+
+// Call the given function with every permutation of the given items, using Heap's algorithm
+fn permutations<T, F>(items: &mut [T], f: &mut F) where F: FnMut(&[T]) {
+    fn generate<T, F>(n: usize, items: &mut [T], f: &mut F) where F: FnMut(&[T]) {
+        if n <= 1 { return f(items); }
+
+        for i in 0..n - 1 {
+            generate(n - 1, items, f);
+            items.swap(if n % 2 == 0 { i } else { 0 }, n - 1);
+        }
+
+        generate(n - 1, items, f);
+    }
+
+    let n = items.len();
+    generate(n, items, f);
+}
+
+#[test]
+fn test_andersson_exhaustive() {
+    // every insertion order of up to 8 keys, followed by the removal of each key
+    for n in 0..9 {
+        let mut keys: Vec<u32> = (0..n).collect();
+        let mut count = 0;
+
+        permutations(&mut keys, &mut |keys| {
+            count += 1;
+
+            let mut map = Map::new();
+
+            for &key in keys {
+                map.insert(key, ());
+                assert_andersson_tree(&map);
+                assert_eq!(map.validate(), Ok(()));
+            }
+
+            for &key in keys {
+                let mut map = map.clone();
+                assert_eq!(map.remove(&key), Some((key, ())));
+                assert_andersson_tree(&map);
+                assert_eq!(map.validate(), Ok(()));
+            }
+        });
+
+        assert_eq!(count, (1..n as usize + 1).product::<usize>());
+    }
+}
+
+#[test]
+#[cfg(feature = "debug-validate")]
+#[should_panic(expected = "comparator is inconsistent")]
+fn test_check_detects_inconsistent_comparator() {
+    use std::cell::Cell;
+
+    let reversed = Cell::new(false);
+    let cmp = |l: &u32, r: &u32| if reversed.get() { r.cmp(l) } else { l.cmp(r) };
+    let mut map = Map::with_cmp(cmp);
+    for i in 0..10 { map.insert(i, ()); }
+
+    reversed.set(true);
+    map.get(&0);
+}
+
+#[test]
+#[cfg(feature = "debug-validate")]
+#[should_panic(expected = "comparator is not transitive")]
+fn test_check_detects_intransitive_comparator() {
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+
+    // once broken, orders 0 after 2 while still ordering both after and before 1, respectively
+    let broken = Cell::new(false);
+    let cmp = |l: &u32, r: &u32| match (*l, *r) {
+        (0, 2) if broken.get() => Ordering::Greater,
+        (2, 0) if broken.get() => Ordering::Less,
+        _ => l.cmp(r),
+    };
+
+    let mut map = Map::with_cmp(cmp);
+    for i in 0..7 { map.insert(i, ()); }
+
+    broken.set(true);
+    map.insert(7, ());
+}
+
+#[test]
+fn test_validate_detects_level_violation() {
+    use map::InvariantError;
+
+    let mut map = Map::new();
+    for i in 0..10 { map.insert(i, ()); }
+
+    map.root_mut().as_mut().unwrap().level += 1;
+    assert_eq!(map.validate(), Err(InvariantError::Level));
+}
@@ -0,0 +1,720 @@
+//! A persistent ordered map based on a binary search tree.
+
+use compare::Compare;
+use std::fmt::{self, Debug};
+use std::hash::{self, Hash};
+use std::iter;
+use std::ops;
+use std::sync::Arc;
+use super::node::{self, Link};
+use super::super::cmp::Natural;
+use super::super::map::InvariantError;
+
+/// A persistent ordered map based on a binary search tree.
+///
+/// Cloning the map takes `O(1)` time and memory: the clone shares the original's tree, and each
+/// subsequent modification of either map copies only those nodes along the modified path that
+/// are still shared. This makes it cheap to keep snapshots of a map while continuing to modify
+/// it.
+///
+/// The behavior of this map is undefined if a key's ordering relative to any other key changes
+/// while the key is in the map. This is normally only possible through `Cell`, `RefCell`, or
+/// unsafe code.
+///
+/// # Examples
+///
+/// ```
+/// let mut map = tree::persistent::Map::new();
+///
+/// map.insert(2, "b");
+/// map.insert(1, "a");
+///
+/// let snapshot = map.clone();
+///
+/// map.insert(3, "c");
+/// map.remove(&1);
+///
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(&2, &"b"), (&3, &"c")]);
+/// assert_eq!(snapshot.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+/// ```
+pub struct Map<K, V, C = Natural<K>> where C: Compare<K> {
+    root: Link<K, V>,
+    len: usize,
+    cmp: C,
+}
+
+impl<K, V> Map<K, V> where K: Ord {
+    /// Creates an empty map ordered according to the natural order of its keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&3, &"c")));
+    /// assert_eq!(it.next(), None);
+    /// ```
+    pub fn new() -> Self { Map::with_cmp(Natural::default()) }
+}
+
+impl<K, V, C> Map<K, V, C> where C: Compare<K> {
+    /// Creates an empty map ordered according to the given comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut map = tree::persistent::Map::with_cmp(natural().rev());
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&3, &"c")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), None);
+    /// # }
+    /// ```
+    pub fn with_cmp(cmp: C) -> Self { Map { root: None, len: 0, cmp: cmp } }
+
+    /// Creates a map with the given comparator and entries.
+    ///
+    /// If the entries are in strictly ascending or descending order according to the comparator,
+    /// this takes `O(n)` time. Otherwise, it takes `O(n log n)` time, and later entries replace
+    /// earlier entries with equal keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::natural;
+    ///
+    /// let map = tree::persistent::Map::from_parts(natural(), vec![(1, "a"), (2, "b")]);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+    /// # }
+    /// ```
+    pub fn from_parts<I>(cmp: C, entries: I) -> Self where I: IntoIterator<Item=(K, V)> {
+        Map::from(super::super::Map::from_parts(cmp, entries))
+    }
+
+    /// Checks if the map and the given map share the same tree, in which case they are equal.
+    ///
+    /// This takes `O(1)` time. Maps that do not share their tree may still be equal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// map.insert(1, "a");
+    ///
+    /// let mut snapshot = map.clone();
+    /// assert!(map.ptr_eq(&snapshot));
+    ///
+    /// snapshot.insert(2, "b");
+    /// assert!(!map.ptr_eq(&snapshot));
+    /// ```
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (&Some(ref l), &Some(ref r)) => Arc::ptr_eq(l, r),
+            (&None, &None) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert!(map.is_empty());
+    ///
+    /// map.insert(2, "b");
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool { self.root.is_none() }
+
+    /// Returns the number of entries in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.len(), 0);
+    ///
+    /// map.insert(2, "b");
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns a reference to the map's comparator.
+    pub fn cmp(&self) -> &C { &self.cmp }
+
+    /// Removes all entries from the map.
+    ///
+    /// Other versions of the map are unaffected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// map.insert(1, "a");
+    ///
+    /// let snapshot = map.clone();
+    /// map.clear();
+    ///
+    /// assert!(map.is_empty());
+    /// assert_eq!(snapshot.len(), 1);
+    /// ```
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Inserts an entry into the map, returning the previous value, if any, associated with the
+    /// key.
+    ///
+    /// Other versions of the map are unaffected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.insert(1, "a"), None);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.insert(1, "b"), Some("a"));
+    /// assert_eq!(map.get(&1), Some(&"b"));
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> where K: Clone, V: Clone {
+        let old = node::insert(&mut self.root, &self.cmp, key, value);
+        if old.is_none() { self.len += 1; }
+        old
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key, returning `None` if
+    /// the map does not contain the key.
+    ///
+    /// Other versions of the map are unaffected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.remove(&1), Some((1, "a")));
+    ///
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map.get(&1), None);
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Clone, V: Clone, C: Compare<Q, K> {
+
+        if !self.contains_key(key) { return None; }
+        self.len -= 1;
+        Some(node::remove(&mut self.root, &self.cmp, key))
+    }
+
+    /// Checks if the map contains the given key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert!(!map.contains_key(&1));
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// ```
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value associated with the given key, or `None` if the map
+    /// does not contain the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.get(&1), None);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// ```
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V> where C: Compare<Q, K> {
+        node::get(&self.root, &self.cmp, key).map(|e| e.1)
+    }
+
+    /// Returns a reference to the map's maximum key and a reference to its associated value, or
+    /// `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.last(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.last(), Some((&3, &"c")));
+    /// ```
+    pub fn last(&self) -> Option<(&K, &V)> { node::last(&self.root) }
+
+    /// Removes the map's maximum key and returns it and its associated value, or `None` if the
+    /// map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.remove_last(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_last(), Some((3, "c")));
+    /// ```
+    pub fn remove_last(&mut self) -> Option<(K, V)> where K: Clone, V: Clone {
+        if self.is_empty() { return None; }
+        self.len -= 1;
+        Some(node::remove_last(&mut self.root))
+    }
+
+    /// Returns a reference to the map's minimum key and a reference to its associated value, or
+    /// `None` if the map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.first(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.first(), Some((&1, &"a")));
+    /// ```
+    pub fn first(&self) -> Option<(&K, &V)> { node::first(&self.root) }
+
+    /// Removes the map's minimum key and returns it and its associated value, or `None` if the
+    /// map is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// assert_eq!(map.remove_first(), None);
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// assert_eq!(map.remove_first(), Some((1, "a")));
+    /// ```
+    pub fn remove_first(&mut self) -> Option<(K, V)> where K: Clone, V: Clone {
+        if self.is_empty() { return None; }
+        self.len -= 1;
+        Some(node::remove_first(&mut self.root))
+    }
+
+    /// Returns an iterator over the map's entries with immutable references to the values.
+    ///
+    /// The iterator yields the entries in ascending order according to the map's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    ///
+    /// let mut it = map.iter();
+    /// assert_eq!(it.next(), Some((&1, &"a")));
+    /// assert_eq!(it.next(), Some((&2, &"b")));
+    /// assert_eq!(it.next(), Some((&3, &"c")));
+    /// assert_eq!(it.next(), None);
+    /// ```
+    pub fn iter(&self) -> Iter<K, V> { Iter(node::Iter::new(&self.root, self.len)) }
+
+    /// Returns a read-only view of the map's current entries.
+    ///
+    /// This takes `O(1)` time, since the snapshot shares the map's tree. The snapshot is
+    /// independent of the map: it does not borrow the map, and it remains unchanged when the map
+    /// is subsequently modified. This allows long-running scans, possibly on other threads, to
+    /// proceed while the map continues to be modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let snapshot = map.snapshot();
+    ///
+    /// for (&key, _) in snapshot.iter() {
+    ///     map.insert(key + 10, "c");
+    /// }
+    ///
+    /// assert_eq!(map.len(), 4);
+    /// assert_eq!(snapshot.len(), 2);
+    /// ```
+    pub fn snapshot(&self) -> Snapshot<K, V, C> where C: Clone { Snapshot { map: self.clone() } }
+
+    /// Returns an iterator over the differences between the map and the given newer version of
+    /// it.
+    ///
+    /// The iterator yields the entries that were removed from, inserted into, or modified in the
+    /// map, in ascending order of their keys according to the map's comparator, which the given
+    /// map is assumed to share. Subtrees that the maps share are skipped without being visited, so
+    /// diffing two versions of a map derived from each other takes time proportional to the
+    /// number of changes times the height of the trees, rather than to the size of the maps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tree::persistent::map::DiffItem;
+    ///
+    /// let mut old = tree::persistent::Map::new();
+    /// for i in 0..1000 { old.insert(i, i); }
+    ///
+    /// let mut new = old.clone();
+    /// new.remove(&1);
+    /// new.insert(500, 0);
+    /// new.insert(1000, 1000);
+    ///
+    /// assert_eq!(old.diff(&new).collect::<Vec<_>>(),
+    ///            [DiffItem::Removed(&1, &1),
+    ///             DiffItem::Modified(&500, &500, &0),
+    ///             DiffItem::Inserted(&1000, &1000)]);
+    /// ```
+    pub fn diff<'a>(&'a self, newer: &'a Self) -> Diff<'a, K, V, C> where V: PartialEq {
+        Diff { diff: node::Diff::new(&self.root, &newer.root), cmp: &self.cmp }
+    }
+
+    /// Returns a map containing the entries of the map and the entries of the given map whose
+    /// keys are not in the map.
+    ///
+    /// The result uses the map's comparator, which the given map is assumed to share. The smaller
+    /// map's keys are located in the larger map's tree by splitting it, and the pieces are joined
+    /// back together, so the result shares every subtree of the larger map that lies between two
+    /// consecutive keys of the smaller one. This takes `O(m log(n / m + 1))` time, where `m` and
+    /// `n` are the lengths of the smaller and larger maps, and `O(1)` time if the maps share
+    /// their tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let b: tree::persistent::Map<_, _> = vec![(2, "x"), (3, "c")].into_iter().collect();
+    ///
+    /// assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b"), (&3, &"c")]);
+    /// ```
+    pub fn union(&self, other: &Self) -> Self where K: Clone, V: Clone, C: Clone {
+        let (root, len) = if self.len >= other.len {
+            let (root, added) = node::union(&self.root, &other.root, &self.cmp, false);
+            (root, self.len + added)
+        } else {
+            let (root, added) = node::union(&other.root, &self.root, &self.cmp, true);
+            (root, other.len + added)
+        };
+
+        Map { root: root, len: len, cmp: self.cmp.clone() }
+    }
+
+    /// Returns a map containing the entries of the map whose keys are also in the given map.
+    ///
+    /// The result uses the map's comparator, which the given map is assumed to share. This shares
+    /// subtrees with the larger map and takes time as described for [`union`](#method.union).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let b: tree::persistent::Map<_, _> = vec![(2, "x"), (3, "c")].into_iter().collect();
+    ///
+    /// assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), [(&2, &"b")]);
+    /// ```
+    pub fn intersection(&self, other: &Self) -> Self where K: Clone, V: Clone, C: Clone {
+        if self.ptr_eq(other) { return self.clone(); }
+
+        let (root, len) = if self.len >= other.len {
+            node::intersection(&self.root, &other.root, &self.cmp, false)
+        } else {
+            node::intersection(&other.root, &self.root, &self.cmp, true)
+        };
+
+        Map { root: root, len: len, cmp: self.cmp.clone() }
+    }
+
+    /// Returns a map containing the entries of the map whose keys are not in the given map.
+    ///
+    /// The result uses the map's comparator, which the given map is assumed to share. The map's
+    /// tree is split at each of the given map's keys, so the result shares every subtree of the
+    /// map that lies between two consecutive keys of the given map. This takes `O(1)` time if the
+    /// maps share their tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Map<_, _> = vec![(1, "a"), (2, "b")].into_iter().collect();
+    /// let b: tree::persistent::Map<_, _> = vec![(2, "x"), (3, "c")].into_iter().collect();
+    ///
+    /// assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), [(&1, &"a")]);
+    /// ```
+    pub fn difference(&self, other: &Self) -> Self where K: Clone, V: Clone, C: Clone {
+        if self.ptr_eq(other) { return Map::with_cmp(self.cmp.clone()); }
+
+        let (root, removed) = node::difference(&self.root, &other.root, &self.cmp);
+        Map { root: root, len: self.len - removed, cmp: self.cmp.clone() }
+    }
+
+    /// Returns a `tree::Map` with the same entries and comparator as the map.
+    ///
+    /// This clones every entry, and is the inverse of the `From<tree::Map>` implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    ///
+    /// let map = map.to_map();
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+    /// ```
+    pub fn to_map(&self) -> super::super::Map<K, V, C> where K: Clone, V: Clone, C: Clone {
+        let entries = self.iter().map(|(key, value)| (key.clone(), value.clone()));
+        super::super::Map::from_parts(self.cmp.clone(), entries)
+    }
+
+    /// Checks that the map's underlying tree satisfies its invariants.
+    ///
+    /// See [`tree::Map::validate`](../../map/struct.Map.html#method.validate) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    ///
+    /// for i in 0..10 { map.insert(i, ()); }
+    /// assert_eq!(map.validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        if node::validate(&self.root, &self.cmp)? == self.len {
+            Ok(())
+        } else {
+            Err(InvariantError::Len)
+        }
+    }
+}
+
+impl<K, V, C> Clone for Map<K, V, C> where C: Compare<K> + Clone {
+    fn clone(&self) -> Self {
+        Map { root: self.root.clone(), len: self.len, cmp: self.cmp.clone() }
+    }
+}
+
+impl<K, V, C> Debug for Map<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+impl<K, V, C> Default for Map<K, V, C> where C: Compare<K> + Default {
+    fn default() -> Self { Map::with_cmp(C::default()) }
+}
+
+impl<K, V, C> Extend<(K, V)> for Map<K, V, C> where K: Clone, V: Clone, C: Compare<K> {
+    fn extend<I: IntoIterator<Item=(K, V)>>(&mut self, it: I) {
+        for (k, v) in it { self.insert(k, v); }
+    }
+}
+
+impl<K, V, C> iter::FromIterator<(K, V)> for Map<K, V, C>
+    where K: Clone, V: Clone, C: Compare<K> + Default {
+
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(it: I) -> Self {
+        let mut map = Map::default();
+        map.extend(it);
+        map
+    }
+}
+
+impl<K, V, C> From<super::super::Map<K, V, C>> for Map<K, V, C> where C: Compare<K> {
+    /// Converts a `tree::Map` into a persistent map with the same entries and comparator.
+    ///
+    /// This takes `O(n)` time without any comparisons. Once converted, the map can be cloned in
+    /// `O(1)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    ///
+    /// let map = tree::persistent::Map::from(map);
+    /// let snapshot = map.clone();
+    ///
+    /// assert_eq!(snapshot.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+    /// ```
+    fn from(map: super::super::Map<K, V, C>) -> Self {
+        let len = map.len();
+        let (cmp, mut it) = map.into_parts();
+        Map { root: node::from_sorted(&mut it, len), len: len, cmp: cmp }
+    }
+}
+
+impl<K, V, C> Hash for Map<K, V, C> where K: Hash, V: Hash, C: Compare<K> {
+    fn hash<H: hash::Hasher>(&self, h: &mut H) {
+        for e in self.iter() { e.hash(h); }
+    }
+}
+
+impl<'a, K, V, C, Q: ?Sized> ops::Index<&'a Q> for Map<K, V, C>
+    where C: Compare<K> + Compare<Q, K> {
+
+    type Output = V;
+    fn index(&self, key: &Q) -> &V { self.get(key).expect("key not found") }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a Map<K, V, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> { self.iter() }
+}
+
+impl<K, V, C> PartialEq for Map<K, V, C> where V: PartialEq, C: Compare<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(l, r)| {
+            self.cmp.compares_eq(&l.0, &r.0) && l.1 == r.1
+        })
+    }
+}
+
+impl<K, V, C> Eq for Map<K, V, C> where V: Eq, C: Compare<K> {}
+
+/// An iterator over the differences between two versions of a map.
+///
+/// Acquire through [`Map::diff`](struct.Map.html#method.diff).
+pub struct Diff<'a, K: 'a, V: 'a, C: 'a = Natural<K>> where C: Compare<K> {
+    diff: node::Diff<'a, K, V>,
+    cmp: &'a C,
+}
+
+impl<'a, K, V, C> Iterator for Diff<'a, K, V, C> where V: PartialEq, C: Compare<K> {
+    type Item = DiffItem<'a, K, V>;
+    fn next(&mut self) -> Option<Self::Item> { self.diff.next(self.cmp) }
+}
+
+/// A difference between two versions of a map.
+///
+/// See [`Map::diff`](struct.Map.html#method.diff).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffItem<'a, K: 'a, V: 'a> {
+    /// An entry that is only in the older version.
+    Removed(&'a K, &'a V),
+    /// An entry that is only in the newer version.
+    Inserted(&'a K, &'a V),
+    /// A key whose value differs between the versions, with its old and new values.
+    Modified(&'a K, &'a V, &'a V),
+}
+
+/// A read-only view of a persistent map's entries at a particular point in time.
+///
+/// Acquire one through [`Map::snapshot`](struct.Map.html#method.snapshot). A snapshot provides
+/// the map's read-only operations through `Deref`.
+pub struct Snapshot<K, V, C = Natural<K>> where C: Compare<K> {
+    map: Map<K, V, C>,
+}
+
+impl<K, V, C> Snapshot<K, V, C> where C: Compare<K> {
+    /// Returns a modifiable copy of the snapshot.
+    ///
+    /// This takes `O(1)` time, since the copy shares the snapshot's tree until it is modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::persistent::Map::new();
+    /// map.insert(1, "a");
+    ///
+    /// let snapshot = map.snapshot();
+    /// map.clear();
+    ///
+    /// let mut map = snapshot.to_map();
+    /// map.insert(2, "b");
+    ///
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(snapshot.len(), 1);
+    /// ```
+    pub fn to_map(&self) -> Map<K, V, C> where C: Clone { self.map.clone() }
+}
+
+impl<K, V, C> Clone for Snapshot<K, V, C> where C: Compare<K> + Clone {
+    fn clone(&self) -> Self { Snapshot { map: self.map.clone() } }
+}
+
+impl<K, V, C> Debug for Snapshot<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<K, V, C> ops::Deref for Snapshot<K, V, C> where C: Compare<K> {
+    type Target = Map<K, V, C>;
+    fn deref(&self) -> &Map<K, V, C> { &self.map }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a Snapshot<K, V, C> where C: Compare<K> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> { self.map.iter() }
+}
+
+/// An iterator over the map's entries with immutable references to the values.
+///
+/// The iterator yields the entries in ascending order according to the map's comparator.
+///
+/// Acquire through [`Map::iter`](struct.Map.html#method.iter) or the `IntoIterator` trait.
+pub struct Iter<'a, K: 'a, V: 'a>(node::Iter<'a, K, V>);
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self { Iter(self.0.clone()) }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
@@ -0,0 +1,16 @@
+//! Persistent collections based on binary search trees whose nodes are shared between versions.
+//!
+//! Cloning a persistent collection takes `O(1)` time and memory, since the clone shares the
+//! original's tree. Each modification copies only those nodes along the modified path that are
+//! shared with another version, so the versions remain independent while sharing the rest of
+//! their structure. In exchange, modifications require the keys and values to implement `Clone`.
+
+pub use self::map::{Map, Snapshot};
+pub use self::set::Set;
+pub use self::versioned::VersionedMap;
+
+pub mod map;
+pub mod set;
+pub mod versioned;
+
+mod node;
@@ -0,0 +1,553 @@
+use compare::Compare;
+use std::cmp::Ordering::*;
+use std::mem::{replace, swap};
+use std::sync::Arc;
+use super::super::map::InvariantError;
+use super::super::node::{self, check};
+use super::super::node::check::Inspect;
+use super::map::DiffItem;
+
+pub type Link<K, V> = Option<Arc<Node<K, V>>>;
+
+// A node of an Andersson tree whose subtrees may be shared with other trees
+//
+// Nodes are never modified while shared: every modification goes through `Arc::make_mut`, which
+// copies a node first if any other tree refers to it.
+#[derive(Clone)]
+pub struct Node<K, V> {
+    pub left: Link<K, V>,
+    pub right: Link<K, V>,
+    pub level: usize,
+    pub key: K,
+    pub value: V,
+}
+
+fn level<K, V>(link: &Link<K, V>) -> usize { link.as_ref().map_or(0, |node| node.level) }
+
+// Remove left horizontal link by rotating right
+fn skew<K, V>(node: &mut Arc<Node<K, V>>) where K: Clone, V: Clone {
+    if level(&node.left) == node.level {
+        let mut save = Arc::make_mut(node).left.take().unwrap();
+        swap(&mut Arc::make_mut(node).left, &mut Arc::make_mut(&mut save).right);
+        swap(node, &mut save);
+        Arc::make_mut(node).right = Some(save);
+    }
+}
+
+// Remove dual horizontal link by rotating left and increasing level of the parent
+fn split<K, V>(node: &mut Arc<Node<K, V>>) where K: Clone, V: Clone {
+    if node.right.as_ref().map_or(0, |right| level(&right.right)) == node.level {
+        let mut save = Arc::make_mut(node).right.take().unwrap();
+        swap(&mut Arc::make_mut(node).right, &mut Arc::make_mut(&mut save).left);
+        Arc::make_mut(&mut save).level += 1;
+        swap(node, &mut save);
+        Arc::make_mut(node).left = Some(save);
+    }
+}
+
+fn needs_skew<K, V>(link: &Link<K, V>) -> bool {
+    link.as_ref().map_or(false, |node| level(&node.left) == node.level)
+}
+
+fn needs_split<K, V>(link: &Link<K, V>) -> bool {
+    link.as_ref().map_or(false, |node| {
+        node.right.as_ref().map_or(0, |right| level(&right.right)) == node.level
+    })
+}
+
+// Restore the Andersson tree invariants after a removal from one of the node's subtrees, only
+// copying those shared nodes that actually change
+fn rebalance<K, V>(node: &mut Arc<Node<K, V>>) where K: Clone, V: Clone {
+    let left_level = level(&node.left);
+    let right_level = level(&node.right);
+
+    if left_level < node.level - 1 || right_level < node.level - 1 {
+        {
+            let node = Arc::make_mut(node);
+            node.level -= 1;
+
+            if right_level > node.level {
+                let node_level = node.level;
+                if let Some(ref mut x) = node.right { Arc::make_mut(x).level = node_level; }
+            }
+        }
+
+        skew(node);
+
+        if needs_skew(&node.right) { skew(Arc::make_mut(node).right.as_mut().unwrap()); }
+
+        if node.right.as_ref().map_or(false, |right| needs_skew(&right.right)) {
+            let right = Arc::make_mut(node).right.as_mut().unwrap();
+            skew(Arc::make_mut(right).right.as_mut().unwrap());
+        }
+
+        split(node);
+
+        if needs_split(&node.right) { split(Arc::make_mut(node).right.as_mut().unwrap()); }
+    }
+}
+
+// Build a balanced tree from the first `len` entries of the given iterator, which must yield
+// them in ascending order
+pub fn from_sorted<K, V, I>(it: &mut I, len: usize) -> Link<K, V>
+    where I: Iterator<Item=(K, V)> {
+
+    node::build_sorted(it, len, &mut |left, right, level, _, key, value| {
+        Arc::new(Node { left: left, right: right, level: level, key: key, value: value })
+    }).0
+}
+
+pub fn get<'a, K, V, C: ?Sized, Q: ?Sized>(mut link: &'a Link<K, V>, cmp: &C, key: &Q)
+    -> Option<(&'a K, &'a V)> where C: Compare<Q, K> {
+
+    while let Some(ref node) = *link {
+        link = match cmp.compare(key, &node.key) {
+            Less => &node.left,
+            Equal => return Some((&node.key, &node.value)),
+            Greater => &node.right,
+        };
+    }
+
+    None
+}
+
+pub fn first<K, V>(link: &Link<K, V>) -> Option<(&K, &V)> {
+    let mut node = match *link { None => return None, Some(ref node) => node };
+    while let Some(ref left) = node.left { node = left; }
+    Some((&node.key, &node.value))
+}
+
+pub fn last<K, V>(link: &Link<K, V>) -> Option<(&K, &V)> {
+    let mut node = match *link { None => return None, Some(ref node) => node };
+    while let Some(ref right) = node.right { node = right; }
+    Some((&node.key, &node.value))
+}
+
+// Insert an entry into the tree, copying the shared nodes along its path and returning the value
+// that was replaced, if any
+pub fn insert<K, V, C>(link: &mut Link<K, V>, cmp: &C, key: K, value: V) -> Option<V>
+    where K: Clone, V: Clone, C: Compare<K> {
+
+    match *link {
+        None => {
+            let node = Node { left: None, right: None, level: 1, key: key, value: value };
+            *link = Some(Arc::new(node));
+            None
+        }
+        Some(ref mut node) => {
+            let old = {
+                let node = Arc::make_mut(node);
+
+                match cmp.compare(&key, &node.key) {
+                    Equal => return Some(replace(&mut node.value, value)),
+                    Less => insert(&mut node.left, cmp, key, value),
+                    Greater => insert(&mut node.right, cmp, key, value),
+                }
+            };
+
+            skew(node);
+            split(node);
+            old
+        }
+    }
+}
+
+// Remove the entry with the given key from the tree, copying the shared nodes along its path
+//
+// The caller must ensure that the tree contains the key, so that no nodes are copied in vain.
+pub fn remove<K, V, C: ?Sized, Q: ?Sized>(link: &mut Link<K, V>, cmp: &C, key: &Q) -> (K, V)
+    where K: Clone, V: Clone, C: Compare<Q, K> {
+
+    let order = cmp.compare(key, &link.as_ref().expect("key not found").key);
+    if order == Equal { return remove_node(link); }
+
+    let removed = {
+        let node = Arc::make_mut(link.as_mut().unwrap());
+        remove(if order == Less { &mut node.left } else { &mut node.right }, cmp, key)
+    };
+
+    rebalance(link.as_mut().unwrap());
+    removed
+}
+
+// Remove the root of the given non-empty tree
+fn remove_node<K, V>(link: &mut Link<K, V>) -> (K, V) where K: Clone, V: Clone {
+    let replacement = {
+        let node = Arc::make_mut(link.as_mut().unwrap());
+
+        if node.left.is_some() {
+            Some(remove_last(&mut node.left))
+        } else if node.right.is_some() {
+            Some(remove_first(&mut node.right))
+        } else {
+            None
+        }
+    };
+
+    match replacement {
+        None => {
+            let node = link.take().unwrap();
+            let node = Arc::try_unwrap(node).unwrap_or_else(|node| (*node).clone());
+            (node.key, node.value)
+        }
+        Some((key, value)) => {
+            let old = {
+                let node = Arc::make_mut(link.as_mut().unwrap());
+                (replace(&mut node.key, key), replace(&mut node.value, value))
+            };
+
+            rebalance(link.as_mut().unwrap());
+            old
+        }
+    }
+}
+
+// Remove the minimum entry of the given non-empty tree
+pub fn remove_first<K, V>(link: &mut Link<K, V>) -> (K, V) where K: Clone, V: Clone {
+    if link.as_ref().unwrap().left.is_none() { return remove_node(link); }
+    let removed = remove_first(&mut Arc::make_mut(link.as_mut().unwrap()).left);
+    rebalance(link.as_mut().unwrap());
+    removed
+}
+
+// Remove the maximum entry of the given non-empty tree
+pub fn remove_last<K, V>(link: &mut Link<K, V>) -> (K, V) where K: Clone, V: Clone {
+    if link.as_ref().unwrap().right.is_none() { return remove_node(link); }
+    let removed = remove_last(&mut Arc::make_mut(link.as_mut().unwrap()).right);
+    rebalance(link.as_mut().unwrap());
+    removed
+}
+
+fn ptr_eq<K, V>(l: &Link<K, V>, r: &Link<K, V>) -> bool {
+    match (l, r) {
+        (&Some(ref l), &Some(ref r)) => Arc::ptr_eq(l, r),
+        (&None, &None) => true,
+        _ => false,
+    }
+}
+
+fn len<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| len(&node.left) + 1 + len(&node.right))
+}
+
+// Join two trees and an entry whose key lies between theirs into a single tree
+//
+// The entry becomes a node on the inner spine of the taller tree, at the level of the shorter
+// tree, and the nodes above it are rebalanced as they would be after an insertion. Only the nodes
+// along that spine are copied.
+fn join<K, V>(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V>
+    where K: Clone, V: Clone {
+
+    let (left_level, right_level) = (level(&left), level(&right));
+
+    if left_level == right_level {
+        let node = Node { left: left, right: right, level: left_level + 1, key: key, value: value };
+        Some(Arc::new(node))
+    } else if left_level > right_level {
+        let mut left = left;
+        join_right(left.as_mut().unwrap(), key, value, right);
+        left
+    } else {
+        let mut right = right;
+        join_left(left, key, value, right.as_mut().unwrap());
+        right
+    }
+}
+
+// Join the given entry and shorter tree onto the right spine of the given node's tree
+fn join_right<K, V>(node: &mut Arc<Node<K, V>>, key: K, value: V, right: Link<K, V>)
+    where K: Clone, V: Clone {
+
+    {
+        let node = Arc::make_mut(node);
+
+        if level(&node.right) == level(&right) {
+            let left = node.right.take();
+            node.right = join(left, key, value, right);
+        } else {
+            join_right(node.right.as_mut().unwrap(), key, value, right);
+        }
+    }
+
+    skew(node);
+    split(node);
+}
+
+// Join the given shorter tree and entry onto the left spine of the given node's tree
+fn join_left<K, V>(left: Link<K, V>, key: K, value: V, node: &mut Arc<Node<K, V>>)
+    where K: Clone, V: Clone {
+
+    {
+        let node = Arc::make_mut(node);
+
+        if level(&node.left) == level(&left) {
+            let right = node.left.take();
+            node.left = join(left, key, value, right);
+        } else {
+            join_left(left, key, value, node.left.as_mut().unwrap());
+        }
+    }
+
+    skew(node);
+    split(node);
+}
+
+// Join two trees, all of the keys of the first of which are less than those of the second
+fn join2<K, V>(left: Link<K, V>, mut right: Link<K, V>) -> Link<K, V> where K: Clone, V: Clone {
+    if right.is_none() { return left; }
+    let (key, value) = remove_first(&mut right);
+    join(left, key, value, right)
+}
+
+// Split the tree into the entries whose keys are less than the given key, the entry whose key is
+// equal to it, if any, and the entries whose keys are greater than it
+//
+// The subtrees hanging off the search path are shared with the result rather than copied.
+fn split_at<K, V, C: ?Sized>(link: &Link<K, V>, cmp: &C, key: &K)
+    -> (Link<K, V>, Option<(K, V)>, Link<K, V>) where K: Clone, V: Clone, C: Compare<K> {
+
+    let node = match *link {
+        None => return (None, None, None),
+        Some(ref node) => node,
+    };
+
+    match cmp.compare(key, &node.key) {
+        Less => {
+            let (left, found, right) = split_at(&node.left, cmp, key);
+            let right = join(right, node.key.clone(), node.value.clone(), node.right.clone());
+            (left, found, right)
+        }
+        Equal => {
+            let found = (node.key.clone(), node.value.clone());
+            (node.left.clone(), Some(found), node.right.clone())
+        }
+        Greater => {
+            let (left, found, right) = split_at(&node.right, cmp, key);
+            let left = join(node.left.clone(), node.key.clone(), node.value.clone(), left);
+            (left, found, right)
+        }
+    }
+}
+
+// Return the union of the trees, taking the entry of `small` for keys in both if `prefer_small`
+// is `true`, along with the number of keys in `small` that are not in `large`
+//
+// `large` is split at each of the keys in `small`, so any of its subtrees that lie entirely
+// between two consecutive keys of `small` are shared with the result, as are any subtrees that
+// the trees themselves share.
+pub fn union<K, V, C: ?Sized>(large: &Link<K, V>, small: &Link<K, V>, cmp: &C, prefer_small: bool)
+    -> (Link<K, V>, usize) where K: Clone, V: Clone, C: Compare<K> {
+
+    let node = match *small {
+        None => return (large.clone(), 0),
+        Some(ref node) => node,
+    };
+
+    if large.is_none() { return (small.clone(), len(small)); }
+    if ptr_eq(large, small) { return (large.clone(), 0); }
+
+    let (left, found, right) = split_at(large, cmp, &node.key);
+    let (left, left_added) = union(&left, &node.left, cmp, prefer_small);
+    let (right, right_added) = union(&right, &node.right, cmp, prefer_small);
+    let added = left_added + found.is_none() as usize + right_added;
+
+    let (key, value) = match found {
+        Some(entry) if !prefer_small => entry,
+        _ => (node.key.clone(), node.value.clone()),
+    };
+
+    (join(left, key, value, right), added)
+}
+
+// Return the intersection of the trees, taking the entry of `small` for each key if
+// `prefer_small` is `true`, along with the number of entries in it
+pub fn intersection<K, V, C: ?Sized>(large: &Link<K, V>, small: &Link<K, V>, cmp: &C,
+                                     prefer_small: bool) -> (Link<K, V>, usize)
+    where K: Clone, V: Clone, C: Compare<K> {
+
+    let node = match *small {
+        None => return (None, 0),
+        Some(ref node) => node,
+    };
+
+    if large.is_none() { return (None, 0); }
+    if ptr_eq(large, small) { return (large.clone(), len(large)); }
+
+    let (left, found, right) = split_at(large, cmp, &node.key);
+    let (left, left_len) = intersection(&left, &node.left, cmp, prefer_small);
+    let (right, right_len) = intersection(&right, &node.right, cmp, prefer_small);
+
+    match found {
+        None => (join2(left, right), left_len + right_len),
+        Some(entry) => {
+            let (key, value) = if prefer_small {
+                (node.key.clone(), node.value.clone())
+            } else {
+                entry
+            };
+
+            (join(left, key, value, right), left_len + 1 + right_len)
+        }
+    }
+}
+
+// Return the entries of the tree whose keys are not in the other tree, along with the number of
+// entries that were removed
+pub fn difference<K, V, C: ?Sized>(link: &Link<K, V>, other: &Link<K, V>, cmp: &C)
+    -> (Link<K, V>, usize) where K: Clone, V: Clone, C: Compare<K> {
+
+    let node = match *other {
+        None => return (link.clone(), 0),
+        Some(ref node) => node,
+    };
+
+    if link.is_none() { return (None, 0); }
+    if ptr_eq(link, other) { return (None, len(link)); }
+
+    let (left, found, right) = split_at(link, cmp, &node.key);
+    let (left, left_removed) = difference(&left, &node.left, cmp);
+    let (right, right_removed) = difference(&right, &node.right, cmp);
+    (join2(left, right), left_removed + found.is_some() as usize + right_removed)
+}
+
+impl<K, V> Inspect for Node<K, V> {
+    type Key = K;
+    fn left(&self) -> Option<&Self> { self.left.as_ref().map(|node| &**node) }
+    fn right(&self) -> Option<&Self> { self.right.as_ref().map(|node| &**node) }
+    fn level(&self) -> usize { self.level }
+    fn key(&self) -> &K { &self.key }
+    fn size(&self) -> Option<usize> { None }
+}
+
+// Verify that the tree's keys are in strictly ascending order according to the comparator and
+// that its levels satisfy the Andersson tree invariants, returning the number of entries
+pub fn validate<K, V, C: ?Sized>(root: &Link<K, V>, cmp: &C) -> Result<usize, InvariantError>
+    where C: Compare<K> {
+
+    check::walk(root.as_ref().map(|node| &**node), cmp)
+}
+
+// An iterator over the entries of a tree in ascending order of their keys
+pub struct Iter<'a, K: 'a, V: 'a> {
+    stack: Vec<&'a Node<K, V>>,
+    size: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub fn new(root: &'a Link<K, V>, size: usize) -> Self {
+        let mut it = Iter { stack: vec![], size: size };
+        it.push_left(root);
+        it
+    }
+
+    fn push_left(&mut self, mut link: &'a Link<K, V>) {
+        while let Some(ref node) = *link {
+            self.stack.push(node);
+            link = &node.left;
+        }
+    }
+}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self { Iter { stack: self.stack.clone(), size: self.size } }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stack.pop().map(|node| {
+            self.size -= 1;
+            self.push_left(&node.right);
+            (&node.key, &node.value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.size, Some(self.size)) }
+}
+
+// A subtree that has not been visited yet, or a node whose left subtree has been visited
+enum Item<'a, K: 'a, V: 'a> {
+    Tree(&'a Arc<Node<K, V>>),
+    Entry(&'a Node<K, V>),
+}
+
+// An iterator over the differences between two trees that skips the subtrees they share
+//
+// Each tree is traversed in order using a stack of items. Whenever both stacks have a subtree on
+// top, the subtrees are skipped if they are the same, and otherwise the taller one is expanded, so
+// that equal subtrees are likely to appear on top of both stacks at the same time. The cost of
+// the traversal is therefore proportional to the number of nodes that are not shared between the
+// trees, which is at most the number of changed entries times the height of the trees.
+pub struct Diff<'a, K: 'a, V: 'a> {
+    old: Vec<Item<'a, K, V>>,
+    new: Vec<Item<'a, K, V>>,
+}
+
+impl<'a, K, V> Diff<'a, K, V> {
+    pub fn new(old: &'a Link<K, V>, new: &'a Link<K, V>) -> Self {
+        let mut diff = Diff { old: vec![], new: vec![] };
+        if let Some(ref node) = *old { diff.old.push(Item::Tree(node)); }
+        if let Some(ref node) = *new { diff.new.push(Item::Tree(node)); }
+        diff
+    }
+
+    pub fn next<C: ?Sized>(&mut self, cmp: &C) -> Option<DiffItem<'a, K, V>>
+        where V: PartialEq, C: Compare<K> {
+
+        loop {
+            let expand_old = match (self.old.last(), self.new.last()) {
+                (None, None) => return None,
+                (Some(&Item::Tree(old)), Some(&Item::Tree(new))) => {
+                    if Arc::ptr_eq(old, new) {
+                        self.old.pop();
+                        self.new.pop();
+                        continue;
+                    }
+
+                    old.level >= new.level
+                }
+                (Some(&Item::Tree(_)), _) => true,
+                (_, Some(&Item::Tree(_))) => false,
+                (Some(&Item::Entry(old)), Some(&Item::Entry(new))) => {
+                    match cmp.compare(&old.key, &new.key) {
+                        Less => {
+                            self.old.pop();
+                            return Some(DiffItem::Removed(&old.key, &old.value));
+                        }
+                        Greater => {
+                            self.new.pop();
+                            return Some(DiffItem::Inserted(&new.key, &new.value));
+                        }
+                        Equal => {
+                            self.old.pop();
+                            self.new.pop();
+
+                            if old as *const _ != new as *const _ && old.value != new.value {
+                                return Some(DiffItem::Modified(&new.key, &old.value, &new.value));
+                            }
+
+                            continue;
+                        }
+                    }
+                }
+                (Some(&Item::Entry(old)), None) => {
+                    self.old.pop();
+                    return Some(DiffItem::Removed(&old.key, &old.value));
+                }
+                (None, Some(&Item::Entry(new))) => {
+                    self.new.pop();
+                    return Some(DiffItem::Inserted(&new.key, &new.value));
+                }
+            };
+
+            let stack = if expand_old { &mut self.old } else { &mut self.new };
+
+            if let Some(Item::Tree(node)) = stack.pop() {
+                if let Some(ref right) = node.right { stack.push(Item::Tree(right)); }
+                stack.push(Item::Entry(node));
+                if let Some(ref left) = node.left { stack.push(Item::Tree(left)); }
+            }
+        }
+    }
+}
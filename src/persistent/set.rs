@@ -0,0 +1,210 @@
+//! A persistent ordered set based on a binary search tree.
+
+use compare::Compare;
+use std::fmt::{self, Debug};
+use std::hash::{self, Hash};
+use std::iter;
+use super::map::{self, Map};
+use super::super::cmp::Natural;
+
+/// A persistent ordered set based on a binary search tree.
+///
+/// Cloning the set takes `O(1)` time and memory, and each subsequent modification of either set
+/// copies only those nodes along the modified path that are still shared.
+///
+/// The set operations [`union`](#method.union), [`intersection`](#method.intersection), and
+/// [`difference`](#method.difference) return new sets, leaving their operands unchanged. Like
+/// their counterparts on [`Map`](../map/struct.Map.html#method.union), they split one operand's
+/// tree at the other's items and join the pieces back together, so the result shares most of its
+/// structure with its operands, and they take `O(1)` time when both operands share the same tree,
+/// as is the case for a set and its unmodified clone.
+///
+/// The behavior of this set is undefined if an item's ordering relative to any other item changes
+/// while the item is in the set. This is normally only possible through `Cell`, `RefCell`, or
+/// unsafe code.
+///
+/// # Examples
+///
+/// ```
+/// let mut a = tree::persistent::Set::new();
+/// a.insert(1);
+/// a.insert(2);
+///
+/// let mut b = a.clone();
+/// b.insert(3);
+/// b.remove(&1);
+///
+/// assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), [&1, &2, &3]);
+/// assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), [&2]);
+/// assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), [&1]);
+/// ```
+pub struct Set<T, C = Natural<T>> where C: Compare<T> {
+    map: Map<T, (), C>,
+}
+
+impl<T> Set<T> where T: Ord {
+    /// Creates an empty set ordered according to the natural order of its items.
+    pub fn new() -> Self { Set { map: Map::new() } }
+}
+
+impl<T, C> Set<T, C> where C: Compare<T> {
+    /// Creates an empty set ordered according to the given comparator.
+    pub fn with_cmp(cmp: C) -> Self { Set { map: Map::with_cmp(cmp) } }
+
+    /// Checks if the set is empty.
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Returns the number of items in the set.
+    pub fn len(&self) -> usize { self.map.len() }
+
+    /// Returns a reference to the set's comparator.
+    pub fn cmp(&self) -> &C { self.map.cmp() }
+
+    /// Removes all items from the set.
+    ///
+    /// Other versions of the set are unaffected.
+    pub fn clear(&mut self) { self.map.clear(); }
+
+    /// Inserts an item into the set, returning `true` if the set did not already contain the
+    /// item.
+    ///
+    /// Other versions of the set are unaffected.
+    pub fn insert(&mut self, item: T) -> bool where T: Clone { self.map.insert(item, ()).is_none() }
+
+    /// Removes the given item from the set, returning `true` if the set contained the item.
+    ///
+    /// Other versions of the set are unaffected.
+    pub fn remove<Q: ?Sized>(&mut self, item: &Q) -> bool where T: Clone, C: Compare<Q, T> {
+        self.map.remove(item).is_some()
+    }
+
+    /// Checks if the set contains the given item.
+    pub fn contains<Q: ?Sized>(&self, item: &Q) -> bool where C: Compare<Q, T> {
+        self.map.contains_key(item)
+    }
+
+    /// Returns a reference to the set's maximum item, or `None` if the set is empty.
+    pub fn last(&self) -> Option<&T> { self.map.last().map(|e| e.0) }
+
+    /// Returns a reference to the set's minimum item, or `None` if the set is empty.
+    pub fn first(&self) -> Option<&T> { self.map.first().map(|e| e.0) }
+
+    /// Returns an iterator over the set.
+    ///
+    /// The iterator yields the items in ascending order according to the set's comparator.
+    pub fn iter(&self) -> Iter<T> { Iter(self.map.iter()) }
+
+    /// Checks if the set and the given set share the same tree, in which case they are equal.
+    ///
+    /// See [`Map::ptr_eq`](../map/struct.Map.html#method.ptr_eq).
+    pub fn ptr_eq(&self, other: &Self) -> bool { self.map.ptr_eq(&other.map) }
+
+    /// Returns the set of items that are in the set, the given set, or both.
+    ///
+    /// The result uses the set's comparator, which the given set is assumed to share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::persistent::Set<_> = vec![2, 3].into_iter().collect();
+    ///
+    /// assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), [&1, &2, &3, &4]);
+    /// ```
+    pub fn union(&self, other: &Self) -> Self where T: Clone, C: Clone {
+        Set { map: self.map.union(&other.map) }
+    }
+
+    /// Returns the set of items that are in both the set and the given set.
+    ///
+    /// The result uses the set's comparator, which the given set is assumed to share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::persistent::Set<_> = vec![2, 3, 4].into_iter().collect();
+    ///
+    /// assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), [&2, &4]);
+    /// ```
+    pub fn intersection(&self, other: &Self) -> Self where T: Clone, C: Clone {
+        Set { map: self.map.intersection(&other.map) }
+    }
+
+    /// Returns the set of items that are in the set but not in the given set.
+    ///
+    /// The result uses the set's comparator, which the given set is assumed to share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::persistent::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::persistent::Set<_> = vec![2, 3].into_iter().collect();
+    ///
+    /// assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), [&1, &4]);
+    /// ```
+    pub fn difference(&self, other: &Self) -> Self where T: Clone, C: Clone {
+        Set { map: self.map.difference(&other.map) }
+    }
+}
+
+impl<T, C> Clone for Set<T, C> where C: Compare<T> + Clone {
+    fn clone(&self) -> Self { Set { map: self.map.clone() } }
+}
+
+impl<T, C> Debug for Set<T, C> where T: Debug, C: Compare<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.debug_set().entries(self).finish() }
+}
+
+impl<T, C> Default for Set<T, C> where C: Compare<T> + Default {
+    fn default() -> Self { Set::with_cmp(C::default()) }
+}
+
+impl<T, C> Extend<T> for Set<T, C> where T: Clone, C: Compare<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for item in it { self.insert(item); }
+    }
+}
+
+impl<T, C> iter::FromIterator<T> for Set<T, C> where T: Clone, C: Compare<T> + Default {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Self {
+        let mut set = Set::default();
+        set.extend(it);
+        set
+    }
+}
+
+impl<T, C> Hash for Set<T, C> where T: Hash, C: Compare<T> {
+    fn hash<H: hash::Hasher>(&self, h: &mut H) { self.map.hash(h); }
+}
+
+impl<'a, T, C> IntoIterator for &'a Set<T, C> where C: Compare<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> { self.iter() }
+}
+
+impl<T, C> PartialEq for Set<T, C> where C: Compare<T> {
+    fn eq(&self, other: &Self) -> bool { self.map == other.map }
+}
+
+impl<T, C> Eq for Set<T, C> where C: Compare<T> {}
+
+/// An iterator over the set.
+///
+/// The iterator yields the items in ascending order according to the set's comparator.
+///
+/// Acquire through [`Set::iter`](struct.Set.html#method.iter) or the `IntoIterator` trait.
+pub struct Iter<'a, T: 'a>(map::Iter<'a, T, ()>);
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self { Iter(self.0.clone()) }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> { self.0.next().map(|e| e.0) }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
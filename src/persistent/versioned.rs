@@ -0,0 +1,115 @@
+//! A persistent ordered map that keeps its committed versions.
+
+use compare::Compare;
+use std::fmt::{self, Debug};
+use std::ops;
+use super::map::Map;
+use super::super::cmp::Natural;
+
+/// A persistent ordered map that keeps each of its committed versions.
+///
+/// The map derefs to a working [`Map`](../map/struct.Map.html) that can be freely modified.
+/// Calling [`commit`](#method.commit) records the working map's current state as a new version
+/// in `O(1)` time, and each committed version shares its unmodified nodes with the others.
+/// Committed versions can be read through [`version`](#method.version) and restored through
+/// [`rollback_to`](#method.rollback_to).
+///
+/// # Examples
+///
+/// ```
+/// use tree::persistent::versioned::VersionedMap;
+///
+/// let mut map = VersionedMap::new();
+///
+/// map.insert(1, "a");
+/// let v0 = map.commit();
+///
+/// map.insert(2, "b");
+/// let v1 = map.commit();
+///
+/// map.remove(&1);
+///
+/// assert_eq!(map.version(v0).unwrap().iter().collect::<Vec<_>>(), [(&1, &"a")]);
+/// assert_eq!(map.version(v1).unwrap().iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b")]);
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(&2, &"b")]);
+///
+/// map.rollback_to(v0);
+///
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a")]);
+/// assert_eq!(map.latest_version(), Some(v0));
+/// ```
+pub struct VersionedMap<K, V, C = Natural<K>> where C: Compare<K> {
+    working: Map<K, V, C>,
+    versions: Vec<(u64, Map<K, V, C>)>,
+    next_version: u64,
+}
+
+impl<K, V> VersionedMap<K, V> where K: Ord {
+    /// Creates an empty map ordered according to the natural order of its keys, with no
+    /// committed versions.
+    pub fn new() -> Self { VersionedMap::with_cmp(Natural::default()) }
+}
+
+impl<K, V, C> VersionedMap<K, V, C> where C: Compare<K> {
+    /// Creates an empty map ordered according to the given comparator, with no committed
+    /// versions.
+    pub fn with_cmp(cmp: C) -> Self {
+        VersionedMap { working: Map::with_cmp(cmp), versions: vec![], next_version: 0 }
+    }
+
+    /// Records the working map's current state as a new version, returning its number.
+    ///
+    /// Versions are numbered in increasing order from `0`. The numbers of versions discarded by
+    /// [`rollback_to`](#method.rollback_to) are never reused.
+    pub fn commit(&mut self) -> u64 where C: Clone {
+        let version = self.next_version;
+        self.versions.push((version, self.working.clone()));
+        self.next_version += 1;
+        version
+    }
+
+    /// Returns the number of the most recently committed version, or `None` if no version has
+    /// been committed.
+    pub fn latest_version(&self) -> Option<u64> { self.versions.last().map(|v| v.0) }
+
+    /// Returns the map as of the given version, or `None` if no such version has been committed
+    /// or it has been discarded by [`rollback_to`](#method.rollback_to).
+    pub fn version(&self, version: u64) -> Option<&Map<K, V, C>> {
+        self.index(version).map(|i| &self.versions[i].1)
+    }
+
+    /// Restores the working map to the given version, discarding any uncommitted modifications
+    /// and all versions committed after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no such version has been committed or it has been discarded.
+    pub fn rollback_to(&mut self, version: u64) where C: Clone {
+        let i = self.index(version)
+            .unwrap_or_else(|| panic!("version {} has not been committed", version));
+        self.versions.truncate(i + 1);
+        self.working = self.versions[i].1.clone();
+    }
+
+    // Returns the index of the given version in `versions`, if it is kept
+    fn index(&self, version: u64) -> Option<usize> {
+        self.versions.binary_search_by(|v| v.0.cmp(&version)).ok()
+    }
+}
+
+impl<K, V, C> Debug for VersionedMap<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.working.fmt(f) }
+}
+
+impl<K, V, C> Default for VersionedMap<K, V, C> where C: Compare<K> + Default {
+    fn default() -> Self { VersionedMap::with_cmp(C::default()) }
+}
+
+impl<K, V, C> ops::Deref for VersionedMap<K, V, C> where C: Compare<K> {
+    type Target = Map<K, V, C>;
+    fn deref(&self) -> &Map<K, V, C> { &self.working }
+}
+
+impl<K, V, C> ops::DerefMut for VersionedMap<K, V, C> where C: Compare<K> {
+    fn deref_mut(&mut self) -> &mut Map<K, V, C> { &mut self.working }
+}
@@ -1,12 +1,15 @@
 //! An ordered set based on a binary search tree.
 
-use compare::{Compare, Natural};
+use compare::Compare;
 use std::cmp::Ordering;
 #[cfg(feature = "range")] use std::collections::Bound;
 use std::fmt::{self, Debug};
 use std::hash::{self, Hash};
 use std::iter;
-use super::map::{self, Map};
+use std::ops;
+use super::cmp::Natural;
+use super::map::{self, Discrete, EitherOrBoth, Map};
+use super::node;
 
 /// An ordered set based on a binary search tree.
 ///
@@ -39,6 +42,29 @@ impl<T> Set<T> where T: Ord {
     pub fn new() -> Self { Set { map: Map::new() } }
 }
 
+impl<T> Set<T> where T: Discrete {
+    /// Returns the smallest item in the given range that is not present in the set, or `None` if
+    /// every item in the range is present.
+    ///
+    /// See [`Map::first_absent_key`](../map/struct.Map.html#method.first_absent_key).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut ids = tree::Set::new();
+    ///
+    /// for id in vec![0, 1, 2, 4] { ids.insert(id); }
+    ///
+    /// assert_eq!(ids.first_missing(0..10), Some(3));
+    /// ids.insert(3);
+    /// assert_eq!(ids.first_missing(0..10), Some(5));
+    /// assert_eq!(ids.first_missing(0..5), None);
+    /// ```
+    pub fn first_missing(&self, range: ops::Range<T>) -> Option<T> {
+        self.map.first_absent_key(range)
+    }
+}
+
 impl<T, C> Set<T, C> where C: Compare<T> {
     /// Creates an empty set ordered according to the given comparator.
     ///
@@ -110,6 +136,125 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// ```
     pub fn cmp(&self) -> &C { self.map.cmp() }
 
+    /// Creates a set containing the keys of the given map, ordered according to its comparator.
+    ///
+    /// This takes constant time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut map = tree::Map::new();
+    ///
+    /// map.insert(2, ());
+    /// map.insert(1, ());
+    ///
+    /// let set = tree::Set::from_map(map);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [&1, &2]);
+    /// ```
+    pub fn from_map(map: Map<T, (), C>) -> Self { Set { map: map } }
+
+    /// Consumes the set and returns a map whose keys are the set's items.
+    ///
+    /// This takes constant time, and allows map-only operations to be applied to the set's items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(1);
+    ///
+    /// let map = set.into_map().map_values(|item, ()| item * 10);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &10), (&2, &20)]);
+    /// ```
+    pub fn into_map(self) -> Map<T, (), C> { self.map }
+
+    /// Returns a reference to the map underlying the set, whose keys are the set's items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(3);
+    ///
+    /// let middle = set.as_map().select_range(1..3).map(|e| e.0);
+    /// assert_eq!(middle.collect::<Vec<_>>(), [&2, &3]);
+    /// ```
+    pub fn as_map(&self) -> &Map<T, (), C> { &self.map }
+
+    /// Returns a mutable reference to the map underlying the set, whose keys are the set's items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(3);
+    ///
+    /// set.as_map_mut().insert_max(4, ());
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [&1, &2, &3, &4]);
+    /// ```
+    pub fn as_map_mut(&mut self) -> &mut Map<T, (), C> { &mut self.map }
+
+    /// Consumes the set and returns a set ordered according to the natural order of its items
+    /// containing the results of applying the given function to the set's items.
+    ///
+    /// The function is applied to the items in ascending order according to the set's
+    /// comparator. If the results are in strictly ascending or strictly descending order, the new
+    /// set is built in `O(n)` time without any further comparisons. Otherwise, the results are
+    /// inserted one at a time, and duplicates are discarded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(-1);
+    /// set.insert(3);
+    ///
+    /// let set = set.map(|item| item * item);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [&1, &4, &9]);
+    /// ```
+    pub fn map<U, F>(self, f: F) -> Set<U> where U: Ord, F: FnMut(T) -> U {
+        self.map_with_cmp(Natural::default(), f)
+    }
+
+    /// Consumes the set and returns a set ordered according to the given comparator containing
+    /// the results of applying the given function to the set's items.
+    ///
+    /// See [`map`](#method.map) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate compare;
+    /// # extern crate tree;
+    /// # fn main() {
+    /// use compare::{Compare, natural};
+    ///
+    /// let mut set = tree::Set::new();
+    ///
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(3);
+    ///
+    /// let set = set.map_with_cmp(natural().rev(), |item| item.to_string());
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), ["3", "2", "1"]);
+    /// # }
+    /// ```
+    pub fn map_with_cmp<U, D, F>(self, cmp: D, mut f: F) -> Set<U, D>
+        where D: Compare<U>, F: FnMut(T) -> U {
+
+        Set { map: Map::from_parts(cmp, self.into_iter().map(|item| (f(item), ()))) }
+    }
+
     /// Removes all items from the set.
     ///
     /// # Examples
@@ -144,6 +289,22 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// ```
     pub fn insert(&mut self, item: T) -> bool { self.map.insert(item, ()).is_none() }
 
+    /// Inserts the given items into the set, as with `Extend`, returning the number of items that
+    /// were not already present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    /// set.insert(1);
+    ///
+    /// assert_eq!(set.insert_many(vec![1, 2, 3, 2]), 2);
+    /// assert_eq!(set.len(), 3);
+    /// ```
+    pub fn insert_many<I>(&mut self, items: I) -> usize where I: IntoIterator<Item=T> {
+        self.map.insert_many(items.into_iter().map(|item| (item, ())))
+    }
+
     /// Removes the given item from the set, returning `true` if the set contained the item.
     ///
     /// # Examples
@@ -250,6 +411,24 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// ```
     pub fn remove_last(&mut self) -> Option<T> { self.map.remove_last().map(|e| e.0) }
 
+    /// Removes and returns the set's maximum item, or `None` if the set is empty.
+    ///
+    /// This is equivalent to [`remove_last`](#method.remove_last) and matches the name used by
+    /// `std::collections::BTreeSet`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(3);
+    ///
+    /// assert_eq!(set.pop_last(), Some(3));
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    pub fn pop_last(&mut self) -> Option<T> { self.remove_last() }
+
     /// Returns the entry corresponding to the set's maximum item.
     ///
     /// # Examples
@@ -306,6 +485,24 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// ```
     pub fn remove_first(&mut self) -> Option<T> { self.map.remove_first().map(|e| e.0) }
 
+    /// Removes and returns the set's minimum item, or `None` if the set is empty.
+    ///
+    /// This is equivalent to [`remove_first`](#method.remove_first) and matches the name used by
+    /// `std::collections::BTreeSet`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut set = tree::Set::new();
+    /// set.insert(2);
+    /// set.insert(1);
+    /// set.insert(3);
+    ///
+    /// assert_eq!(set.pop_first(), Some(1));
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    pub fn pop_first(&mut self) -> Option<T> { self.remove_first() }
+
     /// Returns the entry corresponding to the set's minimum item.
     ///
     /// # Examples
@@ -559,6 +756,126 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// assert_eq!(it.next(), None);
     /// ```
     pub fn iter(&self) -> Iter<T> { Iter(self.map.iter()) }
+
+    /// Returns an iterator over the set in descending order according to the set's comparator.
+    ///
+    /// This is equivalent to `iter().rev()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = (1..4).collect();
+    /// assert_eq!(set.iter_rev().collect::<Vec<_>>(), [&3, &2, &1]);
+    /// ```
+    pub fn iter_rev(&self) -> iter::Rev<Iter<T>> { self.iter().rev() }
+
+    /// Moves all items from the given set into the set, leaving the given set empty.
+    ///
+    /// See [`Map::append`](../map/struct.Map.html#method.append).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut a: tree::Set<_> = vec![1, 2].into_iter().collect();
+    /// let mut b: tree::Set<_> = vec![2, 3].into_iter().collect();
+    ///
+    /// a.append(&mut b);
+    ///
+    /// assert!(b.is_empty());
+    /// assert_eq!(a.iter().collect::<Vec<_>>(), [&1, &2, &3]);
+    /// ```
+    pub fn append(&mut self, other: &mut Self) { self.map.append(&mut other.map); }
+
+    /// Splits the set in two at the given item, returning a set with the same comparator
+    /// containing the items that are greater than or equal to it.
+    ///
+    /// See [`Map::split_off`](../map/struct.Map.html#method.split_off).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut a: tree::Set<_> = vec![1, 2, 3].into_iter().collect();
+    /// let b = a.split_off(&2);
+    ///
+    /// assert_eq!(a.iter().collect::<Vec<_>>(), [&1]);
+    /// assert_eq!(b.iter().collect::<Vec<_>>(), [&2, &3]);
+    /// ```
+    pub fn split_off<Q: ?Sized>(&mut self, item: &Q) -> Self where C: Compare<Q, T> + Clone {
+        Set { map: self.map.split_off(item) }
+    }
+
+    /// Returns the number of items that are in both the set and the given set, without collecting
+    /// them.
+    ///
+    /// The given set is assumed to share the set's comparator. If the sets' items do not overlap,
+    /// this takes `O(log n)` time. If one set is much smaller than the other, this looks up each
+    /// of its items in the larger set. Otherwise, it merges the sets' items in `O(m + n)` time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::Set<_> = vec![2, 3, 4].into_iter().collect();
+    ///
+    /// assert_eq!(a.intersection_len(&b), 2);
+    /// ```
+    pub fn intersection_len(&self, other: &Self) -> usize {
+        let (large, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+
+        match (small.first(), small.last(), large.first(), large.last()) {
+            (Some(small_first), Some(small_last), Some(large_first), Some(large_last)) =>
+                if self.cmp().compares_lt(small_last, large_first) ||
+                   self.cmp().compares_lt(large_last, small_first) {
+                    return 0;
+                },
+            _ => return 0,
+        }
+
+        if node::is_small(small.len(), large.len()) {
+            return small.iter().filter(|item| large.contains(item)).count();
+        }
+
+        node::Merge::new(self.map.iter(), other.map.iter(), self.cmp())
+            .filter(|&(_, ref values)| match *values {
+                EitherOrBoth::Both(..) => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Returns the number of items that are in the set, the given set, or both, without collecting
+    /// them.
+    ///
+    /// See [`intersection_len`](#method.intersection_len) for the time complexity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::Set<_> = vec![2, 3, 4].into_iter().collect();
+    ///
+    /// assert_eq!(a.union_len(&b), 4);
+    /// ```
+    pub fn union_len(&self, other: &Self) -> usize {
+        self.len() + other.len() - self.intersection_len(other)
+    }
+
+    /// Returns the number of items that are in the set but not in the given set, without collecting
+    /// them.
+    ///
+    /// See [`intersection_len`](#method.intersection_len) for the time complexity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a: tree::Set<_> = vec![1, 2, 4].into_iter().collect();
+    /// let b: tree::Set<_> = vec![2, 3, 4].into_iter().collect();
+    ///
+    /// assert_eq!(a.difference_len(&b), 1);
+    /// ```
+    pub fn difference_len(&self, other: &Self) -> usize {
+        self.len() - self.intersection_len(other)
+    }
 }
 
 #[cfg(feature = "range")]
@@ -571,9 +888,6 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// # Examples
     ///
     /// ```
-    /// # #![feature(collections_bound)]
-    /// # extern crate tree;
-    /// # fn main() {
     /// use std::collections::Bound::{Excluded, Unbounded};
     ///
     /// let mut set = tree::Set::new();
@@ -583,7 +897,6 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// set.insert(3);
     ///
     /// assert_eq!(set.into_range(Excluded(&1), Unbounded).collect::<Vec<_>>(), [2, 3]);
-    /// # }
     /// ```
     pub fn into_range<Min: ?Sized, Max: ?Sized>(self, min: Bound<&Min>, max: Bound<&Max>)
         -> IntoRange<T> where C: Compare<Min, T> + Compare<Max, T> {
@@ -598,9 +911,6 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// # Examples
     ///
     /// ```
-    /// # #![feature(collections_bound)]
-    /// # extern crate tree;
-    /// # fn main() {
     /// use std::collections::Bound::{Included, Excluded, Unbounded};
     ///
     /// let mut set = tree::Set::new();
@@ -612,13 +922,95 @@ impl<T, C> Set<T, C> where C: Compare<T> {
     /// assert_eq!(set.range(Unbounded, Unbounded).collect::<Vec<_>>(), [&1, &2, &3]);
     /// assert_eq!(set.range(Excluded(&1), Included(&5)).collect::<Vec<_>>(), [&2, &3]);
     /// assert_eq!(set.range(Included(&1), Excluded(&2)).collect::<Vec<_>>(), [&1]);
-    /// # }
     /// ```
     pub fn range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
         -> Range<T> where C: Compare<Min, T> + Compare<Max, T> {
 
         Range(self.map.range(min, max))
     }
+
+    /// Returns an iterator over the set's items that lie in the given range, or `None` if the
+    /// range is invalid.
+    ///
+    /// See [`Map::checked_range`](../map/struct.Map.html#method.checked_range).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Excluded};
+    ///
+    /// let set: tree::Set<_> = (0..5).collect();
+    ///
+    /// assert_eq!(set.checked_range(Included(&1), Excluded(&3)).unwrap().collect::<Vec<_>>(),
+    ///     [&1, &2]);
+    /// assert!(set.checked_range(Included(&3), Included(&1)).is_none());
+    /// ```
+    pub fn checked_range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> Option<Range<T>> where C: Compare<Min, T> + Compare<Max, T> + Compare<Min, Max> {
+
+        self.map.checked_range(min, max).map(Range)
+    }
+
+    /// Returns an iterator over the set's items that lie in the given range, in descending order
+    /// according to the set's comparator.
+    ///
+    /// This is equivalent to `range(min, max).rev()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Included, Unbounded};
+    ///
+    /// let set: tree::Set<_> = (0..5).collect();
+    /// assert_eq!(set.range_rev(Included(&2), Unbounded).collect::<Vec<_>>(), [&4, &3, &2]);
+    /// ```
+    pub fn range_rev<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> iter::Rev<Range<T>> where C: Compare<Min, T> + Compare<Max, T> {
+
+        self.range(min, max).rev()
+    }
+
+    /// Returns an iterator over the set's items that are greater than or equal to the given item.
+    ///
+    /// This is equivalent to `range(Included(min), Unbounded)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = (0..5).collect();
+    /// assert_eq!(set.range_from(&3).collect::<Vec<_>>(), [&3, &4]);
+    /// ```
+    pub fn range_from<Min: ?Sized>(&self, min: &Min) -> Range<T> where C: Compare<Min, T> {
+        Range(self.map.range_from(min))
+    }
+
+    /// Returns an iterator over the set's items that are less than the given item.
+    ///
+    /// This is equivalent to `range(Unbounded, Excluded(max))`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = (0..5).collect();
+    /// assert_eq!(set.range_to(&2).collect::<Vec<_>>(), [&0, &1]);
+    /// ```
+    pub fn range_to<Max: ?Sized>(&self, max: &Max) -> Range<T> where C: Compare<Max, T> {
+        Range(self.map.range_to(max))
+    }
+
+    /// Returns an iterator over the set's items that are less than or equal to the given item.
+    ///
+    /// This is equivalent to `range(Unbounded, Included(max))`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = (0..5).collect();
+    /// assert_eq!(set.range_to_inclusive(&1).collect::<Vec<_>>(), [&0, &1]);
+    /// ```
+    pub fn range_to_inclusive<Max: ?Sized>(&self, max: &Max) -> Range<T> where C: Compare<Max, T> {
+        Range(self.map.range_to_inclusive(max))
+    }
 }
 
 impl<T, C> Debug for Set<T, C> where T: Debug, C: Compare<T> {
@@ -637,6 +1029,12 @@ impl<T, C> Extend<T> for Set<T, C> where C: Compare<T> {
     }
 }
 
+impl<'a, T, C> Extend<&'a T> for Set<T, C> where T: Copy, C: Compare<T> {
+    fn extend<I: IntoIterator<Item=&'a T>>(&mut self, it: I) {
+        self.extend(it.into_iter().cloned());
+    }
+}
+
 impl<T, C> iter::FromIterator<T> for Set<T, C> where C: Compare<T> + Default {
     fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Self {
         let mut set = Set::default();
@@ -697,6 +1095,63 @@ impl<T, C> Ord for Set<T, C> where C: Compare<T> {
     fn cmp(&self, other: &Self) -> Ordering { Ord::cmp(&self.map, &other.map) }
 }
 
+/// A builder that constructs a set from items supplied in ascending order.
+///
+/// See [`map::Builder`](../map/struct.Builder.html) for the time complexity.
+///
+/// # Examples
+///
+/// ```
+/// let mut builder = tree::set::Builder::new();
+///
+/// for i in 0..100 { builder.push(i); }
+///
+/// let set = builder.build();
+/// assert_eq!(set.len(), 100);
+/// assert!(set.contains(&42));
+/// ```
+pub struct Builder<T, C = Natural<T>>(map::Builder<T, (), C>) where C: Compare<T>;
+
+impl<T> Builder<T> where T: Ord {
+    /// Creates a builder for a set ordered according to the natural order of its items.
+    pub fn new() -> Self { Builder(map::Builder::new()) }
+}
+
+impl<T, C> Builder<T, C> where C: Compare<T> {
+    /// Creates a builder for a set ordered according to the given comparator.
+    pub fn with_cmp(cmp: C) -> Self { Builder(map::Builder::with_cmp(cmp)) }
+
+    /// Checks if no items have been pushed.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Returns the number of items that have been pushed.
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Returns a reference to the most recently pushed item, or `None` if no items have been
+    /// pushed.
+    pub fn last(&self) -> Option<&T> { self.0.last_key() }
+
+    /// Pushes an item that is greater than every item pushed so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item is not greater than the most recently pushed item.
+    pub fn push(&mut self, item: T) { self.0.push(item, ()); }
+
+    /// Consumes the builder, returning a set containing the pushed items.
+    pub fn build(self) -> Set<T, C> { Set { map: self.0.build() } }
+}
+
+impl<T, C> Default for Builder<T, C> where C: Compare<T> + Default {
+    fn default() -> Self { Builder::with_cmp(C::default()) }
+}
+
+impl<T, C> Extend<T> for Builder<T, C> where C: Compare<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for item in it { self.push(item); }
+    }
+}
+
 /// An iterator that consumes the set.
 ///
 /// The iterator yields the items in ascending order according to the set's comparator.
@@ -760,6 +1215,38 @@ impl<'a, T> Clone for Iter<'a, T> {
     fn clone(&self) -> Self { Iter(self.0.clone()) }
 }
 
+impl<'a, T> Iter<'a, T> {
+    /// Returns the item that the next call to `next` will yield, without consuming it, or `None`
+    /// if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = vec![2, 1].into_iter().collect();
+    /// let mut it = set.iter();
+    ///
+    /// assert_eq!(it.peek(), Some(&1));
+    /// assert_eq!(it.next(), Some(&1));
+    /// assert_eq!(it.peek(), Some(&2));
+    /// ```
+    pub fn peek(&mut self) -> Option<&'a T> { self.0.peek().map(|e| e.0) }
+
+    /// Returns the item that the next call to `next_back` will yield, without consuming it, or
+    /// `None` if the iterator is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let set: tree::Set<_> = vec![2, 1].into_iter().collect();
+    /// let mut it = set.iter();
+    ///
+    /// assert_eq!(it.peek_back(), Some(&2));
+    /// assert_eq!(it.next_back(), Some(&2));
+    /// assert_eq!(it.peek_back(), Some(&1));
+    /// ```
+    pub fn peek_back(&mut self) -> Option<&'a T> { self.0.peek_back().map(|e| e.0) }
+}
+
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|e| e.0) }
@@ -790,6 +1277,7 @@ impl<T> Iterator for IntoRange<T> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|e| e.0) }
     fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+    fn count(self) -> usize { self.0.count() }
     fn last(self) -> Option<Self::Item> { self.0.last().map(|e| e.0) }
 }
 
@@ -798,6 +1286,11 @@ impl<T> DoubleEndedIterator for IntoRange<T> {
     fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back().map(|e| e.0) }
 }
 
+#[cfg(feature = "range")]
+impl<T> ExactSizeIterator for IntoRange<T> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
 /// An iterator over the set's items that lie in a given range.
 ///
 /// The iterator yields the items in ascending order according to the set's comparator.
@@ -811,11 +1304,23 @@ impl<'a, T> Clone for Range<'a, T> {
     fn clone(&self) -> Self { Range(self.0.clone()) }
 }
 
+#[cfg(feature = "range")]
+impl<'a, T> Range<'a, T> {
+    /// Returns the item that the next call to `next` will yield, without consuming it, or `None`
+    /// if the iterator is exhausted.
+    pub fn peek(&mut self) -> Option<&'a T> { self.0.peek().map(|e| e.0) }
+
+    /// Returns the item that the next call to `next_back` will yield, without consuming it, or
+    /// `None` if the iterator is exhausted.
+    pub fn peek_back(&mut self) -> Option<&'a T> { self.0.peek_back().map(|e| e.0) }
+}
+
 #[cfg(feature = "range")]
 impl<'a, T> Iterator for Range<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|e| e.0) }
     fn size_hint(&self) -> (usize, Option<usize>) { self.0.size_hint() }
+    fn count(self) -> usize { self.0.count() }
     fn last(self) -> Option<Self::Item> { self.0.last().map(|e| e.0) }
 }
 
@@ -824,6 +1329,11 @@ impl<'a, T> DoubleEndedIterator for Range<'a, T> {
     fn next_back(&mut self) -> Option<Self::Item> { self.0.next_back().map(|e| e.0) }
 }
 
+#[cfg(feature = "range")]
+impl<'a, T> ExactSizeIterator for Range<'a, T> {
+    fn len(&self) -> usize { self.0.len() }
+}
+
 /// An entry in the set.
 pub enum Entry<'a, T: 'a> {
     /// An occupied entry.
@@ -832,6 +1342,16 @@ pub enum Entry<'a, T: 'a> {
     Vacant(VacantEntry<'a, T>),
 }
 
+impl<'a, T> Entry<'a, T> {
+    /// Returns a reference to the entry's item.
+    pub fn get(&self) -> &T {
+        match *self {
+            Entry::Occupied(ref e) => e.get(),
+            Entry::Vacant(ref e) => e.get(),
+        }
+    }
+}
+
 /// An occupied entry.
 pub struct OccupiedEntry<'a, T: 'a>(map::OccupiedEntry<'a, T, ()>);
 
@@ -847,6 +1367,9 @@ impl<'a, T> OccupiedEntry<'a, T> {
 pub struct VacantEntry<'a, T: 'a>(map::VacantEntry<'a, T, ()>);
 
 impl<'a, T> VacantEntry<'a, T> {
+    /// Returns a reference to the item that would be inserted.
+    pub fn get(&self) -> &T { self.0.key() }
+
     /// Inserts the entry into the set with its item.
     pub fn insert(self) { self.0.insert(()); }
 }
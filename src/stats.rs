@@ -0,0 +1,153 @@
+// Instrumentation counters, which are only maintained when the `stats` feature is enabled
+//
+// Every map owns a `Counters`, and each operation wraps the map's comparator in a `Counted` for
+// its duration. Entries, which rebalance the tree after the operation that returned them has
+// finished, attribute their rotations to the map through `Counters::rebalance` instead. Without
+// the feature, both are empty and all of their methods are no-ops. The counters are kept behind a
+// mutex so that maps remain `Sync` with the feature enabled.
+
+use compare::Compare;
+#[cfg(feature = "stats")] use std::cell::Cell;
+#[cfg(feature = "stats")] use std::cmp;
+#[cfg(feature = "stats")] use std::sync::Mutex;
+use std::cmp::Ordering;
+
+/// A snapshot of a map's instrumentation counters.
+///
+/// Acquire through [`Map::stats`](map/struct.Map.html#method.stats). Only available with the
+/// `stats` feature.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of times the map's comparator has been called.
+    pub comparisons: u64,
+    /// The number of rotations performed to rebalance the map's tree.
+    pub rotations: u64,
+    /// The number of nodes allocated by insertions into the map.
+    pub allocations: u64,
+    /// The largest number of comparisons performed by a single operation. For a search, this is
+    /// the length of the path it followed from the root.
+    pub max_comparisons: u64,
+}
+
+#[cfg(feature = "stats")]
+thread_local!(static ROTATIONS: Cell<u64> = Cell::new(0));
+
+// Record a rotation, which is attributed to the map whose operation is currently in progress
+#[cfg(feature = "stats")]
+pub fn rotation() { ROTATIONS.with(|rotations| rotations.set(rotations.get() + 1)); }
+
+#[cfg(not(feature = "stats"))]
+pub fn rotation() {}
+
+// Return the number of rotations recorded since `start` was read from the thread's counter, and
+// reset the counter to `start`, so that an enclosing operation does not count them a second time
+#[cfg(feature = "stats")]
+fn take_rotations(start: u64) -> u64 {
+    ROTATIONS.with(|rotations| rotations.replace(start)) - start
+}
+
+#[derive(Default)]
+pub struct Counters {
+    #[cfg(feature = "stats")]
+    stats: Mutex<Stats>,
+}
+
+impl Clone for Counters {
+    #[cfg(feature = "stats")]
+    fn clone(&self) -> Self { Counters { stats: Mutex::new(self.get()) } }
+
+    #[cfg(not(feature = "stats"))]
+    fn clone(&self) -> Self { Counters {} }
+}
+
+impl Counters {
+    #[cfg(feature = "stats")]
+    pub fn count<'a, C: ?Sized>(&'a self, cmp: &'a C) -> Counted<'a, C> {
+        Counted {
+            cmp: cmp,
+            counters: self,
+            comparisons: Cell::new(0),
+            rotations: ROTATIONS.with(Cell::get),
+        }
+    }
+
+    #[cfg(not(feature = "stats"))]
+    pub fn count<'a, C: ?Sized>(&'a self, cmp: &'a C) -> Counted<'a, C> { Counted { cmp: cmp } }
+
+    // Call the given function, attributing the rotations it performs to the map
+    #[cfg(feature = "stats")]
+    pub fn rebalance<T, F>(&self, f: F) -> T where F: FnOnce() -> T {
+        let start = ROTATIONS.with(Cell::get);
+        let result = f();
+        let rotations = take_rotations(start);
+        self.update(|stats| stats.rotations += rotations);
+        result
+    }
+
+    #[cfg(not(feature = "stats"))]
+    pub fn rebalance<T, F>(&self, f: F) -> T where F: FnOnce() -> T { f() }
+
+    #[cfg(feature = "stats")]
+    pub fn allocation(&self) { self.update(|stats| stats.allocations += 1); }
+
+    #[cfg(not(feature = "stats"))]
+    pub fn allocation(&self) {}
+
+    #[cfg(feature = "stats")]
+    pub fn get(&self) -> Stats { *self.stats.lock().unwrap_or_else(|err| err.into_inner()) }
+
+    #[cfg(feature = "stats")]
+    pub fn reset(&self) { self.update(|stats| *stats = Stats::default()); }
+
+    #[cfg(feature = "stats")]
+    fn update<F>(&self, f: F) where F: FnOnce(&mut Stats) {
+        f(&mut self.stats.lock().unwrap_or_else(|err| err.into_inner()));
+    }
+}
+
+// A comparator that counts the comparisons and rotations performed during a single operation,
+// adding them to its map's counters when dropped
+pub struct Counted<'a, C: ?Sized + 'a> {
+    cmp: &'a C,
+    #[cfg(feature = "stats")]
+    counters: &'a Counters,
+    #[cfg(feature = "stats")]
+    comparisons: Cell<u64>,
+    #[cfg(feature = "stats")]
+    rotations: u64,
+}
+
+impl<'a, C: ?Sized> Counted<'a, C> {
+    #[cfg(feature = "stats")]
+    fn tick(&self) { self.comparisons.set(self.comparisons.get() + 1); }
+
+    #[cfg(not(feature = "stats"))]
+    fn tick(&self) {}
+}
+
+#[cfg(feature = "stats")]
+impl<'a, C: ?Sized> Drop for Counted<'a, C> {
+    fn drop(&mut self) {
+        let comparisons = self.comparisons.get();
+        let rotations = take_rotations(self.rotations);
+
+        self.counters.update(|stats| {
+            stats.comparisons += comparisons;
+            stats.rotations += rotations;
+            stats.max_comparisons = cmp::max(stats.max_comparisons, comparisons);
+        });
+    }
+}
+
+impl<'a, C: ?Sized, L: ?Sized, R: ?Sized> Compare<L, R> for Counted<'a, C>
+    where C: Compare<L, R> {
+
+    fn compare(&self, l: &L, r: &R) -> Ordering { self.tick(); self.cmp.compare(l, r) }
+    fn compares_lt(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_lt(l, r) }
+    fn compares_le(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_le(l, r) }
+    fn compares_ge(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_ge(l, r) }
+    fn compares_gt(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_gt(l, r) }
+    fn compares_eq(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_eq(l, r) }
+    fn compares_ne(&self, l: &L, r: &R) -> bool { self.tick(); self.cmp.compares_ne(l, r) }
+}
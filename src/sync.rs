@@ -0,0 +1,335 @@
+//! Concurrent access to ordered maps.
+//!
+//! # Publication
+//!
+//! A [`Writer`](struct.Writer.html) modifies a private persistent map and periodically publishes
+//! its current state. Any number of [`Reader`](struct.Reader.html)s, possibly on other threads,
+//! acquire snapshots of the most recently published state. Since the map's nodes are shared
+//! between versions, publishing a version and acquiring a snapshot both take `O(1)` time, and a
+//! snapshot remains valid and unchanged regardless of subsequent modifications and
+//! publications.
+//!
+//! Publication is protected by a lock that is only held while a single reference-counted pointer
+//! is copied, so readers never wait for a writer's modifications or for each other's scans.
+//! However, readers are not strictly wait-free: acquiring a snapshot may briefly block on a
+//! concurrent publication.
+//!
+//! ```
+//! use std::thread;
+//!
+//! let (mut writer, reader) = tree::sync::new(tree::persistent::Map::new());
+//!
+//! writer.insert(1, "a");
+//! writer.publish();
+//!
+//! let handle = thread::spawn(move || reader.snapshot().iter().map(|e| *e.0).collect::<Vec<_>>());
+//!
+//! writer.insert(2, "b");
+//!
+//! assert_eq!(handle.join().unwrap(), [1]);
+//! ```
+//!
+//! # Lock striping
+//!
+//! A [`ConcurrentMap`](struct.ConcurrentMap.html) partitions its key space into several ranges,
+//! each stored in a separately locked [`Map`](../map/struct.Map.html), so that operations on
+//! keys in different ranges can proceed in parallel.
+
+use compare::Compare;
+#[cfg(feature = "range")] use std::collections::Bound;
+use std::fmt::{self, Debug};
+use std::mem;
+use std::ops;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use super::cmp::Natural;
+use super::map;
+use super::persistent::{Map, Snapshot};
+
+/// Returns a writer that modifies the given map and a reader that acquires snapshots of it.
+///
+/// The map is published immediately, so the reader observes it until the writer publishes a
+/// newer version.
+pub fn new<K, V, C>(map: Map<K, V, C>) -> (Writer<K, V, C>, Reader<K, V, C>)
+    where C: Compare<K> + Clone {
+
+    let published = Arc::new(RwLock::new(map.snapshot()));
+    (Writer { map: map, published: published.clone() }, Reader { published: published })
+}
+
+/// A handle that modifies a private copy of a persistent map and publishes its versions.
+///
+/// The handle derefs to the private map, whose modifications are invisible to readers until the
+/// next call to [`publish`](#method.publish).
+pub struct Writer<K, V, C = Natural<K>> where C: Compare<K> {
+    map: Map<K, V, C>,
+    published: Arc<RwLock<Snapshot<K, V, C>>>,
+}
+
+impl<K, V, C> Writer<K, V, C> where C: Compare<K> + Clone {
+    /// Makes the private map's current state visible to all readers.
+    ///
+    /// This takes `O(1)` time. Snapshots acquired before the publication are unaffected.
+    pub fn publish(&mut self) {
+        let snapshot = self.map.snapshot();
+
+        // Release the lock before dropping the old snapshot, which may be the last owner of
+        // nodes that the private map has since replaced.
+        let old = {
+            let mut published = self.published.write().unwrap_or_else(|err| err.into_inner());
+            mem::replace(&mut *published, snapshot)
+        };
+
+        drop(old);
+    }
+
+    /// Returns a new reader of the versions published by this writer.
+    pub fn reader(&self) -> Reader<K, V, C> { Reader { published: self.published.clone() } }
+}
+
+impl<K, V, C> Debug for Writer<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.map.fmt(f) }
+}
+
+impl<K, V, C> ops::Deref for Writer<K, V, C> where C: Compare<K> {
+    type Target = Map<K, V, C>;
+    fn deref(&self) -> &Map<K, V, C> { &self.map }
+}
+
+impl<K, V, C> ops::DerefMut for Writer<K, V, C> where C: Compare<K> {
+    fn deref_mut(&mut self) -> &mut Map<K, V, C> { &mut self.map }
+}
+
+/// A handle that acquires snapshots of the versions published by a writer.
+///
+/// Readers can be cloned and sent to other threads.
+pub struct Reader<K, V, C = Natural<K>> where C: Compare<K> {
+    published: Arc<RwLock<Snapshot<K, V, C>>>,
+}
+
+impl<K, V, C> Reader<K, V, C> where C: Compare<K> + Clone {
+    /// Returns a snapshot of the most recently published version.
+    ///
+    /// This takes `O(1)` time. The snapshot does not hold any lock, so it can be scanned at
+    /// length without delaying the writer or other readers.
+    pub fn snapshot(&self) -> Snapshot<K, V, C> {
+        self.published.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
+
+impl<K, V, C> Clone for Reader<K, V, C> where C: Compare<K> {
+    fn clone(&self) -> Self { Reader { published: self.published.clone() } }
+}
+
+impl<K, V, C> Debug for Reader<K, V, C> where C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("Reader { .. }") }
+}
+
+/// An ordered map whose key space is partitioned into separately locked stripes.
+///
+/// The map is created with a list of split keys in ascending order. Each key is stored in the
+/// stripe for the range between the greatest split key less than or equal to it and the next
+/// split key, and each stripe is a [`Map`](../map/struct.Map.html) protected by its own
+/// `RwLock`. Operations on a single key only lock that key's stripe, so concurrent operations on
+/// keys in different stripes do not contend.
+///
+/// Operations that span several stripes, like [`len`](#method.len) and
+/// [`range`](#method.range), lock one stripe at a time, so they do not observe a consistent state
+/// of the whole map if it is modified concurrently. This is not a lock-free map, and the split
+/// keys should be chosen to distribute the expected keys evenly across the stripes.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use tree::sync::ConcurrentMap;
+///
+/// let map = Arc::new(ConcurrentMap::new(vec![250, 500, 750]));
+///
+/// let handles: Vec<_> = (0..4).map(|i| {
+///     let map = map.clone();
+///     thread::spawn(move || for key in i * 250..(i + 1) * 250 { map.insert(key, key * 2); })
+/// }).collect();
+///
+/// for handle in handles { handle.join().unwrap(); }
+///
+/// assert_eq!(map.len(), 1000);
+/// assert_eq!(map.get(&600), Some(1200));
+/// ```
+pub struct ConcurrentMap<K, V, C = Natural<K>> where C: Compare<K> {
+    splits: Vec<K>,
+    stripes: Vec<RwLock<map::Map<K, V, C>>>,
+    cmp: C,
+}
+
+impl<K, V> ConcurrentMap<K, V> where K: Ord {
+    /// Creates an empty map ordered according to the natural order of its keys, partitioned at
+    /// the given split keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the split keys are not in strictly ascending order.
+    pub fn new(splits: Vec<K>) -> Self { ConcurrentMap::with_cmp(Natural::default(), splits) }
+}
+
+impl<K, V, C> ConcurrentMap<K, V, C> where C: Compare<K> {
+    /// Creates an empty map ordered according to the given comparator, partitioned at the given
+    /// split keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the split keys are not in strictly ascending order according to the
+    /// comparator.
+    pub fn with_cmp(cmp: C, splits: Vec<K>) -> Self where C: Clone {
+        assert!(splits.windows(2).all(|w| cmp.compares_lt(&w[0], &w[1])),
+                "split keys are not in strictly ascending order");
+
+        let stripes = (0..splits.len() + 1).map(|_| RwLock::new(map::Map::with_cmp(cmp.clone())));
+        ConcurrentMap { splits: splits, stripes: stripes.collect(), cmp: cmp }
+    }
+
+    /// Returns the number of stripes in the map, which is one more than the number of split keys.
+    pub fn stripes(&self) -> usize { self.stripes.len() }
+
+    /// Checks if the map is empty.
+    pub fn is_empty(&self) -> bool { (0..self.stripes.len()).all(|i| self.read(i).is_empty()) }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize { (0..self.stripes.len()).map(|i| self.read(i).len()).sum() }
+
+    /// Returns a reference to the map's comparator.
+    pub fn cmp(&self) -> &C { &self.cmp }
+
+    /// Removes all entries from the map.
+    pub fn clear(&self) {
+        for i in 0..self.stripes.len() { self.write(i).clear(); }
+    }
+
+    /// Inserts an entry into the map, returning the previous value, if any, associated with the
+    /// key.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let i = self.stripe(&key);
+        self.write(i).insert(key, value)
+    }
+
+    /// Removes and returns the entry whose key is equal to the given key, returning `None` if
+    /// the map does not contain the key.
+    pub fn remove<Q: ?Sized>(&self, key: &Q) -> Option<(K, V)> where C: Compare<Q, K> {
+        self.write(self.stripe(key)).remove(key)
+    }
+
+    /// Checks if the map contains the given key.
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where C: Compare<Q, K> {
+        self.read(self.stripe(key)).contains_key(key)
+    }
+
+    /// Returns a copy of the value associated with the given key, or `None` if the map does not
+    /// contain the key.
+    ///
+    /// The value is cloned because the key's stripe is only locked for the duration of the call.
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<V> where C: Compare<Q, K>, V: Clone {
+        self.read(self.stripe(key)).get(key).cloned()
+    }
+
+    /// Applies the given function to the value associated with the given key while its stripe is
+    /// locked, returning `true` if the map contains the key and `false` otherwise.
+    pub fn update<Q: ?Sized, F>(&self, key: &Q, f: F) -> bool
+        where C: Compare<Q, K>, F: FnOnce(&mut V) {
+
+        self.write(self.stripe(key)).update(key, f)
+    }
+
+    /// Consumes the map, returning a `Map` containing all of its entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let map = tree::sync::ConcurrentMap::new(vec![2]);
+    ///
+    /// map.insert(3, "c");
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let map = map.into_map();
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), [(&1, &"a"), (&2, &"b"), (&3, &"c")]);
+    /// ```
+    pub fn into_map(self) -> map::Map<K, V, C> {
+        let entries = self.stripes.into_iter().flat_map(|stripe| {
+            stripe.into_inner().unwrap_or_else(|err| err.into_inner()).into_iter()
+        });
+
+        map::Map::from_parts(self.cmp, entries)
+    }
+
+    // Returns the index of the stripe that contains the given key
+    fn stripe<Q: ?Sized>(&self, key: &Q) -> usize where C: Compare<Q, K> {
+        match self.splits.binary_search_by(|split| self.cmp.compare(key, split).reverse()) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    fn read(&self, i: usize) -> RwLockReadGuard<map::Map<K, V, C>> {
+        self.stripes[i].read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self, i: usize) -> RwLockWriteGuard<map::Map<K, V, C>> {
+        self.stripes[i].write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(feature = "range")]
+impl<K, V, C> ConcurrentMap<K, V, C> where C: Compare<K> {
+    /// Returns copies of the entries in the map whose keys lie in the given range, in ascending
+    /// order.
+    ///
+    /// The stripes that overlap the range are locked one at a time, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::Bound::{Excluded, Included};
+    ///
+    /// let map = tree::sync::ConcurrentMap::new(vec![3, 6]);
+    /// for key in 0..10 { map.insert(key, key * 2); }
+    ///
+    /// assert_eq!(map.range(Excluded(&2), Included(&7)),
+    ///            [(3, 6), (4, 8), (5, 10), (6, 12), (7, 14)]);
+    /// ```
+    pub fn range<Min: ?Sized, Max: ?Sized>(&self, min: Bound<&Min>, max: Bound<&Max>)
+        -> Vec<(K, V)> where C: Compare<Min, K> + Compare<Max, K>, K: Clone, V: Clone {
+
+        let first = match min {
+            Bound::Included(key) | Bound::Excluded(key) => self.stripe(key),
+            Bound::Unbounded => 0,
+        };
+
+        let last = match max {
+            Bound::Included(key) | Bound::Excluded(key) => self.stripe(key),
+            Bound::Unbounded => self.stripes.len() - 1,
+        };
+
+        let mut entries = vec![];
+
+        for i in first..last + 1 {
+            let stripe = self.read(i);
+            let range = stripe.range(min, max).map(|(key, value)| (key.clone(), value.clone()));
+            entries.extend(range);
+        }
+
+        entries
+    }
+}
+
+impl<K, V, C> Debug for ConcurrentMap<K, V, C> where K: Debug, V: Debug, C: Compare<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+
+        for i in 0..self.stripes.len() {
+            let stripe = self.read(i);
+            for (key, value) in stripe.iter() { map.entry(key, value); }
+        }
+
+        map.finish()
+    }
+}
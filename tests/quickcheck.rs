@@ -1,5 +1,3 @@
-#![cfg_attr(feature = "range", feature(collections_bound))]
-
 extern crate compare;
 extern crate quickcheck;
 extern crate tree;
@@ -241,6 +239,33 @@ mod find {
     remove!{u32, u16, ::Find<u32>}
 }
 
+mod insert_entry {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_insert() {
+        fn test(mut map: Map<u8, u16>, key: u8, value: u16) -> bool {
+            let mut expected = map.clone();
+            expected.insert(key, value);
+
+            let ok = {
+                let e = map.insert_entry(key, value);
+                *e.key() == key && *e.get() == value
+            };
+
+            ok && map.iter().eq(expected.iter()) && map.validate().is_ok() && {
+                let removed = map.insert_entry(key, value).remove();
+                expected.remove(&key);
+                removed == (key, value) && map.iter().eq(expected.iter()) &&
+                    map.validate().is_ok()
+            }
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, u16) -> bool);
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Max;
 
@@ -256,6 +281,74 @@ impl<K, C> OccupiedEntry<K, C> for Max where C: Compare<K> {
     }
 }
 
+mod remove_if {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_get_and_remove() {
+        fn test(mut map: Map<u8, u16>, key: u8, threshold: u16) -> bool {
+            let mut expected = map.clone();
+
+            let removed = match expected.get(&key) {
+                Some(&value) if value < threshold => expected.remove(&key),
+                _ => None,
+            };
+
+            map.remove_if(&key, |_, &value| value < threshold) == removed && map == expected &&
+                map.validate().is_ok()
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, u16) -> bool);
+    }
+}
+
+mod replace_value {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn never_inserts() {
+        fn test(mut map: Map<u8, u16>, key: u8, value: u16) -> bool {
+            let old = map.get(&key).cloned();
+            let len = map.len();
+            let result = map.replace_value(&key, value);
+
+            map.len() == len && match old {
+                Some(old) => result == Ok(old) && map.get(&key) == Some(&value),
+                None => result == Err(value) && !map.contains_key(&key),
+            }
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, u16) -> bool);
+    }
+}
+
+mod swap_values {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_get_and_insert() {
+        fn test(mut map: Map<u8, u16>, k1: u8, k2: u8) -> bool {
+            let mut expected = map.clone();
+
+            let swapped = match (expected.get(&k1).cloned(), expected.get(&k2).cloned()) {
+                (Some(v1), Some(v2)) => {
+                    expected.insert(k1, v2);
+                    expected.insert(k2, v1);
+                    true
+                }
+                _ => false,
+            };
+
+            map.swap_values(&k1, &k2) == swapped && map == expected && map.validate().is_ok()
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, u8) -> bool);
+    }
+}
+
 mod last {
     use quickcheck::quickcheck;
     use tree::Map;
@@ -403,8 +496,106 @@ mod pred {
     remove!{u32, u16, ::Pred<u32>}
 }
 
+mod nearest {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_vec() {
+        fn test(map: Map<u8, u16>, key: u8, k: usize) -> bool {
+            let k = k % 16;
+            let entries: Vec<_> = map.iter().collect();
+            let split = entries.iter().position(|e| *e.0 > key).unwrap_or(entries.len());
+
+            let (mut lo, mut hi) = (split, split);
+
+            while hi - lo < k && (lo > 0 || hi < entries.len()) {
+                if lo > 0 && (split - lo <= hi - split || hi == entries.len()) {
+                    lo -= 1;
+                } else {
+                    hi += 1;
+                }
+            }
+
+            map.nearest(&key, k) == &entries[lo..hi]
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, usize) -> bool);
+    }
+}
+
+mod closest {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_linear_search() {
+        fn test(map: Map<u8, u16>, key: u8) -> bool {
+            let dist = |l: &u8, r: &u8| (*l as i32 - *r as i32).abs();
+            let expected = map.iter().fold(None, |best: Option<(&u8, &u16)>, e| match best {
+                Some(b) if dist(&key, b.0) <= dist(&key, e.0) => Some(b),
+                _ => Some(e),
+            });
+
+            map.closest(&key, dist) == expected
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8) -> bool);
+    }
+}
+
+mod first_absent_key {
+    use quickcheck::quickcheck;
+    use tree::Set;
+
+    #[test]
+    fn agrees_with_linear_search() {
+        fn test(set: Set<i8>, start: i8, end: i8) -> bool {
+            let expected = (start as i16..end as i16).map(|i| i as i8).find(|i| !set.contains(i));
+            set.first_missing(start..end) == expected
+        }
+
+        quickcheck(test as fn(Set<i8>, i8, i8) -> bool);
+    }
+}
+
+mod nth_succ {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(map: Map<u8, u16>, key: u8, n: usize) -> bool {
+            let n = n % 64;
+            let succ = map.iter().filter(|e| *e.0 > key).nth(n);
+            let pred = map.iter().rev().filter(|e| *e.0 < key).nth(n);
+            map.nth_succ(&key, n) == succ && map.nth_pred(&key, n) == pred
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, u8, usize) -> bool);
+    }
+}
+
+mod select_range {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>, start: usize, end: usize) -> bool {
+            let (start, end) = (start % 128, end % 128);
+            let expected = map.iter().skip(start).take(end.saturating_sub(start));
+            let it = map.select_range(start..end);
+            it.len() == expected.clone().count() && it.eq(expected)
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, usize, usize) -> bool);
+    }
+}
+
 mod iter {
     use quickcheck::quickcheck;
+    use std::rc::Rc;
     use tree::Map;
 
     #[test]
@@ -442,88 +633,1421 @@ mod iter {
 
         quickcheck(test as fn(Map<u32, u16>) -> bool);
     }
+
+    #[test]
+    fn peek_agrees_with_next() {
+        fn test(map: Map<u32, u16>, ops: Vec<u8>) -> bool {
+            let mut it = map.iter();
+            let mut expected = map.iter();
+
+            ops.into_iter().all(|op| match op % 4 {
+                0 => it.peek() == it.clone().next(),
+                1 => it.peek_back() == it.clone().next_back(),
+                2 => { expected.next(); it.peek() == it.next() && it.clone().eq(expected.clone()) }
+                _ => {
+                    expected.next_back();
+                    it.peek_back() == it.next_back() && it.clone().eq(expected.clone())
+                }
+            })
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn into_iter_drops_remaining_entries() {
+        fn test(keys: Vec<u32>, n: usize) -> bool {
+            let value = Rc::new(());
+            let map: Map<_, _> = keys.into_iter().map(|key| (key, value.clone())).collect();
+            let len = map.len();
+
+            {
+                let mut it = map.into_iter();
+                for _ in 0..n % (len + 1) { it.next(); }
+                if Rc::strong_count(&value) != 1 + len - n % (len + 1) { return false; }
+            }
+
+            Rc::strong_count(&value) == 1
+        }
+
+        quickcheck(test as fn(Vec<u32>, usize) -> bool);
+    }
 }
 
-#[cfg(feature = "range")]
-mod range {
-    use quickcheck::{Arbitrary, Gen, quickcheck};
-    use std::collections::Bound::*;
+mod enumerate_ranked {
+    use quickcheck::quickcheck;
     use tree::Map;
 
-    #[derive(Clone, Debug)]
-    struct Bound<T>(::std::collections::Bound<T>);
+    #[test]
+    fn agrees_with_enumerate() {
+        fn test(map: Map<u32, u16>, front: usize, back: usize) -> bool {
+            let front = front % (map.len() + 1);
+            let back = back % (map.len() - front + 1);
 
-    impl<T> Bound<T> {
-        fn as_ref(&self) -> Bound<&T> {
-            Bound(match self.0 {
-                Included(ref t) => Included(t),
-                Excluded(ref t) => Excluded(t),
-                Unbounded => Unbounded,
-            })
+            let mut it = map.iter();
+            for _ in 0..front { it.next(); }
+            for _ in 0..back { it.next_back(); }
+
+            let expected = map.iter().enumerate().map(|(rank, (key, value))| (rank, key, value))
+                .skip(front).take(map.len() - front - back);
+
+            it.clone().enumerate_ranked().eq(expected.clone()) &&
+                it.enumerate_ranked().rev().eq(expected.rev())
         }
+
+        quickcheck(test as fn(Map<u32, u16>, usize, usize) -> bool);
     }
 
-    impl<T> Arbitrary for Bound<T> where T: Arbitrary {
-        fn arbitrary<G: Gen>(gen: &mut G) -> Self {
-            Bound(match gen.gen_range(0, 3) {
-                0 => Included(T::arbitrary(gen)),
-                1 => Excluded(T::arbitrary(gen)),
-                _ => Unbounded,
-            })
+    #[test]
+    fn select() {
+        fn test(map: Map<u32, u16>, start: usize, end: usize) -> bool {
+            map.select_range(start..end).enumerate_ranked()
+                .eq((start..end).zip(map.select_range(start..end))
+                    .map(|(rank, (key, value))| (rank, key, value)))
         }
 
-        fn shrink(&self) -> Box<Iterator<Item=Self>> {
-            match self.0 {
-                Included(ref t) => Box::new(t.shrink().map(|t| Bound(Included(t)))),
-                Excluded(ref t) => Box::new(t.shrink().map(|t| Bound(Excluded(t)))),
-                Unbounded => Box::new(None.into_iter()),
+        quickcheck(test as fn(Map<u32, u16>, usize, usize) -> bool);
+    }
+}
+
+mod iter_chunks {
+    use quickcheck::quickcheck;
+    use std::cmp;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>, size: usize) -> bool {
+            let size = size % 8 + 1;
+            let mut chunks = map.iter_chunks(size);
+            let mut it = map.iter();
+
+            while let Some(chunk) = chunks.next_chunk() {
+                if chunk.len() != cmp::min(size, it.len()) { return false; }
+                if !chunk.iter().cloned().eq(it.by_ref().take(size)) { return false; }
             }
+
+            it.next().is_none()
         }
+
+        quickcheck(test as fn(Map<u32, u16>, usize) -> bool);
     }
+}
+
+mod for_each {
+    use quickcheck::quickcheck;
+    use tree::Map;
 
     #[test]
-    fn range() {
-        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
-            let r = map.range(min.as_ref().0, max.as_ref().0);
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>) -> bool {
+            let mut entries = vec![];
+            map.for_each(|key, value| entries.push((*key, *value)));
+            entries == map.iter().map(|e| (*e.0, *e.1)).collect::<Vec<_>>()
+        }
 
-            let i = map.iter()
-                .skip_while(|e| match min.0 {
-                    Included(ref t) => e.0 < t,
-                    Excluded(ref t) => e.0 <= t,
-                    Unbounded => false,
-                })
-            .take_while(|e| match max.0 {
-                Included(ref t) => e.0 <= t,
-                Excluded(ref t) => e.0 < t,
-                Unbounded => true,
+        quickcheck(test as fn(Map<u32, u16>) -> bool);
+    }
+
+    #[test]
+    fn try_stops_at_first_error() {
+        fn test(map: Map<u32, u16>, limit: u32) -> bool {
+            let mut keys = vec![];
+            let result = map.try_for_each(|key, _| {
+                if *key > limit { return Err(*key); }
+                keys.push(*key);
+                Ok(())
             });
 
-            r.collect::<Vec<_>>() == i.collect::<Vec<_>>()
+            let expected: Vec<_> = map.iter().map(|e| *e.0).collect();
+            result == expected.iter().find(|key| **key > limit).map_or(Ok(()), |key| Err(*key)) &&
+                keys == expected.into_iter().take_while(|key| *key <= limit).collect::<Vec<_>>()
         }
 
-        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+        quickcheck(test as fn(Map<u32, u16>, u32) -> bool);
     }
+}
+
+mod map_values {
+    use quickcheck::quickcheck;
+    use tree::Map;
 
     #[test]
-    fn range_rev() {
-        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
-            let r = map.range(min.as_ref().0, max.as_ref().0).rev();
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>) -> bool {
+            let f = |key: &u32, value: u16| key.wrapping_add(value as u32);
+            let expected: Vec<_> = map.iter().map(|e| (*e.0, f(e.0, *e.1))).collect();
+            let map = map.map_values(f);
+            map.len() == expected.len() && map.into_iter().collect::<Vec<_>>() == expected
+        }
 
-            let i = map.iter().rev()
-                .skip_while(|e| match max.0 {
-                    Included(ref t) => e.0 > t,
-                    Excluded(ref t) => e.0 >= t,
-                    Unbounded => false,
-                })
-            .take_while(|e| match min.0 {
-                Included(ref t) => e.0 >= t,
-                Excluded(ref t) => e.0 > t,
-                Unbounded => true,
-            });
+        quickcheck(test as fn(Map<u32, u16>) -> bool);
+    }
+}
 
-            r.collect::<Vec<_>>() == i.collect::<Vec<_>>()
+mod eq_keys {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(l: Map<u32, u16>, r: Map<u32, ()>) -> bool {
+            let expected = l.iter().map(|e| e.0).eq(r.iter().map(|e| e.0));
+            l.eq_keys(&r) == expected && l.eq_keys(&l.clone().map_values(|_, _| ()))
         }
 
-        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+        quickcheck(test as fn(Map<u32, u16>, Map<u32, ()>) -> bool);
+    }
+}
+
+mod is_disjoint_keys {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_contains_key() {
+        fn test(l: Map<u8, u16>, r: Map<u8, ()>) -> bool {
+            let expected = !l.iter().any(|e| r.contains_key(e.0));
+            l.is_disjoint_keys(&r) == expected && r.is_disjoint_keys(&l) == expected
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Map<u8, ()>) -> bool);
+    }
+}
+
+mod map_ops {
+    use quickcheck::quickcheck;
+    use tree::Map;
+    use tree::map::EitherOrBoth;
+
+    #[test]
+    fn union_agrees_with_get() {
+        fn test(l: Map<u8, u16>, r: Map<u8, u32>) -> bool {
+            let mut keys = l.iter().map(|e| *e.0).chain(r.iter().map(|e| *e.0)).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            let expected = keys.iter().map(|key| (key, match (l.get(key), r.get(key)) {
+                (Some(a), Some(b)) => EitherOrBoth::Both(a, b),
+                (Some(a), None) => EitherOrBoth::Left(a),
+                (None, Some(b)) => EitherOrBoth::Right(b),
+                (None, None) => unreachable!(),
+            }));
+
+            l.union(&r).eq(expected)
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Map<u8, u32>) -> bool);
+    }
+
+    #[test]
+    fn intersection_agrees_with_get() {
+        fn test(l: Map<u8, u16>, r: Map<u8, u32>) -> bool {
+            let expected = l.iter().filter_map(|(k, v)| r.get(k).map(|w| (k, v, w)));
+            l.intersection(&r).eq(expected)
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Map<u8, u32>) -> bool);
+    }
+
+    #[test]
+    fn difference_agrees_with_contains_key() {
+        fn test(l: Map<u8, u16>, r: Map<u8, u32>) -> bool {
+            l.difference(&r).eq(l.iter().filter(|e| !r.contains_key(e.0))) &&
+                r.difference(&l).eq(r.iter().filter(|e| !l.contains_key(e.0)))
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Map<u8, u32>) -> bool);
+    }
+}
+
+mod invert {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_insert() {
+        fn test(map: Map<u16, u8>) -> bool {
+            let mut expected = Map::new();
+            for (&key, &value) in &map { expected.insert(value, key); }
+            map.invert() == expected
+        }
+
+        quickcheck(test as fn(Map<u16, u8>) -> bool);
+    }
+
+    #[test]
+    fn multi_keeps_every_key() {
+        fn test(map: Map<u16, u8>) -> bool {
+            let inverse = map.clone().invert_multi();
+
+            inverse.iter().map(|e| e.1.len()).sum::<usize>() == map.len() &&
+                inverse.iter().all(|(value, keys)| {
+                    keys.windows(2).all(|w| w[0] < w[1]) &&
+                        keys.iter().all(|key| map[key] == *value)
+                })
+        }
+
+        quickcheck(test as fn(Map<u16, u8>) -> bool);
+    }
+}
+
+mod group_by {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_entry() {
+        fn test(map: Map<u16, u8>, modulus: u16) -> bool {
+            let f = |key: &u16, value: &u8| (key / (modulus % 8 + 1)) ^ (*value as u16 % 3);
+
+            let mut expected = Map::new();
+
+            for (&key, &value) in &map {
+                expected.entry(f(&key, &value)).or_insert_with(Vec::new).push((key, value));
+            }
+
+            map.group_by(f) == expected
+        }
+
+        quickcheck(test as fn(Map<u16, u8>, u16) -> bool);
+    }
+}
+
+mod filter_map {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>) -> bool {
+            let f = |key: &u32, value: u16| if key % 3 == 0 { None } else { Some(value / 2) };
+            let expected: Vec<_> =
+                map.iter().filter_map(|e| f(e.0, *e.1).map(|v| (*e.0, v))).collect();
+            let map = map.filter_map(f);
+            map.len() == expected.len() && map.into_iter().collect::<Vec<_>>() == expected
+        }
+
+        quickcheck(test as fn(Map<u32, u16>) -> bool);
+    }
+}
+
+mod partition {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_iter() {
+        fn test(map: Map<u32, u16>) -> bool {
+            let f = |key: &u32, value: &u16| (*key ^ *value as u32) % 2 == 0;
+            let (pass, fail): (Vec<_>, Vec<_>) =
+                map.clone().into_iter().partition(|e| f(&e.0, &e.1));
+            let (map_pass, map_fail) = map.partition(f);
+            map_pass.validate().is_ok() && map_fail.validate().is_ok() &&
+                map_pass.into_iter().collect::<Vec<_>>() == pass &&
+                map_fail.into_iter().collect::<Vec<_>>() == fail
+        }
+
+        quickcheck(test as fn(Map<u32, u16>) -> bool);
+    }
+}
+
+#[cfg(feature = "stats")]
+mod nodes {
+    use quickcheck::quickcheck;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_depths() {
+        fn test(map: Map<u32, u16>) -> bool {
+            let mut keys: Vec<_> = map.nodes().map(|node| node.1).collect();
+            keys.sort();
+
+            let total_depth: usize = map.nodes().map(|node| node.0).sum();
+
+            map.nodes().len() == map.len() &&
+                keys == map.iter().map(|e| e.0).collect::<Vec<_>>() &&
+                map.nodes().map(|node| node.0).max().unwrap_or(0) == map.height() &&
+                (map.is_empty() || total_depth as f64 / map.len() as f64 == map.average_depth())
+        }
+
+        quickcheck(test as fn(Map<u32, u16>) -> bool);
+    }
+}
+
+#[cfg(feature = "stats")]
+mod stats {
+    use quickcheck::quickcheck;
+    use tree::Map;
+    use tree::map::Entry;
+
+    #[test]
+    fn entries_agree_with_insert_and_remove() {
+        fn test(keys: Vec<u8>) -> bool {
+            let mut map = Map::new();
+            let mut entry_map = Map::new();
+
+            for &key in &keys {
+                map.insert(key, ());
+                entry_map.entry(key).or_insert(());
+            }
+
+            for &key in &keys[..keys.len() / 2] {
+                map.remove(&key);
+                if let Entry::Occupied(e) = entry_map.entry(key) { e.remove(); }
+            }
+
+            let (stats, entry_stats) = (map.stats(), entry_map.stats());
+            (stats.rotations, stats.allocations) == (entry_stats.rotations, entry_stats.allocations)
+        }
+
+        quickcheck(test as fn(Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn insert_max_agrees_with_insert() {
+        fn test(len: u8) -> bool {
+            let mut map = Map::new();
+            let mut max_map = Map::new();
+
+            for key in 0..len {
+                map.insert(key, ());
+                max_map.insert_max(key, ());
+            }
+
+            let (stats, max_stats) = (map.stats(), max_map.stats());
+            (stats.rotations, stats.allocations) == (max_stats.rotations, max_stats.allocations)
+        }
+
+        quickcheck(test as fn(u8) -> bool);
+    }
+}
+
+mod append {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeMap;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_btree_map() {
+        fn test(a: Vec<(u8, u16)>, b: Vec<(u8, u16)>) -> bool {
+            let mut ma: Map<u8, u16> = a.iter().cloned().collect();
+            let mut mb: Map<u8, u16> = b.iter().cloned().collect();
+            let mut ea: BTreeMap<u8, u16> = a.into_iter().collect();
+            let mut eb: BTreeMap<u8, u16> = b.into_iter().collect();
+
+            ma.append(&mut mb);
+            ea.append(&mut eb);
+
+            mb.is_empty() && ma.len() == ea.len() && ma.iter().eq(ea.iter()) &&
+                ma.validate().is_ok()
+        }
+
+        quickcheck(test as fn(Vec<(u8, u16)>, Vec<(u8, u16)>) -> bool);
+    }
+}
+
+mod split_off {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeMap;
+    use tree::Map;
+
+    #[test]
+    fn agrees_with_btree_map() {
+        fn test(entries: Vec<(u8, u16)>, key: u8) -> bool {
+            let mut map: Map<u8, u16> = entries.iter().cloned().collect();
+            let mut expected: BTreeMap<u8, u16> = entries.into_iter().collect();
+
+            let right = map.split_off(&key);
+            let expected_right = expected.split_off(&key);
+
+            map.iter().eq(expected.iter()) && right.iter().eq(expected_right.iter()) &&
+                map.len() == expected.len() && right.len() == expected_right.len() &&
+                map.validate().is_ok() && right.validate().is_ok()
+        }
+
+        quickcheck(test as fn(Vec<(u8, u16)>, u8) -> bool);
+    }
+}
+
+mod apply_batch {
+    use quickcheck::quickcheck;
+    use tree::Map;
+    use tree::map::BatchOp;
+
+    #[test]
+    fn agrees_with_insert_and_remove() {
+        fn test(mut map: Map<u8, u16>, mut ops: Vec<(u8, Option<u16>)>) -> bool {
+            ops.sort_by(|a, b| a.0.cmp(&b.0));
+            ops.dedup_by_key(|op| op.0);
+
+            let mut expected = map.clone();
+
+            for &(key, op) in &ops {
+                match op {
+                    Some(value) => { expected.insert(key, value); }
+                    None => { expected.remove(&key); }
+                }
+            }
+
+            map.apply_batch(ops.into_iter().map(|(key, op)| {
+                (key, op.map_or(BatchOp::Delete, BatchOp::Upsert))
+            }));
+
+            map.validate().is_ok() && map == expected
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Vec<(u8, Option<u16>)>) -> bool);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch keys are not in strictly ascending order")]
+    fn panics_on_unordered_keys() {
+        let mut map = Map::new();
+        map.insert(1, 1);
+        map.apply_batch(vec![(2, BatchOp::Upsert(2)), (2, BatchOp::Delete)]);
+    }
+}
+
+mod builder {
+    use quickcheck::quickcheck;
+    use tree::map::{Builder, Map};
+
+    #[test]
+    fn agrees_with_collect() {
+        fn test(keys: Vec<u32>, more: Vec<u32>) -> bool {
+            let mut expected: Map<u32, u32> = keys.iter().map(|&k| (k, k)).collect();
+            let mut builder = Builder::new();
+            builder.extend(expected.iter().map(|e| (*e.0, *e.1)));
+
+            let mut map = builder.build();
+            if map != expected || map.validate().is_err() { return false; }
+
+            for key in more {
+                map.insert(key, key);
+                expected.insert(key, key);
+            }
+
+            map == expected && map.validate().is_ok()
+        }
+
+        quickcheck(test as fn(Vec<u32>, Vec<u32>) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_unordered_key() {
+        let mut builder = Builder::new();
+        builder.push(2, ());
+        builder.push(1, ());
+    }
+}
+
+mod set_ops_len {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeSet;
+    use tree::Set;
+
+    #[test]
+    fn lens_agree_with_btree_set() {
+        fn test(a: Vec<u8>, b: Vec<u8>) -> bool {
+            let sa: Set<u8> = a.iter().cloned().collect();
+            let sb: Set<u8> = b.iter().cloned().collect();
+            let ea: BTreeSet<u8> = a.into_iter().collect();
+            let eb: BTreeSet<u8> = b.into_iter().collect();
+
+            sa.intersection_len(&sb) == ea.intersection(&eb).count() &&
+                sa.union_len(&sb) == ea.union(&eb).count() &&
+                sa.difference_len(&sb) == ea.difference(&eb).count() &&
+                sb.difference_len(&sa) == eb.difference(&ea).count()
+        }
+
+        quickcheck(test as fn(Vec<u8>, Vec<u8>) -> bool);
+    }
+}
+
+mod multimap {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeMap;
+    use tree::MultiMap;
+
+    // Apply insertions and removals of all values for a key to both a multimap and a model
+    fn build(ops: Vec<(bool, u8, u16)>) -> (MultiMap<u8, u16>, BTreeMap<u8, Vec<u16>>) {
+        let mut map = MultiMap::new();
+        let mut model = BTreeMap::new();
+
+        for (insert, key, value) in ops {
+            if insert {
+                map.insert(key, value);
+                model.entry(key).or_insert_with(Vec::new).push(value);
+            } else {
+                let values = map.remove_all(&key);
+                assert_eq!(values, model.remove(&key).unwrap_or_else(Vec::new));
+            }
+        }
+
+        (map, model)
+    }
+
+    #[test]
+    fn agrees_with_btree_map() {
+        fn test(ops: Vec<(bool, u8, u16)>) -> bool {
+            let (map, model) = build(ops);
+
+            let expected = model.iter().flat_map(|(k, vs)| vs.iter().map(move |v| (k, v)));
+
+            map.len() == model.values().map(Vec::len).sum::<usize>() &&
+                map.keys_len() == model.len() &&
+                map.iter().eq(expected.clone()) &&
+                map.iter().rev().eq(expected.collect::<Vec<_>>().into_iter().rev()) &&
+                map.groups().eq(model.iter().map(|(k, vs)| (k, &vs[..]))) &&
+                model.iter().all(|(k, vs)| map.get_all(k) == &vs[..] && map.get(k) == vs.first())
+        }
+
+        quickcheck(test as fn(Vec<(bool, u8, u16)>) -> bool);
+    }
+
+    #[test]
+    fn iter_from_both_ends() {
+        fn test(ops: Vec<(bool, u8, u16)>, back: Vec<bool>) -> bool {
+            let (map, _) = build(ops);
+            let expected = map.iter().collect::<Vec<_>>();
+            let (mut front, mut rear) = (0, expected.len());
+            let mut it = map.iter();
+
+            for back in back {
+                let e = if back { it.next_back() } else { it.next() };
+                if front == rear { return e.is_none() && it.len() == 0; }
+
+                if back {
+                    rear -= 1;
+                    if e != Some(expected[rear]) { return false; }
+                } else {
+                    if e != Some(expected[front]) { return false; }
+                    front += 1;
+                }
+
+                if it.len() != rear - front { return false; }
+            }
+
+            true
+        }
+
+        quickcheck(test as fn(Vec<(bool, u8, u16)>, Vec<bool>) -> bool);
+    }
+}
+
+mod multiset {
+    use quickcheck::quickcheck;
+    use std::cmp;
+    use std::collections::BTreeMap;
+    use tree::Multiset;
+
+    // Apply insertions and removals of several occurrences of an item to both a multiset and a
+    // model
+    fn build(ops: Vec<(bool, u8, u8)>) -> (Multiset<u8>, BTreeMap<u8, usize>) {
+        let mut set = Multiset::new();
+        let mut model = BTreeMap::new();
+
+        for (insert, item, n) in ops {
+            let n = n as usize % 4;
+            let count = model.get(&item).cloned().unwrap_or(0);
+
+            if insert {
+                assert_eq!(set.insert_n(item, n), count);
+                if n != 0 { model.insert(item, count + n); }
+            } else {
+                assert_eq!(set.remove_n(&item, n), cmp::min(count, n));
+                if count > n { model.insert(item, count - n); } else { model.remove(&item); }
+            }
+        }
+
+        (set, model)
+    }
+
+    #[test]
+    fn agrees_with_btree_map() {
+        fn test(ops: Vec<(bool, u8, u8)>) -> bool {
+            let (set, model) = build(ops);
+
+            let expected = model.iter().flat_map(|(item, &n)| (0..n).map(move |_| item));
+
+            set.len() == model.values().sum::<usize>() && set.distinct_len() == model.len() &&
+                set.iter().eq(expected.clone()) &&
+                set.iter().rev().eq(expected.collect::<Vec<_>>().into_iter().rev()) &&
+                set.counts().eq(model.iter().map(|(item, &n)| (item, n))) &&
+                (0..255).all(|item| set.count(&item) == model.get(&item).cloned().unwrap_or(0))
+        }
+
+        quickcheck(test as fn(Vec<(bool, u8, u8)>) -> bool);
+    }
+
+    #[test]
+    fn set_ops_agree_with_counts() {
+        fn test(a: Vec<(bool, u8, u8)>, b: Vec<(bool, u8, u8)>) -> bool {
+            let (a, _) = build(a);
+            let (b, _) = build(b);
+
+            let mut items = a.counts().chain(b.counts()).map(|e| *e.0).collect::<Vec<_>>();
+            items.sort();
+            items.dedup();
+
+            let union = items.iter().map(|item| (item, cmp::max(a.count(item), b.count(item))));
+            let sum = items.iter().map(|item| (item, a.count(item) + b.count(item)));
+            let intersection = items.iter()
+                .map(|item| (item, cmp::min(a.count(item), b.count(item))))
+                .filter(|e| e.1 != 0);
+
+            a.union(&b).eq(union) && a.sum(&b).eq(sum) && a.intersection(&b).eq(intersection)
+        }
+
+        quickcheck(test as fn(Vec<(bool, u8, u8)>, Vec<(bool, u8, u8)>) -> bool);
+    }
+}
+
+mod bi_ordered_map {
+    use compare::{Compare, natural};
+    use quickcheck::quickcheck;
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+    use std::panic::{self, AssertUnwindSafe};
+    use tree::BiOrderedMap;
+
+    #[test]
+    fn agrees_with_sorted_btree_map() {
+        fn test(ops: Vec<(u8, u8, u16)>) -> bool {
+            let mut map = BiOrderedMap::new();
+            let mut model = BTreeMap::new();
+
+            for (op, key, value) in ops {
+                let agrees = match op % 3 {
+                    0 => map.insert(key, value) == model.insert(key, value),
+                    1 => map.remove(&key) == model.remove(&key).map(|v| (key, v)),
+                    _ => {
+                        let updated = model.get_mut(&key).map(|v| *v = v.wrapping_add(value));
+                        map.update(&key, |v| *v = v.wrapping_add(value)) == updated.is_some()
+                    }
+                };
+
+                if !agrees { return false; }
+            }
+
+            let mut by_value = model.iter().map(|(k, v)| (v, k)).collect::<Vec<_>>();
+            by_value.sort();
+
+            map.len() == model.len() && map.iter().eq(model.iter()) &&
+                map.iter_by_value().eq(by_value.into_iter().map(|(v, k)| (k, v)))
+        }
+
+        quickcheck(test as fn(Vec<(u8, u8, u16)>) -> bool);
+    }
+
+    #[test]
+    fn index_keeps_stored_keys() {
+        // compares keys by their first part only, so that equal keys can be told apart
+        #[derive(Clone)]
+        struct ByFirst;
+
+        impl Compare<(u8, u8)> for ByFirst {
+            fn compare(&self, l: &(u8, u8), r: &(u8, u8)) -> Ordering { l.0.cmp(&r.0) }
+        }
+
+        fn test(ops: Vec<((u8, u8), u16)>) -> bool {
+            let mut map = BiOrderedMap::with_cmps(ByFirst, natural());
+
+            for ((group, tag), value) in ops {
+                map.insert((group % 16, tag), value);
+            }
+
+            let mut by_value = map.iter().map(|(k, v)| (v, k)).collect::<Vec<_>>();
+            by_value.sort_by(|l, r| l.0.cmp(r.0).then((l.1).0.cmp(&(r.1).0)));
+
+            map.iter_by_value().eq(by_value.into_iter().map(|(v, k)| (k, v)))
+        }
+
+        quickcheck(test as fn(Vec<((u8, u8), u16)>) -> bool);
+    }
+
+    #[test]
+    fn update_keeps_entry_if_function_panics() {
+        let mut map = BiOrderedMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            map.update(&"a", |score| { *score += 10; panic!("update failed"); })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(map.iter().collect::<Vec<_>>(), [(&"a", &1), (&"b", &2)]);
+        assert_eq!(map.iter_by_value().collect::<Vec<_>>(), [(&"a", &1), (&"b", &2)]);
+    }
+}
+
+mod persistent {
+    use quickcheck::quickcheck;
+    use tree::{Map, persistent};
+
+    #[test]
+    fn agrees_with_map() {
+        fn test(ops: Vec<(u8, u8, u16)>) -> bool {
+            let mut map = Map::new();
+            let mut pmap = persistent::Map::new();
+            let mut versions = vec![];
+
+            for (op, key, value) in ops {
+                versions.push((map.clone(), pmap.clone()));
+
+                let agrees = match op % 4 {
+                    0 => map.insert(key, value) == pmap.insert(key, value),
+                    1 => map.remove(&key) == pmap.remove(&key),
+                    2 => map.remove_first() == pmap.remove_first(),
+                    _ => map.remove_last() == pmap.remove_last(),
+                };
+
+                if !agrees || pmap.validate().is_err() { return false; }
+            }
+
+            versions.push((map, pmap));
+            versions.iter().all(|v| v.0.len() == v.1.len() && v.0.iter().eq(v.1.iter()))
+        }
+
+        quickcheck(test as fn(Vec<(u8, u8, u16)>) -> bool);
+    }
+
+    #[test]
+    fn set_ops_agree_with_map() {
+        fn test(a: Vec<(u8, u16)>, b: Vec<(u8, u16)>, shared: Vec<(u8, u16)>) -> bool {
+            let base: persistent::Map<_, _> = shared.into_iter().collect();
+
+            let mut pa = base.clone();
+            pa.extend(a);
+            let mut pb = base;
+            pb.extend(b);
+
+            let (ma, mb) = (pa.to_map(), pb.to_map());
+
+            let mut union = mb.clone();
+            union.extend(ma.iter().map(|(&k, &v)| (k, v)));
+            let intersection: Map<_, _> =
+                ma.iter().filter(|e| mb.contains_key(e.0)).map(|(&k, &v)| (k, v)).collect();
+            let difference: Map<_, _> =
+                ma.iter().filter(|e| !mb.contains_key(e.0)).map(|(&k, &v)| (k, v)).collect();
+
+            let agrees = |p: persistent::Map<u8, u16>, m: &Map<u8, u16>| {
+                p.validate().is_ok() && p.len() == m.len() && p.iter().eq(m.iter())
+            };
+
+            agrees(pa.union(&pb), &union) &&
+                agrees(pa.intersection(&pb), &intersection) &&
+                agrees(pa.difference(&pb), &difference) &&
+                agrees(pa.union(&pa), &ma) && agrees(pa.intersection(&pa), &ma) &&
+                pa.difference(&pa).is_empty()
+        }
+
+        quickcheck(test as fn(Vec<(u8, u16)>, Vec<(u8, u16)>, Vec<(u8, u16)>) -> bool);
+    }
+
+    #[test]
+    fn versioned_map_agrees_with_snapshots() {
+        fn test(ops: Vec<(u8, u8, u16)>) -> bool {
+            let mut map = persistent::VersionedMap::new();
+            let mut working = Map::new();
+            let mut kept: Vec<(u64, Map<u8, u16>)> = vec![];
+            let mut issued = vec![];
+
+            for (op, key, value) in ops {
+                match op % 4 {
+                    0 => { map.insert(key, value); working.insert(key, value); }
+                    1 => { map.remove(&key); working.remove(&key); }
+                    2 => {
+                        let version = map.commit();
+                        if issued.last().map_or(false, |&v| version <= v) { return false; }
+                        issued.push(version);
+                        kept.push((version, working.clone()));
+                    }
+                    _ => if !kept.is_empty() {
+                        let i = key as usize % kept.len();
+                        map.rollback_to(kept[i].0);
+                        kept.truncate(i + 1);
+                        working = kept[i].1.clone();
+                    },
+                }
+
+                if !map.iter().eq(working.iter()) ||
+                    map.latest_version() != kept.last().map(|v| v.0) { return false; }
+            }
+
+            issued.iter().all(|&version| {
+                match kept.iter().find(|v| v.0 == version) {
+                    Some(v) => map.version(version).map_or(false, |m| m.iter().eq(v.1.iter())),
+                    None => map.version(version).is_none(),
+                }
+            })
+        }
+
+        quickcheck(test as fn(Vec<(u8, u8, u16)>) -> bool);
+    }
+}
+
+mod checked {
+    use quickcheck::quickcheck;
+    use std::cell::Cell;
+    use tree::Map;
+    use tree::checked::CheckedMap;
+
+    #[test]
+    fn agrees_with_map() {
+        fn test(ops: Vec<(u8, u8, u16)>) -> bool {
+            let mut map = CheckedMap::new();
+            let mut expected = Map::new();
+
+            for (op, key, value) in ops {
+                let agrees = match op % 5 {
+                    0 => {
+                        let mut e = map.entry(key);
+                        *e.or_insert(0) += value as u32;
+                        *expected.entry(key).or_insert(0) += value as u32;
+                        e.get() == expected.get(&key)
+                    }
+                    1 => map.entry(key).remove() == expected.remove(&key),
+                    2 => {
+                        map.retain(|k, v| { *v += 1; k % 3 != 0 });
+                        expected =
+                            expected.filter_map(|k, v| if k % 3 != 0 { Some(v + 1) } else { None });
+                        true
+                    }
+                    3 => {
+                        let mut right = map.split_off(&key);
+                        let expected_right = expected.split_off(&key);
+                        let agrees = right.iter().eq(expected_right.iter());
+                        map.append(&mut right);
+                        expected.extend(expected_right);
+                        agrees && right.is_empty()
+                    }
+                    _ => map.first_entry().and_then(|e| e.remove()) == expected.remove_first(),
+                };
+
+                if !agrees || !map.iter().eq(expected.iter()) { return false; }
+            }
+
+            true
+        }
+
+        quickcheck(test as fn(Vec<(u8, u8, u16)>) -> bool);
+    }
+
+    #[test]
+    #[should_panic(expected = "map invariant violated after `entry`")]
+    fn entry_panics_on_broken_comparator() {
+        let reversed = Cell::new(false);
+        let cmp = |l: &u32, r: &u32| if reversed.get() { r.cmp(l) } else { l.cmp(r) };
+
+        let mut map = CheckedMap::with_cmp(cmp);
+        map.insert(1, 1);
+        map.insert(2, 2);
+
+        let mut e = map.entry(1);
+        reversed.set(true);
+        *e.get_mut().unwrap() += 1;
+    }
+}
+
+mod sync {
+    use quickcheck::quickcheck;
+    use tree::Map;
+    use tree::sync::ConcurrentMap;
+
+    #[test]
+    fn concurrent_map_agrees_with_map() {
+        fn test(splits: Vec<u8>, ops: Vec<(bool, u8, u16)>) -> bool {
+            let mut splits = splits;
+            splits.sort();
+            splits.dedup();
+
+            let mut map = Map::new();
+            let cmap = ConcurrentMap::new(splits);
+
+            for (insert, key, value) in ops {
+                let agrees = if insert {
+                    map.insert(key, value) == cmap.insert(key, value)
+                } else {
+                    map.remove(&key) == cmap.remove(&key)
+                };
+
+                if !agrees || map.len() != cmap.len() { return false; }
+            }
+
+            map.iter().all(|(key, value)| cmap.get(key) == Some(*value)) && cmap.into_map() == map
+        }
+
+        quickcheck(test as fn(Vec<u8>, Vec<(bool, u8, u16)>) -> bool);
+    }
+}
+
+mod transaction {
+    use compare::Compare;
+    use quickcheck::quickcheck;
+    use std::cmp::Ordering;
+    use tree::Map;
+
+    #[test]
+    fn rollback_restores_map() {
+        fn test(map: Map<u8, u16>, ops: Vec<(u8, u8, u16)>, commit: bool) -> bool {
+            let original = map.clone();
+            let mut expected = map.clone();
+            let mut map = map;
+
+            {
+                let mut txn = map.transaction();
+
+                for (op, key, value) in ops {
+                    let agrees = match op % 3 {
+                        0 => txn.insert(key, value) == expected.insert(key, value),
+                        1 => txn.remove(&key) == expected.remove(&key),
+                        _ => txn.update(&key, |v| *v ^= value) ==
+                             expected.update(&key, |v| *v ^= value),
+                    };
+
+                    if !agrees { return false; }
+                }
+
+                if commit { txn.commit(); } else { txn.rollback(); }
+            }
+
+            map.validate().is_ok() && map == if commit { expected } else { original }
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Vec<(u8, u8, u16)>, bool) -> bool);
+
+        // compares keys by their first part only, so that equal keys can be told apart
+        #[derive(Clone)]
+        struct ByFirst;
+
+        impl Compare<(u8, u8)> for ByFirst {
+            fn compare(&self, l: &(u8, u8), r: &(u8, u8)) -> Ordering { l.0.cmp(&r.0) }
+        }
+
+        fn coarse(entries: Vec<((u8, u8), u16)>, ops: Vec<(u8, (u8, u8), u16)>, commit: bool)
+            -> bool {
+
+            let mut map = Map::with_cmp(ByFirst);
+            map.extend(entries.into_iter().map(|((g, s), v)| ((g % 16, s), v)));
+
+            let original = map.clone();
+            let mut expected = map.clone();
+
+            {
+                let mut txn = map.transaction();
+
+                for (op, (g, s), value) in ops {
+                    let key = (g % 16, s);
+
+                    let agrees = match op % 3 {
+                        0 => txn.insert(key, value) == expected.insert(key, value),
+                        1 => txn.remove(&key) == expected.remove(&key),
+                        _ => txn.update(&key, |v| *v ^= value) ==
+                             expected.update(&key, |v| *v ^= value),
+                    };
+
+                    if !agrees { return false; }
+                }
+
+                if commit { txn.commit(); } else { txn.rollback(); }
+            }
+
+            let expected = if commit { expected } else { original };
+            map.validate().is_ok() && map.iter().eq(expected.iter())
+        }
+
+        quickcheck(coarse as fn(Vec<((u8, u8), u16)>, Vec<(u8, (u8, u8), u16)>, bool) -> bool);
+    }
+}
+
+mod persistent_diff {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeMap;
+    use tree::persistent::Map;
+    use tree::persistent::map::DiffItem;
+
+    #[test]
+    fn agrees_with_naive_diff() {
+        fn test(base: Vec<(u8, u8)>, ops: Vec<(bool, u8, u8)>) -> bool {
+            let old: Map<u8, u8> = base.into_iter().collect();
+            let mut new = old.clone();
+
+            for (insert, key, value) in ops {
+                if insert { new.insert(key, value); } else { new.remove(&key); }
+            }
+
+            let o: BTreeMap<_, _> = old.iter().collect();
+            let n: BTreeMap<_, _> = new.iter().collect();
+            let mut keys: Vec<_> = o.keys().chain(n.keys()).cloned().collect();
+            keys.sort();
+            keys.dedup();
+
+            let expected = keys.into_iter().filter_map(|key| match (o.get(key), n.get(key)) {
+                (Some(v), None) => Some(DiffItem::Removed(key, *v)),
+                (None, Some(v)) => Some(DiffItem::Inserted(key, *v)),
+                (Some(v), Some(w)) if v != w => Some(DiffItem::Modified(key, *v, *w)),
+                _ => None,
+            });
+
+            old.diff(&new).eq(expected)
+        }
+
+        quickcheck(test as fn(Vec<(u8, u8)>, Vec<(bool, u8, u8)>) -> bool);
+    }
+}
+
+mod persistent_set {
+    use quickcheck::quickcheck;
+    use std::collections::BTreeSet;
+    use tree::persistent::Set;
+
+    fn agree(set: &Set<u8>, expected: &BTreeSet<u8>) -> bool { set.iter().eq(expected.iter()) }
+
+    #[test]
+    fn set_ops_agree_with_btree_set() {
+        fn test(a: Vec<u8>, b: Vec<u8>, shared: Vec<u8>) -> bool {
+            let base: Set<u8> = shared.iter().cloned().collect();
+
+            let mut sa = base.clone();
+            sa.extend(a.iter().cloned());
+            let mut sb = base.clone();
+            sb.extend(b.iter().cloned());
+
+            let ea: BTreeSet<u8> = shared.iter().chain(a.iter()).cloned().collect();
+            let eb: BTreeSet<u8> = shared.iter().chain(b.iter()).cloned().collect();
+
+            agree(&sa.union(&sb), &ea.union(&eb).cloned().collect()) &&
+                agree(&sa.intersection(&sb), &ea.intersection(&eb).cloned().collect()) &&
+                agree(&sa.difference(&sb), &ea.difference(&eb).cloned().collect()) &&
+                agree(&sb.difference(&sa), &eb.difference(&ea).cloned().collect()) &&
+                agree(&sa.union(&sa), &ea) && sa.difference(&sa).is_empty()
+        }
+
+        quickcheck(test as fn(Vec<u8>, Vec<u8>, Vec<u8>) -> bool);
+    }
+}
+
+#[cfg(feature = "range")]
+mod range {
+    use compare::Compare;
+    use quickcheck::{Arbitrary, Gen, quickcheck};
+    use std::cmp::Ordering;
+    use std::collections::Bound::*;
+    use std::iter;
+    use tree::Map;
+
+    #[derive(Clone, Debug)]
+    struct Bound<T>(::std::collections::Bound<T>);
+
+    impl<T> Bound<T> {
+        fn as_ref(&self) -> Bound<&T> {
+            Bound(match self.0 {
+                Included(ref t) => Included(t),
+                Excluded(ref t) => Excluded(t),
+                Unbounded => Unbounded,
+            })
+        }
+    }
+
+    impl<T> Arbitrary for Bound<T> where T: Arbitrary {
+        fn arbitrary<G: Gen>(gen: &mut G) -> Self {
+            Bound(match gen.gen_range(0, 3) {
+                0 => Included(T::arbitrary(gen)),
+                1 => Excluded(T::arbitrary(gen)),
+                _ => Unbounded,
+            })
+        }
+
+        fn shrink(&self) -> Box<Iterator<Item=Self>> {
+            match self.0 {
+                Included(ref t) => Box::new(t.shrink().map(|t| Bound(Included(t)))),
+                Excluded(ref t) => Box::new(t.shrink().map(|t| Bound(Excluded(t)))),
+                Unbounded => Box::new(None.into_iter()),
+            }
+        }
+    }
+
+    #[test]
+    fn range() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
+            let r = map.range(min.as_ref().0, max.as_ref().0);
+
+            let i = map.iter()
+                .skip_while(|e| match min.0 {
+                    Included(ref t) => e.0 < t,
+                    Excluded(ref t) => e.0 <= t,
+                    Unbounded => false,
+                })
+            .take_while(|e| match max.0 {
+                Included(ref t) => e.0 <= t,
+                Excluded(ref t) => e.0 < t,
+                Unbounded => true,
+            });
+
+            let last = r.clone().last();
+            let r = r.collect::<Vec<_>>();
+            last == r.last().cloned() && r == i.collect::<Vec<_>>()
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+    }
+
+    // orders `(group, seq)` keys lexicographically, and compares a group with their first part,
+    // so that a group compares equal to several keys
+    #[derive(Clone)]
+    struct ByGroup;
+
+    impl Compare<(u8, u8)> for ByGroup {
+        fn compare(&self, l: &(u8, u8), r: &(u8, u8)) -> Ordering { l.cmp(r) }
+    }
+
+    impl Compare<u8, (u8, u8)> for ByGroup {
+        fn compare(&self, l: &u8, r: &(u8, u8)) -> Ordering { l.cmp(&r.0) }
+    }
+
+    #[test]
+    fn equal_range() {
+        fn test(entries: Vec<((u8, u8), u16)>, group: u8) -> bool {
+            let mut map = Map::with_cmp(ByGroup);
+            map.extend(entries.into_iter().map(|((g, s), v)| ((g % 8, s), v)));
+            let group = group % 8;
+
+            let expected = map.iter().filter(|e| (e.0).0 == group).collect::<Vec<_>>();
+            let r = map.equal_range(&group);
+
+            r.clone().collect::<Vec<_>>() == expected &&
+                r.rev().eq(expected.iter().rev().cloned())
+        }
+
+        quickcheck(test as fn(Vec<((u8, u8), u16)>, u8) -> bool);
+    }
+
+    #[test]
+    fn size_hint_is_exact() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>, back: Vec<bool>) -> bool {
+            let mut it = map.range(min.as_ref().0, max.as_ref().0);
+            let mut len = it.clone().fold(0, |len, _| len + 1);
+            if it.clone().count() != len { return false; }
+
+            for back in back.into_iter().chain(iter::repeat(false)) {
+                if it.size_hint() != (len, Some(len)) { return false; }
+                if if back { it.next_back() } else { it.next() }.is_none() { break; }
+                len -= 1;
+            }
+
+            true
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>, Vec<bool>) -> bool);
+    }
+
+    #[test]
+    fn peek_agrees_with_next() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>, ops: Vec<u8>) -> bool {
+            let mut it = map.range(min.as_ref().0, max.as_ref().0);
+
+            ops.into_iter().all(|op| match op % 4 {
+                0 => it.peek() == it.clone().next(),
+                1 => it.peek_back() == it.clone().next_back(),
+                2 => it.peek() == it.next(),
+                _ => it.peek_back() == it.next_back(),
+            }) && it.clone().fold(0, |len, _| len + 1) == it.len()
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn enumerate_ranked() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
+            let r = map.range(min.as_ref().0, max.as_ref().0);
+            let ranked = r.clone().enumerate_ranked().collect::<Vec<_>>();
+
+            ranked.iter().all(|&(rank, key, value)| {
+                map.select_range(rank..rank + 1).next() == Some((key, value))
+            }) && r.enumerate_ranked().rev().eq(ranked.into_iter().rev())
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+    }
+
+    #[test]
+    fn range_entries() {
+        fn test(mut map: Map<u8, u16>, min: Bound<u8>, max: Bound<u8>, remove: Vec<bool>) -> bool {
+            let in_range = map.range(min.as_ref().0, max.as_ref().0)
+                .map(|e| (*e.0, *e.1))
+                .collect::<Vec<_>>();
+
+            let mut expected = map.clone();
+            let mut remove = remove.into_iter().chain(iter::repeat(false));
+            let mut visited = vec![];
+
+            {
+                let mut entries = map.range_entries(min.as_ref().0, max.as_ref().0);
+
+                while let Some(mut entry) = entries.next_entry() {
+                    let (key, value) = (*entry.key(), *entry.get());
+                    visited.push((key, value));
+
+                    if remove.next().unwrap() {
+                        if entry.remove() != (key, value) { return false; }
+                        expected.remove(&key);
+                    } else {
+                        entry.insert(value.wrapping_add(1));
+                        expected.insert(key, value.wrapping_add(1));
+                    }
+                }
+            }
+
+            visited == in_range && map.validate().is_ok() && map == expected
+        }
+
+        quickcheck(test as fn(Map<u8, u16>, Bound<u8>, Bound<u8>, Vec<bool>) -> bool);
+    }
+
+    #[test]
+    fn range_entries_with_coarse_bounds() {
+        fn test(entries: Vec<((u8, u8), u16)>, min: Bound<u8>, max: Bound<u8>, remove: Vec<bool>)
+            -> bool {
+
+            let group = |bound: Bound<u8>| Bound(match bound.0 {
+                Included(g) => Included(g % 8),
+                Excluded(g) => Excluded(g % 8),
+                Unbounded => Unbounded,
+            });
+
+            let (min, max) = (group(min), group(max));
+            let mut map = Map::with_cmp(ByGroup);
+            map.extend(entries.into_iter().map(|((g, s), v)| ((g % 8, s), v)));
+
+            let in_range = map.range(min.as_ref().0, max.as_ref().0)
+                .map(|e| (*e.0, *e.1))
+                .collect::<Vec<_>>();
+
+            let mut expected = map.clone();
+            let mut remove = remove.into_iter().chain(iter::repeat(false));
+            let mut visited = vec![];
+
+            {
+                let mut entries = map.range_entries(min.as_ref().0, max.as_ref().0);
+
+                while let Some(mut entry) = entries.next_entry() {
+                    let (key, value) = (*entry.key(), *entry.get());
+                    visited.push((key, value));
+
+                    if remove.next().unwrap() {
+                        if entry.remove() != (key, value) { return false; }
+                        expected.remove(&key);
+                    } else {
+                        entry.insert(value.wrapping_add(1));
+                        expected.insert(key, value.wrapping_add(1));
+                    }
+                }
+            }
+
+            visited == in_range && map.validate().is_ok() && map == expected
+        }
+
+        quickcheck(test as fn(Vec<((u8, u8), u16)>, Bound<u8>, Bound<u8>, Vec<bool>) -> bool);
+    }
+
+    #[test]
+    fn checked_range() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
+            let valid = match (&min.0, &max.0) {
+                (&Excluded(ref min), &Excluded(ref max)) => min < max,
+                (&Included(ref min), &Included(ref max)) |
+                (&Included(ref min), &Excluded(ref max)) |
+                (&Excluded(ref min), &Included(ref max)) => min <= max,
+                _ => true,
+            };
+
+            match map.checked_range(min.as_ref().0, max.as_ref().0) {
+                None => !valid,
+                Some(r) => valid && r.eq(map.range(min.as_ref().0, max.as_ref().0)),
+            }
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+    }
+
+    #[test]
+    fn range_rev() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
+            let r = map.range(min.as_ref().0, max.as_ref().0).rev();
+
+            let i = map.iter().rev()
+                .skip_while(|e| match max.0 {
+                    Included(ref t) => e.0 > t,
+                    Excluded(ref t) => e.0 >= t,
+                    Unbounded => false,
+                })
+            .take_while(|e| match min.0 {
+                Included(ref t) => e.0 >= t,
+                Excluded(ref t) => e.0 > t,
+                Unbounded => true,
+            });
+
+            r.collect::<Vec<_>>() == i.collect::<Vec<_>>()
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+    }
+
+    #[test]
+    fn fold_range() {
+        fn test(map: Map<u32, u16>, min: Bound<u32>, max: Bound<u32>) -> bool {
+            let f = |mut acc: Vec<(u32, u16)>, key: &u32, value: &u16| {
+                acc.push((*key, *value));
+                acc
+            };
+
+            let r = map.range(min.as_ref().0, max.as_ref().0)
+                .fold(vec![], |acc, e| f(acc, e.0, e.1));
+            map.fold_range(min.as_ref().0, max.as_ref().0, vec![], f) == r
+        }
+
+        quickcheck(test as fn(Map<u32, u16>, Bound<u32>, Bound<u32>) -> bool);
+    }
+
+    #[test]
+    fn range_prefix() {
+        // draws characters from a small alphabet around the edges of the `char` range, so that
+        // prefixes often match and their ends must skip surrogates or carry
+        fn string(s: Vec<u8>) -> String {
+            s.into_iter().map(|c| ['a', 'b', '\u{d7ff}', '\u{e000}', '\u{10ffff}'][c as usize % 5])
+                .collect()
+        }
+
+        fn test(entries: Vec<(Vec<u8>, u16)>, prefix: Vec<u8>) -> bool {
+            let map: Map<_, _> = entries.into_iter().map(|(k, v)| (string(k), v)).collect();
+            let prefix = string(prefix);
+
+            map.range_prefix(&prefix).collect::<Vec<_>>() ==
+                map.iter().filter(|e| e.0.starts_with(&prefix)).collect::<Vec<_>>()
+        }
+
+        quickcheck(test as fn(Vec<(Vec<u8>, u16)>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn range_prefix_bytes() {
+        fn bytes(b: Vec<u8>) -> Vec<u8> {
+            b.into_iter().map(|b| [0, 1, 0xfe, 0xff][b as usize % 4]).collect()
+        }
+
+        fn test(entries: Vec<(Vec<u8>, u16)>, prefix: Vec<u8>) -> bool {
+            let map: Map<_, _> = entries.into_iter().map(|(k, v)| (bytes(k), v)).collect();
+            let prefix = bytes(prefix);
+
+            map.range_prefix_bytes(&prefix).collect::<Vec<_>>() ==
+                map.iter().filter(|e| e.0.starts_with(&prefix)).collect::<Vec<_>>()
+        }
+
+        quickcheck(test as fn(Vec<(Vec<u8>, u16)>, Vec<u8>) -> bool);
     }
 }
@@ -0,0 +1,103 @@
+extern crate tree;
+
+use tree::{indexed, map, multimap, multiset, set};
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+macro_rules! assert_send_sync {
+    ($($t:ty),*) => { $(assert_send::<$t>(); assert_sync::<$t>();)* }
+}
+
+type K = String;
+type V = Vec<u8>;
+
+#[test]
+fn map_types_are_send_and_sync() {
+    assert_send_sync!(
+        map::Map<K, V>,
+        map::Builder<K, V>,
+        map::IntoIter<K, V>,
+        map::Iter<'static, K, V>,
+        map::Chunks<'static, K, V>,
+        map::IterMut<'static, K, V>,
+        map::Select<'static, K, V>,
+        map::Ranked<map::Iter<'static, K, V>>,
+        map::Union<'static, K, V, V>,
+        map::Intersection<'static, K, V, V>,
+        map::Difference<'static, K, V, V>,
+        map::Entry<'static, K, V>,
+        map::OccupiedEntry<'static, K, V>,
+        map::VacantEntry<'static, K, V>,
+        map::Descending<'static, K, V>
+    );
+}
+
+#[cfg(feature = "range")]
+#[test]
+fn map_range_types_are_send_and_sync() {
+    assert_send_sync!(
+        map::IntoRange<K, V>,
+        map::Range<'static, K, V>,
+        map::RangeMut<'static, K, V>,
+        map::RangeEntries<'static, K, V>,
+        map::RangeEntry<'static, K, V>
+    );
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn map_stats_types_are_send_and_sync() {
+    assert_send_sync!(map::Nodes<'static, K, V>);
+}
+
+#[test]
+fn indexed_types_are_send_and_sync() {
+    assert_send_sync!(indexed::BiOrderedMap<K, V>, indexed::ByValueIter<'static, K, V>);
+}
+
+#[test]
+fn multimap_types_are_send_and_sync() {
+    assert_send_sync!(
+        multimap::MultiMap<K, V>,
+        multimap::Iter<'static, K, V>,
+        multimap::Groups<'static, K, V>
+    );
+}
+
+#[cfg(feature = "range")]
+#[test]
+fn multimap_range_types_are_send_and_sync() {
+    assert_send_sync!(multimap::Range<'static, K, V>);
+}
+
+#[test]
+fn multiset_types_are_send_and_sync() {
+    assert_send_sync!(
+        multiset::Multiset<K>,
+        multiset::Iter<'static, K>,
+        multiset::Counts<'static, K>,
+        multiset::Union<'static, K>,
+        multiset::Intersection<'static, K>,
+        multiset::Sum<'static, K>
+    );
+}
+
+#[test]
+fn set_types_are_send_and_sync() {
+    assert_send_sync!(
+        set::Set<K>,
+        set::Builder<K>,
+        set::IntoIter<K>,
+        set::Iter<'static, K>,
+        set::Entry<'static, K>,
+        set::OccupiedEntry<'static, K>,
+        set::VacantEntry<'static, K>
+    );
+}
+
+#[cfg(feature = "range")]
+#[test]
+fn set_range_types_are_send_and_sync() {
+    assert_send_sync!(set::IntoRange<K>, set::Range<'static, K>);
+}